@@ -0,0 +1,24 @@
+-- A balanced tree of adders summing `inputs` values of type `kind`
+function build(params, block)
+  local n = tonumber(params.inputs)
+  local kind = params.kind or "integer"
+  local layer = {}
+  for i = 1, n do
+    layer[i] = block:input("in" .. (i - 1), kind)
+  end
+  while #layer > 1 do
+    local next_layer = {}
+    for i = 1, #layer - 1, 2 do
+      local add = block:module("Add", {{"a", kind}, {"b", kind}}, {{"y", kind}})
+      block:wire(layer[i], add.a)
+      block:wire(layer[i + 1], add.b)
+      next_layer[#next_layer + 1] = add.y
+    end
+    -- An odd one out goes straight through to the next layer
+    if #layer % 2 == 1 then
+      next_layer[#next_layer + 1] = layer[#layer]
+    end
+    layer = next_layer
+  end
+  block:wire(layer[1], block:output("sum", kind))
+end
@@ -0,0 +1,34 @@
+// Generated by CasperFlow from project `blinky`
+#![allow(non_camel_case_types, non_snake_case)]
+
+use rust_hdl::prelude::*;
+
+#[derive(LogicBlock, Default)]
+pub struct invert {
+    pub a: Signal<In, Bits<1>>,
+    pub y: Signal<Out, Bits<1>>,
+    pub Inverter_0: Inverter,
+}
+
+impl Logic for invert {
+    #[hdl_gen]
+    fn update(&mut self) {
+        self.Inverter_0.I.next = self.a.val();
+        self.y.next = self.Inverter_0.O.val();
+    }
+}
+
+#[derive(LogicBlock, Default)]
+pub struct top {
+    pub x: Signal<In, Bits<1>>,
+    pub led: Signal<Out, Bits<1>>,
+    pub invert_0: invert,
+}
+
+impl Logic for top {
+    #[hdl_gen]
+    fn update(&mut self) {
+        self.invert_0.a.next = self.x.val();
+        self.led.next = self.invert_0.y.val();
+    }
+}
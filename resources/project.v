@@ -0,0 +1,33 @@
+// Generated by CasperFlow from project `blinky`
+
+module invert (
+    input wire a,
+    output wire y
+);
+
+    wire Inverter_0_O;
+
+    Inverter Inverter_0 (
+        .I(a),
+        .O(Inverter_0_O)
+    );
+
+    assign y = Inverter_0_O;
+
+endmodule
+
+module top (
+    input wire x,
+    output wire led
+);
+
+    wire invert_0_y;
+
+    invert invert_0 (
+        .a(x),
+        .y(invert_0_y)
+    );
+
+    assign led = invert_0_y;
+
+endmodule
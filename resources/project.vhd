@@ -0,0 +1,57 @@
+-- Generated by CasperFlow from project `blinky`
+
+library ieee;
+use ieee.std_logic_1164.all;
+use ieee.numeric_std.all;
+
+entity invert is
+    port (
+        a : in std_logic;
+        y : out std_logic
+    );
+end entity invert;
+
+architecture structural of invert is
+    component Inverter is
+        port (
+            I : in std_logic;
+            O : out std_logic
+        );
+    end component;
+    signal Inverter_0_O : std_logic;
+begin
+
+    Inverter_0 : Inverter
+        port map (
+            I => a,
+            O => Inverter_0_O
+        );
+
+    y <= Inverter_0_O;
+
+end architecture structural;
+
+library ieee;
+use ieee.std_logic_1164.all;
+use ieee.numeric_std.all;
+
+entity top is
+    port (
+        x : in std_logic;
+        led : out std_logic
+    );
+end entity top;
+
+architecture structural of top is
+    signal invert_0_y : std_logic;
+begin
+
+    invert_0 : entity work.invert
+        port map (
+            a => x,
+            y => invert_0_y
+        );
+
+    led <= invert_0_y;
+
+end architecture structural;
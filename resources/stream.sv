@@ -0,0 +1,45 @@
+// Generated by CasperFlow from project `streaming`
+
+interface axis;
+    logic signed [31:0] tdata;
+    logic tvalid;
+    logic tready;
+
+    modport source (output tdata, output tvalid, input tready);
+    modport sink (input tdata, input tvalid, output tready);
+endinterface
+
+module top (
+    input logic enable,
+    input logic downstream_ready,
+    output logic overflow,
+    output logic ready
+);
+
+    axis stream_source_0_m_axis ();
+    axis fifo_0_m_axis ();
+    axis stream_sink_0_s_axis ();
+    logic stream_sink_0_Overflow;
+
+    stream_source #(.width(32)) stream_source_0 (
+        .Enable(enable),
+        .m_axis(stream_source_0_m_axis)
+    );
+
+    fifo fifo_0 (
+        .s_axis(stream_source_0_m_axis),
+        .m_axis(fifo_0_m_axis)
+    );
+
+    stream_sink stream_sink_0 (
+        .Overflow(stream_sink_0_Overflow),
+        .s_axis(stream_sink_0_s_axis)
+    );
+
+    assign fifo_0_m_axis.tready = downstream_ready;
+    assign stream_sink_0_s_axis.tdata = fifo_0_m_axis.tdata;
+    assign stream_sink_0_s_axis.tvalid = fifo_0_m_axis.tvalid;
+    assign overflow = stream_sink_0_Overflow;
+    assign ready = stream_sink_0_s_axis.tready;
+
+endmodule
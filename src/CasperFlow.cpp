@@ -12,7 +12,8 @@ int main() {
   ApplicationLog log;
   WindowState ws;
 
-  // Graph
+  // The open design and its graph
+  rust::Box<rs::Document> doc = rs::new_document();
   rs::CGraph graph;
 
   // Run the gui!
@@ -22,8 +23,8 @@ int main() {
     gui_newframe();
 
     // Display the main menu
-    draw_main_menu(&ws.show_editor, &ws.show_log, &ws.show_browser,
-                   &ws.show_demo, &ws.quit);
+    draw_main_menu(*doc, &ws.show_editor, &ws.show_log, &ws.show_browser,
+                   &ws.show_console, &ws.show_demo, &ws.quit, log);
 
     // Create central dockspace
     auto ds_id = ImGui::DockSpaceOverViewport(ImGui::GetMainViewport());
@@ -43,12 +44,39 @@ int main() {
     }
 
     if (ImNodes::IsLinkCreated(&ws.start_attr, &ws.stop_attr)) {
-      // Attempt to make the connection. Do something? with the error
-      try {
-        rs::add_wire(ws.start_attr, ws.stop_attr);
-        ws.stale_graph = true;
-      } catch (std::exception error) {
-        log.add_log("%s\n", error.what());
+      // Attempt to make the connection, logging why if we can't
+      auto err = doc->add_wire(ws.start_attr, ws.stop_attr);
+      if (err.kind != rs::ErrorKind::Ok) {
+        log.add_log("%s\n", err.message.c_str());
+      }
+    }
+
+    // Copy and paste the selected modules through the system clipboard, unless a text field wants the keys
+    bool shortcuts = ImGui::GetIO().KeyCtrl && !ImGui::GetIO().WantTextInput;
+    if (shortcuts && ImGui::IsKeyPressed(ImGuiKey_C)) {
+      int num_nodes = ImNodes::NumSelectedNodes();
+      if (num_nodes > 0) {
+        std::vector<int> node_ids(num_nodes);
+        ImNodes::GetSelectedNodes(node_ids.data());
+        rust::Vec<int32_t> ids;
+        for (auto id : node_ids) {
+          ids.push_back(id);
+        }
+        rust::String json;
+        auto err = doc->copy_modules(std::move(ids), json);
+        if (err.kind == rs::ErrorKind::Ok) {
+          ImGui::SetClipboardText(std::string(json).c_str());
+        } else {
+          log.add_log("%s\n", err.message.c_str());
+        }
+      }
+    }
+    if (shortcuts && ImGui::IsKeyPressed(ImGuiKey_V)) {
+      const char *text = ImGui::GetClipboardText();
+      rust::Vec<int32_t> pasted;
+      auto err = doc->paste_modules(text ? text : "", pasted);
+      if (err.kind != rs::ErrorKind::Ok) {
+        log.add_log("%s\n", err.message.c_str());
       }
     }
 
@@ -65,45 +93,42 @@ int main() {
 
       if (bulk_delete){
         if (ImGui::MenuItem("Delete all")) {
-        // Oh god, heap
-        int* node_ids = (int*)malloc(sizeof(int)*num_nodes);
-        int* link_ids = (int*)malloc(sizeof(int)*num_links);
-        ImNodes::GetSelectedNodes(node_ids);
-        ImNodes::GetSelectedLinks(link_ids);
-
-        for (int i = 0; i < num_links; i++) {
-          if (rs::remove_wire(link_ids[i]) < 0) {
-            log.add_log("We tried to delete a link that didn't exist, this "
-                        "shouldn't happen.\n");
-          }
+        std::vector<int> node_ids(num_nodes);
+        std::vector<int> link_ids(num_links);
+        ImNodes::GetSelectedNodes(node_ids.data());
+        ImNodes::GetSelectedLinks(link_ids.data());
+
+        // Remove the lot in one go so a bad id doesn't leave half a selection
+        rust::Vec<int32_t> mod_ids;
+        rust::Vec<int32_t> wire_ids;
+        for (auto id : node_ids) {
+          mod_ids.push_back(id);
         }
-
-        for (int i = 0; i < num_nodes; i++) {
-          rs::remove_module(node_ids[i]);
+        for (auto id : link_ids) {
+          wire_ids.push_back(id);
+        }
+        auto err = doc->remove_many(std::move(mod_ids), std::move(wire_ids));
+        if (err.kind != rs::ErrorKind::Ok) {
+          log.add_log("%s\n", err.message.c_str());
         }
-
-        // Don't forget you're programming c, you big dummy
-        free(node_ids);
-        free(link_ids);
-
-        ws.stale_graph = true;
         }
       }
 
       else if (delete_single_link) {
         if (ImGui::MenuItem("Delete wire")) {
-        if (rs::remove_wire(ws.link) < 0) {
-          log.add_log("We tried to delete a link that didn't exist, this "
-                      "shouldn't happen.\n");
+        auto err = doc->remove_wire(ws.link);
+        if (err.kind != rs::ErrorKind::Ok) {
+          log.add_log("%s\n", err.message.c_str());
         }
-        ws.stale_graph = true;
         }
       }
 
       else if (delete_single_node) {
         if (ImGui::MenuItem("Delete node")) {
-        rs::remove_module(ws.node);
-        ws.stale_graph = true;
+        auto err = doc->remove_module(ws.node);
+        if (err.kind != rs::ErrorKind::Ok) {
+          log.add_log("%s\n", err.message.c_str());
+        }
       }
       }
 
@@ -113,19 +138,23 @@ int main() {
     // File selector
     file_selector(&ws.stale_graph);
 
-    // If graph is stale, get a new one
+    // If graph is stale, get a new one, otherwise just apply the changes
     if (ws.stale_graph) {
       ws.stale_graph = false;
-      graph = rs::get_graph();
+      graph = doc->get_graph();
+    } else {
+      apply_events(*doc, graph);
     }
 
     // Run the layout
     if (ws.show_editor)
-      draw_editor(&ws.show_editor, graph);
+      draw_editor(*doc, &ws.show_editor, graph);
     if (ws.show_browser)
       draw_library(&ws.show_browser);
     if (ws.show_log)
       log.draw("Log", &ws.show_log);
+    if (ws.show_console)
+      draw_console(*doc, &ws.show_console, log);
     if (ws.show_demo)
       ImGui::ShowDemoWindow(&ws.show_demo);
 
@@ -11,6 +11,7 @@
 #include "ui_components.hpp"
 // stdlib
 #include <iostream>
+#include <vector>
 
 // Store the state things that we need to know about every frame that rust
 // doesn't need to know about
@@ -19,6 +20,7 @@ struct WindowState {
   bool show_editor;
   bool show_log;
   bool show_browser;
+  bool show_console;
   bool show_demo;
   bool stale_graph;
   // Flag to quit with
@@ -35,6 +37,7 @@ struct WindowState {
     show_log = true;
     quit = false;
     show_browser = true;
+    show_console = false;
     show_demo = false;
     start_attr = 0;
     stop_attr = 0;
@@ -0,0 +1,43 @@
+//! A git merge driver for netlist files
+//!
+//! Register it with
+//! ```text
+//! git config merge.cfrs.driver "cfrs-merge %O %A %B"
+//! echo "my_design.json merge=cfrs" >> .gitattributes
+//! ```
+//! The merged netlist is written over the "ours" file. Conflicts keep our side, are listed on stderr and make the
+//! driver exit with a non-zero status so git flags the file as conflicted. A merged netlist that doesn't load counts
+//! as a conflict too.
+
+use cfrs::merge::merge;
+use cfrs::save::SavedNetlist;
+use std::{fs, process::ExitCode};
+
+fn load(path: &str) -> anyhow::Result<SavedNetlist> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+fn run(base: &str, ours: &str, theirs: &str) -> anyhow::Result<bool> {
+    let result = merge(&load(base)?, &load(ours)?, &load(theirs)?);
+    fs::write(ours, serde_json::to_string_pretty(&result.merged)?)?;
+    for conflict in &result.conflicts {
+        eprintln!("CONFLICT {}: {}", ours, conflict);
+    }
+    Ok(result.is_clean())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<_> = std::env::args().collect();
+    let [_, base, ours, theirs] = args.as_slice() else {
+        eprintln!("Usage: cfrs-merge <base> <ours> <theirs>");
+        return ExitCode::from(2);
+    };
+    match run(base, ours, theirs) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("cfrs-merge: {:#}", e);
+            ExitCode::from(2)
+        }
+    }
+}
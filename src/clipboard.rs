@@ -0,0 +1,153 @@
+//! This module handles copying parts of a netlist and pasting them back in
+//! A clipboard fragment is just a saved netlist holding the copied modules and the wires between them
+
+use crate::ffi::InterconnectDirection;
+use crate::netlist::{ModuleIndex, Netlist};
+use crate::save::{SavedNetlist, SavedPinRef};
+use anyhow::anyhow;
+use std::collections::{HashMap, HashSet};
+
+/// How far pasted modules are moved from where they were copied, so they don't land on top of the originals
+pub const PASTE_OFFSET: (f32, f32) = (40.0, 40.0);
+
+impl Netlist {
+    /// Extract the modules `modules` and the wires between them into a fragment.
+    /// Wires to modules outside the selection are left behind. Returns `None` if any module doesn't exist.
+    pub fn fragment(&self, modules: &[ModuleIndex]) -> Option<SavedNetlist> {
+        let ids = modules
+            .iter()
+            .map(|x| self.get_module(*x).map(|m| m.id()))
+            .collect::<Option<HashSet<_>>>()?;
+        let mut saved = self.to_saved();
+        saved.modules.retain(|m| ids.contains(&m.id));
+        saved
+            .wires
+            .retain(|w| ids.contains(&w.from.module) && ids.contains(&w.to.module));
+        Some(saved)
+    }
+
+    /// Copy the modules `modules` and their internal wiring to a json clipboard fragment
+    pub fn copy(&self, modules: &[ModuleIndex]) -> Option<String> {
+        Some(serde_json::to_string(&self.fragment(modules)?).unwrap())
+    }
+
+    /// Add a copy of every module and wire in `fragment`, giving the modules fresh ids.
+    /// Instance names aren't copied, as they would collide with the originals, and positions are moved by
+    /// [`PASTE_OFFSET`].
+    /// Returns the new modules in the order they appear in the fragment. Nothing is added if the fragment is invalid.
+    pub fn paste_fragment(&mut self, fragment: &SavedNetlist) -> anyhow::Result<Vec<ModuleIndex>> {
+        // Make sure the fragment stands on its own before touching anything
+        Netlist::from_saved(fragment)?;
+        let mut added = vec![];
+        let mut new_ids = HashMap::new();
+        for m in &fragment.modules {
+            let mi = self.add_module(m.name.clone());
+            for (name, value) in &m.parameters {
+                self.set_parameter(mi, name.clone(), value.clone());
+            }
+            self.set_bundles(mi, m.bundles.clone());
+            let position = m
+                .position
+                .map(|(x, y)| (x + PASTE_OFFSET.0, y + PASTE_OFFSET.1));
+            self.set_position(mi, position);
+            self.set_resource_model(mi, m.resources.clone());
+            self.set_latency(mi, m.latency.clone());
+            self.set_ranges(mi, m.ranges.clone());
+            for p in &m.inputs {
+                self.add_pin(mi, p.name.clone(), p.kind, InterconnectDirection::Input);
+            }
+            for p in &m.outputs {
+                self.add_pin(mi, p.name.clone(), p.kind, InterconnectDirection::Output);
+            }
+            new_ids.insert(m.id, self.get_module(mi).expect("We just added it").id());
+            added.push(mi);
+        }
+        let renumber = |pin: &SavedPinRef| SavedPinRef {
+            module: new_ids[&pin.module],
+            pin: pin.pin.clone(),
+        };
+        for w in &fragment.wires {
+            let from = self
+                .resolve_pin(&renumber(&w.from), InterconnectDirection::Output)
+                .expect("Checked when loading the fragment");
+            let to = self
+                .resolve_pin(&renumber(&w.to), InterconnectDirection::Input)
+                .expect("Checked when loading the fragment");
+            self.add_wire(from, to)
+                .expect("Checked when loading the fragment");
+        }
+        Ok(added)
+    }
+
+    /// Paste a json clipboard fragment written by [`Netlist::copy`]
+    pub fn paste(&mut self, json: &str) -> anyhow::Result<Vec<ModuleIndex>> {
+        let fragment: SavedNetlist =
+            serde_json::from_str(json).map_err(|e| anyhow!("Not a clipboard fragment: {}", e))?;
+        self.paste_fragment(&fragment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_paste() {
+        let mut netlist = Netlist::from_json(include_str!("../resources/netlist.json")).unwrap();
+        let (source, sink) = (
+            netlist.find_module(0).unwrap(),
+            netlist.find_module(1).unwrap(),
+        );
+
+        // Copying both modules keeps the wires between them
+        let json = netlist.copy(&[source, sink]).unwrap();
+        let pasted = netlist.paste(&json).unwrap();
+        assert_eq!(pasted.len(), 2);
+        let ids: Vec<_> = pasted
+            .iter()
+            .map(|x| netlist.get_module(*x).unwrap().id())
+            .collect();
+        assert_eq!(ids, vec![2, 3]);
+        assert_eq!(netlist.wires().len(), 4);
+        assert_eq!(
+            netlist.get_module(pasted[0]).unwrap().parameter("width"),
+            Some("8")
+        );
+        assert_eq!(netlist.get_module(pasted[0]).unwrap().position(), None);
+        let input = netlist
+            .find_pin(pasted[1], "A", InterconnectDirection::Input)
+            .unwrap();
+        assert_eq!(
+            netlist.pin_ref(netlist.driver(input).unwrap()).unwrap(),
+            SavedPinRef {
+                module: 2,
+                pin: "O".to_owned()
+            }
+        );
+
+        // Placed modules are pasted beside the originals
+        netlist.set_position(sink, Some((100.0, 50.0)));
+        let pasted = netlist.paste(&netlist.copy(&[sink]).unwrap()).unwrap();
+        assert_eq!(
+            netlist.get_module(pasted[0]).unwrap().position(),
+            Some((140.0, 90.0))
+        );
+
+        // Copying just the sink leaves its inputs undriven, and pastes into another netlist
+        let mut other = Netlist::new();
+        let pasted = other.paste(&netlist.copy(&[sink]).unwrap()).unwrap();
+        assert_eq!(other.modules().len(), 1);
+        assert_eq!(other.wires().len(), 0);
+        assert_eq!(other.get_module(pasted[0]).unwrap().name(), "Sink");
+    }
+
+    #[test]
+    fn test_bad_fragment() {
+        let mut netlist = Netlist::new();
+        assert!(netlist.paste("[]").is_err());
+        // A wire to a module that isn't part of the fragment
+        let json = r#"{"modules":[],"wires":[{"from":{"module":0,"pin":"O"},"to":{"module":1,"pin":"I"}}]}"#;
+        assert!(netlist.paste(json).is_err());
+        assert_eq!(netlist.modules().len(), 0);
+    }
+}
@@ -0,0 +1,272 @@
+//! This module runs Lua scripts that edit a netlist in bulk, from the GUI console or saved with a project
+//! Scripts see the netlist as the global `netlist`, whose methods mirror those of [`Netlist`], taking and returning
+//! handles to modules, pins and wires. Directions are written `"input"` or `"output"`, and pin kinds by name.
+//! Anything passed to `print` is collected rather than written to stdout.
+
+use crate::ffi::{InterconnectDirection, PinKind};
+use crate::netlist::{Module, ModuleIndex, NameError, Netlist, Pin, PinIndex, WireIndex};
+use crate::project::Project;
+use crate::script::{runtime_error, ModuleHandle, PinHandle, WireHandle};
+use anyhow::anyhow;
+use mlua::{Function, Lua, UserData, UserDataMethods, Value, Variadic};
+use std::cell::RefCell;
+
+fn get_module(netlist: &Netlist, m: ModuleHandle) -> mlua::Result<&Module> {
+    netlist
+        .get_module(m.0)
+        .ok_or_else(|| runtime_error("The module no longer exists"))
+}
+
+fn get_pin(netlist: &Netlist, p: PinHandle) -> mlua::Result<&Pin> {
+    netlist
+        .get_pin(p.0)
+        .ok_or_else(|| runtime_error("The pin no longer exists"))
+}
+
+fn parse_direction(direction: &str) -> mlua::Result<InterconnectDirection> {
+    match direction {
+        "input" => Ok(InterconnectDirection::Input),
+        "output" => Ok(InterconnectDirection::Output),
+        _ => Err(runtime_error(format!("Unknown direction `{}`", direction))),
+    }
+}
+
+fn pins(m: std::slice::Iter<'_, PinIndex>) -> Vec<PinHandle> {
+    m.map(|x| PinHandle(*x)).collect()
+}
+
+impl UserData for Netlist {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        // Edits
+        methods.add_method_mut("add_module", |_, this, name: String| {
+            Ok(ModuleHandle(this.add_module(name)))
+        });
+        methods.add_method_mut("remove_module", |_, this, m: ModuleHandle| {
+            get_module(this, m)?;
+            this.remove_module(m.0);
+            Ok(())
+        });
+        methods.add_method_mut(
+            "add_pin",
+            |_, this, (m, name, kind, direction): (ModuleHandle, String, String, String)| {
+                let kind: PinKind = kind.parse().map_err(runtime_error)?;
+                let direction = parse_direction(&direction)?;
+                get_module(this, m)?;
+                let pi = this
+                    .add_pin(m.0, name.clone(), kind, direction)
+                    .ok_or_else(|| runtime_error(NameError::Taken(name)))?;
+                Ok(PinHandle(pi))
+            },
+        );
+        methods.add_method_mut("remove_pin", |_, this, p: PinHandle| {
+            get_pin(this, p)?;
+            this.remove_pin(p.0);
+            Ok(())
+        });
+        methods.add_method_mut("add_wire", |_, this, (from, to): (PinHandle, PinHandle)| {
+            let wi = this.add_wire(from.0, to.0).map_err(runtime_error)?;
+            Ok(WireHandle(wi))
+        });
+        methods.add_method_mut("remove_wire", |_, this, w: WireHandle| {
+            this.remove_wire(w.0)
+                .ok_or_else(|| runtime_error("The wire no longer exists"))
+        });
+        methods.add_method_mut(
+            "set_parameter",
+            |_, this, (m, name, value): (ModuleHandle, String, String)| {
+                get_module(this, m)?;
+                Ok(this
+                    .set_parameter(m.0, name, value)
+                    .expect("The module exists"))
+            },
+        );
+        methods.add_method_mut(
+            "rename_module",
+            |_, this, (m, name): (ModuleHandle, String)| {
+                this.rename_module(m.0, name).map_err(runtime_error)
+            },
+        );
+        methods.add_method_mut(
+            "set_instance_name",
+            |_, this, (m, name): (ModuleHandle, String)| {
+                this.set_instance_name(m.0, name).map_err(runtime_error)
+            },
+        );
+        methods.add_method_mut("rename_pin", |_, this, (p, name): (PinHandle, String)| {
+            this.rename_pin(p.0, name).map_err(runtime_error)
+        });
+
+        // Queries
+        methods.add_method("modules", |_, this, ()| {
+            let mut modules: Vec<_> = this.modules().map(|(i, m)| (m.id(), i)).collect();
+            modules.sort_by_key(|(id, _)| *id);
+            Ok(modules
+                .into_iter()
+                .map(|(_, i)| ModuleHandle(ModuleIndex(i)))
+                .collect::<Vec<_>>())
+        });
+        methods.add_method("find_module", |_, this, id: i32| {
+            Ok(this.find_module(id).map(ModuleHandle))
+        });
+        methods.add_method("id", |_, this, m: ModuleHandle| {
+            Ok(get_module(this, m)?.id())
+        });
+        methods.add_method("name", |_, this, m: ModuleHandle| {
+            Ok(get_module(this, m)?.name().to_owned())
+        });
+        methods.add_method("instance", |_, this, m: ModuleHandle| {
+            Ok(get_module(this, m)?.instance().to_owned())
+        });
+        methods.add_method("parameter", |_, this, (m, name): (ModuleHandle, String)| {
+            Ok(get_module(this, m)?.parameter(&name).map(str::to_owned))
+        });
+        methods.add_method("inputs", |_, this, m: ModuleHandle| {
+            Ok(pins(get_module(this, m)?.inputs()))
+        });
+        methods.add_method("outputs", |_, this, m: ModuleHandle| {
+            Ok(pins(get_module(this, m)?.outputs()))
+        });
+        methods.add_method(
+            "find_pin",
+            |_, this, (m, name, direction): (ModuleHandle, String, String)| {
+                let direction = parse_direction(&direction)?;
+                get_module(this, m)?;
+                Ok(this.find_pin(m.0, &name, direction).map(PinHandle))
+            },
+        );
+        methods.add_method("pin_name", |_, this, p: PinHandle| {
+            Ok(get_pin(this, p)?.name().to_owned())
+        });
+        methods.add_method("pin_kind", |_, this, p: PinHandle| {
+            Ok(get_pin(this, p)?.kind().to_string())
+        });
+        methods.add_method("parent", |_, this, p: PinHandle| {
+            Ok(ModuleHandle(get_pin(this, p)?.parent()))
+        });
+        methods.add_method("driver", |_, this, p: PinHandle| {
+            get_pin(this, p)?;
+            Ok(this.driver(p.0).map(PinHandle))
+        });
+        methods.add_method("fanout", |_, this, p: PinHandle| {
+            get_pin(this, p)?;
+            Ok(this
+                .fanout(p.0)
+                .unwrap_or_default()
+                .iter()
+                .map(|x| PinHandle(*x))
+                .collect::<Vec<_>>())
+        });
+        methods.add_method("wire_into", |_, this, p: PinHandle| {
+            get_pin(this, p)?;
+            Ok(this
+                .wires()
+                .find(|(_, (input, _))| *input == p.0)
+                .map(|(i, _)| WireHandle(WireIndex(i))))
+        });
+    }
+}
+
+/// Run the script `source` against `netlist`, appending whatever it prints to `output`.
+/// Edits made before a failure are kept, as they would be when typed into a console.
+pub fn run(
+    netlist: &mut Netlist,
+    name: &str,
+    source: &str,
+    output: &mut String,
+) -> anyhow::Result<()> {
+    let lua = Lua::new();
+    let printed = RefCell::new(std::mem::take(output));
+    let result = lua.scope(|scope| {
+        let globals = lua.globals();
+        globals.set("netlist", scope.create_userdata_ref_mut(netlist)?)?;
+        let print = scope.create_function(|lua, values: Variadic<Value>| {
+            let tostring: Function = lua.globals().get("tostring")?;
+            let values = values
+                .into_iter()
+                .map(|x| tostring.call::<_, String>(x))
+                .collect::<mlua::Result<Vec<_>>>()?;
+            let mut printed = printed.borrow_mut();
+            printed.push_str(&values.join("\t"));
+            printed.push('\n');
+            Ok(())
+        })?;
+        globals.set("print", print)?;
+        lua.load(source).set_name(name).exec()
+    });
+    *output = printed.into_inner();
+    result.map_err(|e| anyhow!("Script `{}` failed: {}", name, e))
+}
+
+impl Project {
+    /// Run the script saved with the project as `name` against the subgraph `subgraph`, returning what it printed.
+    /// Nothing changes if the script fails.
+    pub fn run_script(&mut self, name: &str, subgraph: &str) -> anyhow::Result<String> {
+        let source = self
+            .script(name)
+            .ok_or_else(|| anyhow!("There's no script named `{}`", name))?
+            .to_owned();
+        self.edit_subgraph(subgraph, |netlist| {
+            let mut output = String::new();
+            run(netlist, name, &source, &mut output)?;
+            Ok(output)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::Subgraph;
+
+    #[test]
+    fn test_batch_edit() {
+        let mut netlist = Netlist::from_json(include_str!("../resources/netlist.json")).unwrap();
+        let source = r#"
+            -- Hang a bank of sinks off the source, one per channel
+            local source = netlist:find_module(0)
+            local out = netlist:find_pin(source, "O", "output")
+            for i = 0, 3 do
+                local sink = netlist:add_module("Sink")
+                netlist:set_instance_name(sink, "channel_" .. i)
+                netlist:add_wire(out, netlist:add_pin(sink, "A", netlist:pin_kind(out), "input"))
+            end
+            print(#netlist:modules(), #netlist:fanout(out))
+            assert(netlist:parent(out) == source)
+        "#;
+        let mut output = String::new();
+        run(&mut netlist, "channels", source, &mut output).unwrap();
+        assert_eq!(output, "6\t6\n");
+        netlist.assert_invariants();
+        assert!(netlist.modules().any(|(_, m)| m.instance() == "channel_3"));
+
+        // Errors come back, with the edits made up to that point
+        let source = r#"
+            netlist:add_module("Extra")
+            print("before")
+            netlist:add_pin(netlist:find_module(0), "X", "wire", "sideways")
+        "#;
+        let mut output = String::new();
+        assert!(run(&mut netlist, "bad", source, &mut output).is_err());
+        assert_eq!(output, "before\n");
+        assert_eq!(netlist.modules().len(), 7);
+    }
+
+    #[test]
+    fn test_project_script() {
+        let top = Subgraph::new("top".to_owned(), vec![], vec![], Netlist::new()).unwrap();
+        let mut project = Project::new("p".to_owned(), top);
+        project.set_script("grow".to_owned(), r#"netlist:add_module("a")"#.to_owned());
+        project.set_script(
+            "fail".to_owned(),
+            r#"netlist:add_module("b") error("oops")"#.to_owned(),
+        );
+        assert!(project.run_script("grow", "top").is_ok());
+        assert!(project.run_script("fail", "top").is_err());
+        assert!(project.run_script("missing", "top").is_err());
+        // The failed script left nothing behind
+        assert_eq!(project.top().netlist().modules().len(), 1);
+        // Scripts are saved with the project
+        let loaded = Project::from_json(&project.to_json()).unwrap();
+        assert_eq!(loaded.script("grow"), Some(r#"netlist:add_module("a")"#));
+    }
+}
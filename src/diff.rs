@@ -0,0 +1,662 @@
+//! This module compares two netlists semantically
+//! Modules are matched by id and pins by name, so the result doesn't depend on arena indices
+
+use crate::ffi::{InterconnectDirection, PinKind};
+use crate::library::{Bundle, LibraryPin, ResourceModel};
+use crate::netlist::Netlist;
+use crate::save::{SavedModule, SavedNetlist, SavedPinRef};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    ModuleAdded {
+        module: i32,
+        name: String,
+    },
+    ModuleRemoved {
+        module: i32,
+        name: String,
+    },
+    ModuleRenamed {
+        module: i32,
+        from: String,
+        to: String,
+    },
+    InstanceRenamed {
+        module: i32,
+        from: Option<String>,
+        to: Option<String>,
+    },
+    ParameterChanged {
+        module: i32,
+        parameter: String,
+        from: Option<String>,
+        to: Option<String>,
+    },
+    BundleChanged {
+        module: i32,
+        bundle: String,
+        from: Option<Bundle>,
+        to: Option<Bundle>,
+    },
+    ResourcesChanged {
+        module: i32,
+        from: Option<ResourceModel>,
+        to: Option<ResourceModel>,
+    },
+    LatencyChanged {
+        module: i32,
+        from: Option<String>,
+        to: Option<String>,
+    },
+    /// The range of values on the output `pin` changed
+    RangeChanged {
+        module: i32,
+        pin: String,
+        from: Option<String>,
+        to: Option<String>,
+    },
+    PinAdded {
+        module: i32,
+        pin: String,
+        direction: InterconnectDirection,
+        kind: PinKind,
+    },
+    PinRemoved {
+        module: i32,
+        pin: String,
+        direction: InterconnectDirection,
+        kind: PinKind,
+    },
+    PinKindChanged {
+        module: i32,
+        pin: String,
+        direction: InterconnectDirection,
+        from: PinKind,
+        to: PinKind,
+    },
+    WireAdded {
+        from: SavedPinRef,
+        to: SavedPinRef,
+    },
+    WireRemoved {
+        from: SavedPinRef,
+        to: SavedPinRef,
+    },
+    /// The input `to` is driven by `new_from` where it used to be driven by `old_from`
+    WireRerouted {
+        to: SavedPinRef,
+        old_from: SavedPinRef,
+        new_from: SavedPinRef,
+    },
+}
+
+impl Change {
+    /// Get the ids of every module touched by this change, so they can be highlighted
+    pub fn modules(&self) -> Vec<i32> {
+        match self {
+            Change::ModuleAdded { module, .. }
+            | Change::ModuleRemoved { module, .. }
+            | Change::ModuleRenamed { module, .. }
+            | Change::InstanceRenamed { module, .. }
+            | Change::ParameterChanged { module, .. }
+            | Change::BundleChanged { module, .. }
+            | Change::ResourcesChanged { module, .. }
+            | Change::LatencyChanged { module, .. }
+            | Change::RangeChanged { module, .. }
+            | Change::PinAdded { module, .. }
+            | Change::PinRemoved { module, .. }
+            | Change::PinKindChanged { module, .. } => vec![*module],
+            Change::WireAdded { from, to } | Change::WireRemoved { from, to } => {
+                vec![from.module, to.module]
+            }
+            Change::WireRerouted {
+                to,
+                old_from,
+                new_from,
+            } => vec![to.module, old_from.module, new_from.module],
+        }
+    }
+}
+
+/// Describe a bundle by its interface and the pin playing each role, like `axis(tdata: D, tvalid: V)`
+fn describe_bundle(bundle: &Option<Bundle>) -> String {
+    match bundle {
+        Some(b) => {
+            let signals: Vec<_> = b
+                .signals
+                .iter()
+                .map(|s| format!("{}: {}", s.role, s.pin))
+                .collect();
+            format!("{}({})", b.interface, signals.join(", "))
+        }
+        None => "(unset)".to_owned(),
+    }
+}
+
+/// Describe a cost model by the expression for each resource it uses, like `luts: width, ffs: width`
+fn describe_resources(resources: &Option<ResourceModel>) -> String {
+    let Some(r) = resources else {
+        return "(unset)".to_owned();
+    };
+    let costs: Vec<_> = [
+        ("luts", &r.luts),
+        ("ffs", &r.ffs),
+        ("dsp48s", &r.dsp48s),
+        ("brams", &r.brams),
+    ]
+    .into_iter()
+    .filter_map(|(name, cost)| Some(format!("{}: {}", name, cost.as_ref()?)))
+    .collect();
+    if costs.is_empty() {
+        "(free)".to_owned()
+    } else {
+        costs.join(", ")
+    }
+}
+
+fn direction_name(direction: InterconnectDirection) -> &'static str {
+    match direction {
+        InterconnectDirection::Input => "input",
+        InterconnectDirection::Output => "output",
+        _ => unreachable!(),
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::ModuleAdded { module, name } => write!(f, "+ module {} `{}`", module, name),
+            Change::ModuleRemoved { module, name } => write!(f, "- module {} `{}`", module, name),
+            Change::ModuleRenamed { module, from, to } => {
+                write!(f, "~ module {} renamed `{}` -> `{}`", module, from, to)
+            }
+            Change::InstanceRenamed { module, from, to } => write!(
+                f,
+                "~ module {} instance: {} -> {}",
+                module,
+                from.as_deref().unwrap_or("(unset)"),
+                to.as_deref().unwrap_or("(unset)")
+            ),
+            Change::ParameterChanged {
+                module,
+                parameter,
+                from,
+                to,
+            } => write!(
+                f,
+                "~ module {} parameter `{}`: {} -> {}",
+                module,
+                parameter,
+                from.as_deref().unwrap_or("(unset)"),
+                to.as_deref().unwrap_or("(unset)")
+            ),
+            Change::BundleChanged {
+                module,
+                bundle,
+                from,
+                to,
+            } => write!(
+                f,
+                "~ module {} bundle `{}`: {} -> {}",
+                module,
+                bundle,
+                describe_bundle(from),
+                describe_bundle(to)
+            ),
+            Change::ResourcesChanged { module, from, to } => write!(
+                f,
+                "~ module {} resources: {} -> {}",
+                module,
+                describe_resources(from),
+                describe_resources(to)
+            ),
+            Change::LatencyChanged { module, from, to } => write!(
+                f,
+                "~ module {} latency: {} -> {}",
+                module,
+                from.as_deref().unwrap_or("(unset)"),
+                to.as_deref().unwrap_or("(unset)")
+            ),
+            Change::RangeChanged {
+                module,
+                pin,
+                from,
+                to,
+            } => write!(
+                f,
+                "~ output {}.`{}` range: {} -> {}",
+                module,
+                pin,
+                from.as_deref().unwrap_or("(unset)"),
+                to.as_deref().unwrap_or("(unset)")
+            ),
+            Change::PinAdded {
+                module,
+                pin,
+                direction,
+                kind,
+            } => write!(
+                f,
+                "+ {} {}.`{}` ({})",
+                direction_name(*direction),
+                module,
+                pin,
+                kind
+            ),
+            Change::PinRemoved {
+                module,
+                pin,
+                direction,
+                kind,
+            } => write!(
+                f,
+                "- {} {}.`{}` ({})",
+                direction_name(*direction),
+                module,
+                pin,
+                kind
+            ),
+            Change::PinKindChanged {
+                module,
+                pin,
+                direction,
+                from,
+                to,
+            } => write!(
+                f,
+                "~ {} {}.`{}` kind {} -> {}",
+                direction_name(*direction),
+                module,
+                pin,
+                from,
+                to
+            ),
+            Change::WireAdded { from, to } => write!(f, "+ wire {} -> {}", from, to),
+            Change::WireRemoved { from, to } => write!(f, "- wire {} -> {}", from, to),
+            Change::WireRerouted {
+                to,
+                old_from,
+                new_from,
+            } => write!(
+                f,
+                "~ wire {} driven by {} instead of {}",
+                to, new_from, old_from
+            ),
+        }
+    }
+}
+
+/// The list of changes taking one netlist to another
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct NetlistDiff {
+    pub changes: Vec<Change>,
+}
+
+impl NetlistDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl Display for NetlistDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the pins of one direction on a module that exists on both sides
+fn diff_pins(
+    module: i32,
+    direction: InterconnectDirection,
+    old: &[LibraryPin],
+    new: &[LibraryPin],
+    changes: &mut Vec<Change>,
+) {
+    let old: BTreeMap<_, _> = old.iter().map(|p| (&p.name, p.kind)).collect();
+    let new: BTreeMap<_, _> = new.iter().map(|p| (&p.name, p.kind)).collect();
+    for (name, kind) in &old {
+        match new.get(name) {
+            None => changes.push(Change::PinRemoved {
+                module,
+                pin: name.to_string(),
+                direction,
+                kind: *kind,
+            }),
+            Some(new_kind) if new_kind != kind => changes.push(Change::PinKindChanged {
+                module,
+                pin: name.to_string(),
+                direction,
+                from: *kind,
+                to: *new_kind,
+            }),
+            _ => (),
+        }
+    }
+    for (name, kind) in &new {
+        if !old.contains_key(name) {
+            changes.push(Change::PinAdded {
+                module,
+                pin: name.to_string(),
+                direction,
+                kind: *kind,
+            })
+        }
+    }
+}
+
+/// Compare a module that exists on both sides
+fn diff_module(old: &SavedModule, new: &SavedModule, changes: &mut Vec<Change>) {
+    // Positions are left out on purpose, as moving blocks around the canvas doesn't change the design
+    let module = old.id;
+    if old.name != new.name {
+        changes.push(Change::ModuleRenamed {
+            module,
+            from: old.name.clone(),
+            to: new.name.clone(),
+        });
+    }
+    if old.instance != new.instance {
+        changes.push(Change::InstanceRenamed {
+            module,
+            from: old.instance.clone(),
+            to: new.instance.clone(),
+        });
+    }
+    let mut parameters: Vec<_> = old.parameters.keys().chain(new.parameters.keys()).collect();
+    parameters.sort();
+    parameters.dedup();
+    for parameter in parameters {
+        let from = old.parameters.get(parameter);
+        let to = new.parameters.get(parameter);
+        if from != to {
+            changes.push(Change::ParameterChanged {
+                module,
+                parameter: parameter.clone(),
+                from: from.cloned(),
+                to: to.cloned(),
+            });
+        }
+    }
+    let old_bundles: BTreeMap<_, _> = old.bundles.iter().map(|b| (&b.name, b)).collect();
+    let new_bundles: BTreeMap<_, _> = new.bundles.iter().map(|b| (&b.name, b)).collect();
+    let mut names: Vec<_> = old_bundles.keys().chain(new_bundles.keys()).collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        let from = old_bundles.get(*name).copied();
+        let to = new_bundles.get(*name).copied();
+        if from != to {
+            changes.push(Change::BundleChanged {
+                module,
+                bundle: name.to_string(),
+                from: from.cloned(),
+                to: to.cloned(),
+            });
+        }
+    }
+    if old.resources != new.resources {
+        changes.push(Change::ResourcesChanged {
+            module,
+            from: old.resources.clone(),
+            to: new.resources.clone(),
+        });
+    }
+    if old.latency != new.latency {
+        changes.push(Change::LatencyChanged {
+            module,
+            from: old.latency.clone(),
+            to: new.latency.clone(),
+        });
+    }
+    let mut pins: Vec<_> = old.ranges.keys().chain(new.ranges.keys()).collect();
+    pins.sort();
+    pins.dedup();
+    for pin in pins {
+        let from = old.ranges.get(pin);
+        let to = new.ranges.get(pin);
+        if from != to {
+            changes.push(Change::RangeChanged {
+                module,
+                pin: pin.clone(),
+                from: from.cloned(),
+                to: to.cloned(),
+            });
+        }
+    }
+    diff_pins(
+        module,
+        InterconnectDirection::Input,
+        &old.inputs,
+        &new.inputs,
+        changes,
+    );
+    diff_pins(
+        module,
+        InterconnectDirection::Output,
+        &old.outputs,
+        &new.outputs,
+        changes,
+    );
+}
+
+/// Compute the changes that take the `old` netlist to the `new` one.
+/// Module changes come first, ordered by id, followed by the wiring changes ordered by the input they drive.
+pub fn diff(old: &SavedNetlist, new: &SavedNetlist) -> NetlistDiff {
+    let mut changes = vec![];
+    let old_mods: BTreeMap<_, _> = old.modules.iter().map(|m| (m.id, m)).collect();
+    let new_mods: BTreeMap<_, _> = new.modules.iter().map(|m| (m.id, m)).collect();
+    let mut ids: Vec<_> = old_mods.keys().chain(new_mods.keys()).copied().collect();
+    ids.sort();
+    ids.dedup();
+    for id in ids {
+        match (old_mods.get(&id), new_mods.get(&id)) {
+            (Some(o), Some(n)) => diff_module(o, n, &mut changes),
+            (Some(o), None) => changes.push(Change::ModuleRemoved {
+                module: id,
+                name: o.name.clone(),
+            }),
+            (None, Some(n)) => changes.push(Change::ModuleAdded {
+                module: id,
+                name: n.name.clone(),
+            }),
+            (None, None) => unreachable!(),
+        }
+    }
+    // Every input has at most one driver, so wires are keyed by the input they drive
+    let old_wires: BTreeMap<_, _> = old.wires.iter().map(|w| (&w.to, &w.from)).collect();
+    let new_wires: BTreeMap<_, _> = new.wires.iter().map(|w| (&w.to, &w.from)).collect();
+    let mut inputs: Vec<_> = old_wires.keys().chain(new_wires.keys()).copied().collect();
+    inputs.sort();
+    inputs.dedup();
+    for to in inputs {
+        match (old_wires.get(to), new_wires.get(to)) {
+            (Some(o), Some(n)) if o != n => changes.push(Change::WireRerouted {
+                to: to.clone(),
+                old_from: (*o).clone(),
+                new_from: (*n).clone(),
+            }),
+            (Some(o), None) => changes.push(Change::WireRemoved {
+                from: (*o).clone(),
+                to: to.clone(),
+            }),
+            (None, Some(n)) => changes.push(Change::WireAdded {
+                from: (*n).clone(),
+                to: to.clone(),
+            }),
+            _ => (),
+        }
+    }
+    NetlistDiff { changes }
+}
+
+impl Netlist {
+    /// Compute the changes that take this netlist to `other`
+    pub fn diff(&self, other: &Netlist) -> NetlistDiff {
+        diff(&self.to_saved(), &other.to_saved())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::BundleSignal;
+
+    fn pin_ref(module: i32, pin: &str) -> SavedPinRef {
+        SavedPinRef {
+            module,
+            pin: pin.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_identical() {
+        let netlist = Netlist::from_json(include_str!("../resources/netlist.json")).unwrap();
+        assert!(netlist.diff(&netlist).is_empty());
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = Netlist::from_json(include_str!("../resources/netlist.json")).unwrap();
+        let mut new = Netlist::from_json(include_str!("../resources/netlist.json")).unwrap();
+
+        // Bump a parameter on the source
+        let src = new.find_module(0).unwrap();
+        new.set_parameter(src, "width".to_owned(), "16".to_owned());
+        // Add a new source and reroute the sink to it
+        let new_src = new.add_module("Constant".to_owned());
+        let out = new
+            .add_pin(
+                new_src,
+                "O".to_owned(),
+                PinKind::Wire,
+                InterconnectDirection::Output,
+            )
+            .unwrap();
+        let sink = new.find_module(1).unwrap();
+        let input = new
+            .find_pin(sink, "A", InterconnectDirection::Input)
+            .unwrap();
+        let wi = new
+            .wires()
+            .find(|(_, (x, _))| *x == input)
+            .map(|(i, _)| crate::netlist::WireIndex(i))
+            .unwrap();
+        new.remove_wire(wi).unwrap();
+        new.add_wire(out, input).unwrap();
+        // Drop the sink's second input, along with its wire
+        let b = new
+            .find_pin(sink, "B", InterconnectDirection::Input)
+            .unwrap();
+        new.remove_pin(b).unwrap();
+
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.changes,
+            vec![
+                Change::ParameterChanged {
+                    module: 0,
+                    parameter: "width".to_owned(),
+                    from: Some("8".to_owned()),
+                    to: Some("16".to_owned()),
+                },
+                Change::PinRemoved {
+                    module: 1,
+                    pin: "B".to_owned(),
+                    direction: InterconnectDirection::Input,
+                    kind: PinKind::Wire,
+                },
+                Change::ModuleAdded {
+                    module: 2,
+                    name: "Constant".to_owned(),
+                },
+                Change::WireRerouted {
+                    to: pin_ref(1, "A"),
+                    old_from: pin_ref(0, "O"),
+                    new_from: pin_ref(2, "O"),
+                },
+                Change::WireRemoved {
+                    from: pin_ref(0, "O"),
+                    to: pin_ref(1, "B"),
+                },
+            ]
+        );
+        assert_eq!(
+            diff.to_string(),
+            "~ module 0 parameter `width`: 8 -> 16\n\
+             - input 1.`B` (wire)\n\
+             + module 2 `Constant`\n\
+             ~ wire 1.`A` driven by 2.`O` instead of 0.`O`\n\
+             - wire 0.`O` -> 1.`B`\n"
+        );
+    }
+
+    #[test]
+    fn test_module_attributes() {
+        let old = Netlist::from_json(include_str!("../resources/netlist.json")).unwrap();
+        let mut new = Netlist::from_json(include_str!("../resources/netlist.json")).unwrap();
+        let src = new.find_module(0).unwrap();
+        let bundle = Bundle {
+            name: "out".to_owned(),
+            interface: "stream".to_owned(),
+            signals: vec![BundleSignal {
+                role: "data".to_owned(),
+                pin: "O".to_owned(),
+            }],
+        };
+        new.set_bundles(src, vec![bundle.clone()]);
+        let resources = ResourceModel {
+            luts: Some("width".to_owned()),
+            ffs: Some("width".to_owned()),
+            ..Default::default()
+        };
+        new.set_resource_model(src, Some(resources.clone()));
+        new.set_latency(src, Some("taps + 1".to_owned()));
+        new.set_ranges(
+            src,
+            BTreeMap::from([("O".to_owned(), "range(0, 255)".to_owned())]),
+        );
+
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.changes,
+            vec![
+                Change::BundleChanged {
+                    module: 0,
+                    bundle: "out".to_owned(),
+                    from: None,
+                    to: Some(bundle),
+                },
+                Change::ResourcesChanged {
+                    module: 0,
+                    from: None,
+                    to: Some(resources),
+                },
+                Change::LatencyChanged {
+                    module: 0,
+                    from: None,
+                    to: Some("taps + 1".to_owned()),
+                },
+                Change::RangeChanged {
+                    module: 0,
+                    pin: "O".to_owned(),
+                    from: None,
+                    to: Some("range(0, 255)".to_owned()),
+                },
+            ]
+        );
+        assert_eq!(
+            diff.to_string(),
+            "~ module 0 bundle `out`: (unset) -> stream(data: O)\n\
+             ~ module 0 resources: (unset) -> luts: width, ffs: width\n\
+             ~ module 0 latency: (unset) -> taps + 1\n\
+             ~ output 0.`O` range: (unset) -> range(0, 255)\n"
+        );
+    }
+}
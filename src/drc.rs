@@ -0,0 +1,212 @@
+//! This module contains the design rule checks run before emitting any HDL
+
+use crate::netlist::{Module, Netlist};
+use crate::project::{Project, Subgraph};
+use serde::Serialize;
+use std::fmt::Display;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    pub severity: Severity,
+    /// The subgraph the violation was found in, if checked as part of a project
+    pub subgraph: Option<String>,
+    /// The id of the offending module
+    pub module: Option<i32>,
+    pub message: String,
+}
+
+impl Violation {
+    fn new(severity: Severity, module: Option<&Module>, message: String) -> Self {
+        Self {
+            severity,
+            subgraph: None,
+            module: module.map(Module::id),
+            message,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: ")?,
+            Severity::Error => write!(f, "error: ")?,
+        }
+        if let Some(sg) = &self.subgraph {
+            write!(f, "{}: ", sg)?;
+        }
+        if let Some(m) = self.module {
+            write!(f, "module {}: ", m)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Netlist {
+    /// Check the netlist for undriven inputs and unused outputs
+    pub fn drc(&self) -> Vec<Violation> {
+        let mut violations = vec![];
+        let mut modules: Vec<_> = self.modules().map(|(_, m)| m).collect();
+        modules.sort_by_key(|m| m.id());
+        for m in modules {
+            for pi in m.inputs() {
+                let p = self.get_pin(*pi).expect("Module pins always exist");
+                if self.driver(*pi).is_none() {
+                    violations.push(Violation::new(
+                        Severity::Error,
+                        Some(m),
+                        format!("Input `{}` of `{}` is undriven", p.name(), m.name()),
+                    ));
+                }
+            }
+            for pi in m.outputs() {
+                let p = self.get_pin(*pi).expect("Module pins always exist");
+                if self.fanout(*pi).is_some_and(|x| x.is_empty()) {
+                    violations.push(Violation::new(
+                        Severity::Warning,
+                        Some(m),
+                        format!("Output `{}` of `{}` is unused", p.name(), m.name()),
+                    ));
+                }
+            }
+        }
+        violations
+    }
+}
+
+/// Check that the pins of a module instantiating `child` line up with the child's ports
+fn check_instance(netlist: &Netlist, m: &Module, child: &Subgraph) -> Vec<Violation> {
+    let mut violations = vec![];
+    let pins = |pins: std::slice::Iter<'_, _>| -> Vec<_> {
+        pins.filter_map(|x| netlist.get_pin(*x))
+            .map(|p| (p.name(), p.kind()))
+            .collect()
+    };
+    for (direction, pins, ports) in [
+        ("input", pins(m.inputs()), child.input_ports()),
+        ("output", pins(m.outputs()), child.output_ports()),
+    ] {
+        for (name, kind) in &ports {
+            match pins.iter().find(|(n, _)| n == name) {
+                None => violations.push(Violation::new(
+                    Severity::Error,
+                    Some(m),
+                    format!(
+                        "Missing {} `{}` of subgraph `{}`",
+                        direction,
+                        name,
+                        child.name()
+                    ),
+                )),
+                Some((_, k)) if k != kind => violations.push(Violation::new(
+                    Severity::Error,
+                    Some(m),
+                    format!(
+                        "{} `{}` is {} but subgraph `{}` expects {}",
+                        direction,
+                        name,
+                        k,
+                        child.name(),
+                        kind
+                    ),
+                )),
+                _ => (),
+            }
+        }
+        for (name, _) in &pins {
+            if !ports.iter().any(|(n, _)| n == name) {
+                violations.push(Violation::new(
+                    Severity::Error,
+                    Some(m),
+                    format!(
+                        "Subgraph `{}` has no {} `{}`",
+                        child.name(),
+                        direction,
+                        name
+                    ),
+                ));
+            }
+        }
+    }
+    violations
+}
+
+impl Project {
+    /// Run the design rule checks over every subgraph in the hierarchy, along with checks on the hierarchy itself
+    pub fn drc(&self) -> Vec<Violation> {
+        let subgraphs = match self.hierarchy_order() {
+            Ok(x) => x,
+            Err(e) => return vec![Violation::new(Severity::Error, None, e.to_string())],
+        };
+        let mut violations = vec![];
+        for sg in subgraphs {
+            let netlist = sg.netlist();
+            let mut found = netlist.drc();
+            for (_, m) in netlist.modules() {
+                if let Some(child) = self.subgraph(m.name()) {
+                    found.extend(check_instance(netlist, m, child));
+                }
+            }
+            violations.extend(found.into_iter().map(|v| Violation {
+                subgraph: Some(sg.name().to_owned()),
+                ..v
+            }));
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_netlist_drc() {
+        let netlist = Netlist::from_json(include_str!("../resources/netlist.json")).unwrap();
+        assert!(netlist.drc().is_empty());
+        let mut netlist = netlist;
+        let sink = netlist.find_module(1).unwrap();
+        netlist.add_pin(
+            sink,
+            "C".to_owned(),
+            crate::ffi::PinKind::Wire,
+            crate::ffi::InterconnectDirection::Input,
+        );
+        let violations = netlist.drc();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(
+            violations[0].to_string(),
+            "error: module 1: Input `C` of `Sink` is undriven"
+        );
+    }
+
+    #[test]
+    fn test_project_drc() {
+        let project = Project::from_json(include_str!("../resources/project.json")).unwrap();
+        assert!(project.drc().is_empty());
+        // Rename the input port of the inverter subgraph
+        let mut broken: serde_json::Value =
+            serde_json::from_str(include_str!("../resources/project.json")).unwrap();
+        broken["subgraphs"][1]["netlist"]["modules"][0]["name"] = "b".into();
+        let project = Project::from_json(&broken.to_string()).unwrap();
+        let violations: Vec<_> = project.drc().iter().map(|x| x.to_string()).collect();
+        assert_eq!(
+            violations,
+            vec![
+                "error: top: module 1: Missing input `b` of subgraph `invert`",
+                "error: top: module 1: Subgraph `invert` has no input `a`",
+            ]
+        );
+    }
+}
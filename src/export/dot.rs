@@ -0,0 +1,121 @@
+//! Graphviz DOT backend, for diagrams in docs and reviews
+//! Modules become record nodes with a port per pin, inputs on the left and outputs on the right,
+//! and wires become edges labelled with the kind of data they carry.
+
+use super::sorted_modules;
+use crate::netlist::{Netlist, PinIndex};
+use std::fmt::Write;
+
+/// Quote a string for DOT, where only quotes need escaping
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\\\""))
+}
+
+/// Escape the characters that structure a record label
+fn escape_record(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        if "{}|<>\"\\ ".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// The record fields for a list of pins, with ports named `prefix` and their position
+fn fields(netlist: &Netlist, pins: std::slice::Iter<'_, PinIndex>, prefix: char) -> String {
+    let fields: Vec<_> = pins
+        .enumerate()
+        .map(|(n, pi)| {
+            let p = netlist.get_pin(*pi).expect("Module pins always exist");
+            format!("<{}{}> {}", prefix, n, escape_record(p.name()))
+        })
+        .collect();
+    fields.join("|")
+}
+
+/// Find which of its module's pins `idx` is, for the record port
+fn port(netlist: &Netlist, idx: PinIndex) -> (i32, usize) {
+    let p = netlist.get_pin(idx).expect("Wired pins always exist");
+    let m = netlist
+        .get_module(p.parent())
+        .expect("Pins always have a module");
+    let mut pins = if p.is_input() {
+        m.inputs()
+    } else {
+        m.outputs()
+    };
+    let n = pins
+        .position(|x| *x == idx)
+        .expect("Modules list their pins");
+    (m.id(), n)
+}
+
+/// Render a netlist as a left-to-right Graphviz digraph called `name`
+pub fn dot(netlist: &Netlist, name: &str) -> String {
+    let mut out = String::new();
+    writeln!(out, "digraph {} {{", quote(name)).unwrap();
+    writeln!(out, "    rankdir=LR;").unwrap();
+    writeln!(out, "    node [shape=record, fontname=\"Helvetica\"];").unwrap();
+    writeln!(out, "    edge [fontname=\"Helvetica\", fontsize=10];").unwrap();
+    for (_, m) in sorted_modules(netlist) {
+        // With the graph on its side, the outer braces lay the fields out left to right again
+        let mut groups = vec![];
+        if m.inputs().len() > 0 {
+            groups.push(format!("{{{}}}", fields(netlist, m.inputs(), 'i')));
+        }
+        groups.push(format!(
+            "{}\\n({})",
+            escape_record(m.instance()),
+            escape_record(m.name())
+        ));
+        if m.outputs().len() > 0 {
+            groups.push(format!("{{{}}}", fields(netlist, m.outputs(), 'o')));
+        }
+        // Record escapes already cover quotes, and backslashes pass through DOT strings untouched
+        writeln!(out, "    m{} [label=\"{{{}}}\"];", m.id(), groups.join("|")).unwrap();
+    }
+    let mut wires: Vec<_> = netlist
+        .wires()
+        .map(|(_, (input, output))| (port(netlist, *input), port(netlist, *output), *output))
+        .collect();
+    wires.sort_by_key(|(to, from, _)| (*to, *from));
+    for ((to, i), (from, o), output) in wires {
+        let kind = netlist
+            .get_pin(output)
+            .expect("Wired pins always exist")
+            .kind();
+        writeln!(
+            out,
+            "    m{}:o{}:e -> m{}:i{}:w [label={}];",
+            from,
+            o,
+            to,
+            i,
+            quote(&kind.to_string())
+        )
+        .unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dot() {
+        let netlist = Netlist::from_json(include_str!("../../resources/netlist.json")).unwrap();
+        assert_eq!(
+            dot(&netlist, "netlist"),
+            include_str!("../../resources/netlist.dot")
+        );
+    }
+
+    #[test]
+    fn test_escape_record() {
+        assert_eq!(escape_record("a {b} | <c>"), "a\\ \\{b\\}\\ \\|\\ \\<c\\>");
+    }
+}
@@ -0,0 +1,157 @@
+//! FuseSoC backend, writing a CAPI2 `.core` file next to the Verilog and constraints it lists.
+//! The core has a Vivado target, and an F4PGA one for the 7 series parts it supports, so
+//! `fusesoc run --target vivado` builds the design through Edalize.
+
+use super::{identifier, verilog::verilog, xdc::xdc, Names, VERILOG};
+use crate::project::Project;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// The FPGA on a SNAP board
+pub const SNAP_PART: &str = "xc7k160tffg676-2";
+
+/// What the core is built for, and with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreOptions {
+    /// The version in the core's VLNV name
+    pub version: String,
+    /// The Xilinx part both targets build for
+    pub part: String,
+    /// Verilog defines, given to every target as parameters
+    pub defines: BTreeMap<String, String>,
+}
+
+impl Default for CoreOptions {
+    fn default() -> Self {
+        Self {
+            version: "0.1.0".to_owned(),
+            part: SNAP_PART.to_owned(),
+            defines: BTreeMap::new(),
+        }
+    }
+}
+
+/// The 7 series family F4PGA knows the part by, if it's one F4PGA can build for
+fn device_type(part: &str) -> Option<&'static str> {
+    match part.get(..4)? {
+        "xc7a" => Some("artix7"),
+        "xc7k" => Some("kintex7"),
+        "xc7s" => Some("spartan7"),
+        "xc7z" => Some("zynq7"),
+        _ => None,
+    }
+}
+
+/// Quote a string for YAML, which takes any JSON string
+fn quote(value: &str) -> String {
+    serde_json::Value::from(value).to_string()
+}
+
+/// Make the core for the project, returning the contents of every file by name: the core itself, the Verilog of the
+/// design and its generated blocks, and the XDC constraints
+pub fn fusesoc(
+    project: &Project,
+    options: &CoreOptions,
+) -> anyhow::Result<BTreeMap<String, String>> {
+    let names = Names::new(project, &VERILOG)?;
+    let toplevel = &names.subgraph(project.top()).module;
+    let stem = identifier(project.name());
+    let (rtl, constraints) = (format!("{}.v", stem), format!("{}.xdc", stem));
+
+    let mut out = format!(
+        "CAPI=2:\n# Generated by CasperFlow from project `{}`\n",
+        project.name()
+    );
+    writeln!(out, "name: casperflow:user:{}:{}", stem, options.version)?;
+    writeln!(
+        out,
+        "description: {}",
+        quote(&format!("CasperFlow project {}", project.name()))
+    )?;
+    writeln!(out, "\nfilesets:")?;
+    for (name, file, kind) in [
+        ("rtl", &rtl, "verilogSource"),
+        ("constraints", &constraints, "xdc"),
+    ] {
+        writeln!(out, "  {}:", name)?;
+        writeln!(out, "    files:\n      - {}", quote(file))?;
+        writeln!(out, "    file_type: {}", kind)?;
+    }
+    if !options.defines.is_empty() {
+        writeln!(out, "\nparameters:")?;
+        for (name, value) in &options.defines {
+            let (datatype, value) = match value.parse::<i64>() {
+                Ok(_) => ("int", value.clone()),
+                Err(_) => ("str", quote(value)),
+            };
+            writeln!(out, "  {}:", name)?;
+            writeln!(out, "    datatype: {}", datatype)?;
+            writeln!(out, "    paramtype: vlogdefine")?;
+            writeln!(out, "    default: {}", value)?;
+        }
+    }
+    writeln!(out, "\ntargets:")?;
+    writeln!(out, "  default: &default")?;
+    writeln!(out, "    filesets: [rtl]")?;
+    writeln!(out, "    toplevel: {}", toplevel)?;
+    if !options.defines.is_empty() {
+        let defines: Vec<_> = options.defines.keys().map(String::as_str).collect();
+        writeln!(out, "    parameters: [{}]", defines.join(", "))?;
+    }
+    let mut targets = vec![("vivado", vec![("part", options.part.as_str())])];
+    if let Some(device_type) = device_type(&options.part) {
+        targets.push((
+            "f4pga",
+            vec![
+                ("arch", "xilinx"),
+                ("device_type", device_type),
+                ("part", options.part.as_str()),
+            ],
+        ));
+    }
+    for (tool, settings) in targets {
+        writeln!(out, "  {}:", tool)?;
+        writeln!(out, "    <<: *default")?;
+        writeln!(out, "    default_tool: {}", tool)?;
+        writeln!(out, "    filesets: [rtl, constraints]")?;
+        writeln!(out, "    tools:\n      {}:", tool)?;
+        for (key, value) in settings {
+            writeln!(out, "        {}: {}", key, quote(value))?;
+        }
+    }
+
+    Ok(BTreeMap::from([
+        (format!("{}.core", stem), out),
+        (rtl, verilog(project)?),
+        (constraints, xdc(project)?),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fusesoc() {
+        let project = Project::from_json(include_str!("../../resources/project.json")).unwrap();
+        let mut options = CoreOptions::default();
+        options.defines.insert("SEED".to_owned(), "42".to_owned());
+        let files = fusesoc(&project, &options).unwrap();
+        assert_eq!(
+            files.keys().collect::<Vec<_>>(),
+            ["blinky.core", "blinky.v", "blinky.xdc"]
+        );
+        assert_eq!(
+            files["blinky.core"],
+            include_str!("../../resources/blinky.core")
+        );
+        assert_eq!(files["blinky.v"], include_str!("../../resources/project.v"));
+        assert_eq!(files["blinky.xdc"], xdc(&project).unwrap());
+
+        // UltraScale parts are beyond F4PGA
+        options.part = "xcku040-ffva1156-2-e".to_owned();
+        let core = &fusesoc(&project, &options).unwrap()["blinky.core"];
+        assert!(core.contains("part: \"xcku040-ffva1156-2-e\""));
+        assert!(!core.contains("f4pga"));
+    }
+}
@@ -0,0 +1,454 @@
+//! This module contains the backends that turn a project into files for other tools
+
+pub mod dot;
+pub mod fusesoc;
+pub mod rusthdl;
+pub mod svg;
+pub mod systemverilog;
+pub mod verilog;
+pub mod vhdl;
+pub mod xdc;
+
+use crate::ffi::PinKind;
+use crate::netlist::{Module, ModuleIndex, Netlist, PinIndex};
+use crate::project::{Project, Subgraph};
+use std::collections::{HashMap, HashSet};
+
+/// The rules for identifiers in one HDL
+pub struct Language {
+    /// Reserved words that can't be used as identifiers, separated by whitespace
+    pub keywords: &'static str,
+    /// Whether `foo` and `FOO` are different identifiers
+    pub case_sensitive: bool,
+    /// Whether identifiers can start or end with an underscore, or have two in a row
+    pub loose_underscores: bool,
+}
+
+/// Verilog-2005, whose keywords are all lowercase
+pub const VERILOG: Language = Language {
+    keywords: "\
+        always and assign automatic begin buf bufif0 bufif1 case casex casez cell cmos config \
+        deassign default defparam design disable edge else end endcase endconfig endfunction \
+        endgenerate endmodule endprimitive endspecify endtable endtask event for force forever \
+        fork function generate genvar highz0 highz1 if ifnone incdir include initial inout input \
+        instance integer join large liblist library localparam macromodule medium module nand \
+        negedge nmos nor noshowcancelled not notif0 notif1 or output parameter pmos posedge \
+        primitive pull0 pull1 pulldown pullup pulsestyle_ondetect pulsestyle_onevent rcmos real \
+        realtime reg release repeat rnmos rpmos rtran rtranif0 rtranif1 scalared showcancelled \
+        signed small specify specparam strong0 strong1 supply0 supply1 table task time tran \
+        tranif0 tranif1 tri tri0 tri1 triand trior trireg unsigned use uwire vectored wait wand \
+        weak0 weak1 while wire wor xnor xor",
+    case_sensitive: true,
+    loose_underscores: true,
+};
+
+/// Rust 2021, with the RustHDL prelude's types reserved too as they share the generated module's scope, and `_`
+/// as it can't name a field
+pub const RUST: Language = Language {
+    keywords: "\
+        abstract as async await become box break const continue crate do dyn else enum extern \
+        false final fn for if impl in let loop macro match mod move mut override priv pub ref \
+        return self Self static struct super trait true try type typeof unsafe unsized use \
+        virtual where while yield _ Bits Clock Default In Logic LogicBlock Out Signal Signed",
+    case_sensitive: true,
+    loose_underscores: true,
+};
+
+/// SystemVerilog-2017, whose keywords are a superset of Verilog's
+pub const SYSTEMVERILOG: Language = Language {
+    keywords: "\
+        accept_on alias always always_comb always_ff always_latch and assert assign assume \
+        automatic before begin bind bins binsof bit break buf bufif0 bufif1 byte case casex casez \
+        cell chandle checker class clocking cmos config const constraint context continue cover \
+        covergroup coverpoint cross deassign default defparam design disable dist do edge else \
+        end endcase endchecker endclass endclocking endconfig endfunction endgenerate endgroup \
+        endinterface endmodule endpackage endprimitive endprogram endproperty endsequence \
+        endspecify endtable endtask enum event eventually expect export extends extern final \
+        first_match for force foreach forever fork forkjoin function generate genvar global \
+        highz0 highz1 if iff ifnone ignore_bins illegal_bins implements implies import incdir \
+        include initial inout input inside instance int integer interconnect interface \
+        intersect join join_any join_none large let liblist library local localparam logic \
+        longint macromodule matches medium modport module nand negedge nettype new nexttime nmos \
+        nor noshowcancelled not notif0 notif1 null or output package packed parameter pmos \
+        posedge primitive priority program property protected pull0 pull1 pulldown pullup \
+        pulsestyle_ondetect pulsestyle_onevent pure rand randc randcase randsequence rcmos real \
+        realtime ref reg reject_on release repeat restrict return rnmos rpmos rtran rtranif0 \
+        rtranif1 s_always s_eventually s_nexttime s_until s_until_with scalared sequence \
+        shortint shortreal showcancelled signed small soft solve specify specparam static string \
+        strong strong0 strong1 struct super supply0 supply1 sync_accept_on sync_reject_on table \
+        tagged task this throughout time timeprecision timeunit tran tranif0 tranif1 tri tri0 \
+        tri1 triand trior trireg type typedef union unique unique0 unsigned until until_with \
+        untyped use uwire var vectored virtual void wait wait_order wand weak weak0 weak1 while \
+        wildcard wire with within wor xnor xor",
+    case_sensitive: true,
+    loose_underscores: true,
+};
+
+/// VHDL-2008, restricted to basic identifiers
+pub const VHDL: Language = Language {
+    keywords: "\
+        abs access after alias all and architecture array assert assume assume_guarantee \
+        attribute begin block body buffer bus case component configuration constant context \
+        cover default disconnect downto else elsif end entity exit fairness file for force \
+        function generate generic group guarded if impure in inertial inout is label library \
+        linkage literal loop map mod nand new next nor not null of on open or others out \
+        package parameter port postponed procedure process property protected pure range record \
+        register reject release rem report restrict restrict_guarantee return rol ror select \
+        sequence severity shared signal sla sll sra srl strong subtype then to transport type \
+        unaffected units until use variable vmode vprop vunit wait when while with xnor xor",
+    case_sensitive: false,
+    loose_underscores: false,
+};
+
+impl Language {
+    /// Turn an arbitrary name into an identifier of this language, which might still be a keyword
+    pub fn identifier(&self, name: &str) -> String {
+        let ident = identifier(name);
+        if self.loose_underscores {
+            return ident;
+        }
+        // Squash runs of underscores and trim them from the ends, then make sure we start with a letter
+        let mut ident = ident
+            .split('_')
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>()
+            .join("_");
+        if !ident.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            ident.insert(0, 'x');
+        }
+        ident
+    }
+
+    /// Check if `ident` is a reserved word
+    pub fn is_keyword(&self, ident: &str) -> bool {
+        let mut keywords = self.keywords.split_whitespace();
+        if self.case_sensitive {
+            keywords.any(|k| k == ident)
+        } else {
+            keywords.any(|k| k.eq_ignore_ascii_case(ident))
+        }
+    }
+}
+
+/// Turn an arbitrary name into an HDL identifier by replacing anything that isn't alphanumeric with an
+/// underscore, prefixing an underscore if it would start with a digit. An empty name becomes `x`.
+pub fn identifier(name: &str) -> String {
+    if name.is_empty() {
+        return "x".to_owned();
+    }
+    let mut ident: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !ident.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        ident.insert(0, '_');
+    }
+    ident
+}
+
+/// A scope of identifiers, handing out legal names that don't collide with each other or with keywords
+pub struct Namespace<'a> {
+    language: &'a Language,
+    used: HashSet<String>,
+}
+
+impl<'a> Namespace<'a> {
+    pub fn new(language: &'a Language) -> Self {
+        Self {
+            language,
+            used: HashSet::new(),
+        }
+    }
+
+    /// Get an identifier for `name`, numbering it if it's a keyword or already taken
+    pub fn claim(&mut self, name: &str) -> String {
+        let base = self.language.identifier(name);
+        let ident = std::iter::once(base.clone())
+            .chain((1..).map(|n| format!("{}_{}", base, n)))
+            .find(|x| !self.language.is_keyword(x) && !self.used.contains(&self.key(x)))
+            .expect("There's always a free name");
+        self.used.insert(self.key(&ident));
+        ident
+    }
+
+    fn key(&self, ident: &str) -> String {
+        if self.language.case_sensitive {
+            ident.to_owned()
+        } else {
+            ident.to_ascii_lowercase()
+        }
+    }
+}
+
+/// The identifiers of everything in one subgraph
+#[derive(Debug)]
+pub struct SubgraphNames {
+    /// The name of the module the subgraph becomes
+    pub module: String,
+    ports: HashMap<ModuleIndex, String>,
+    // The same port identifiers, keyed by the name of the port module
+    port_names: HashMap<String, String>,
+    instances: HashMap<ModuleIndex, String>,
+    nets: HashMap<PinIndex, String>,
+    formals: HashMap<PinIndex, String>,
+    // The port and the interface instance of each pin bundle of a library block, by bundle position
+    bundles: HashMap<(ModuleIndex, usize), (String, String)>,
+}
+
+impl SubgraphNames {
+    /// The name of the port a port module becomes
+    pub fn port(&self, idx: ModuleIndex) -> &str {
+        &self.ports[&idx]
+    }
+
+    /// The name of the port made from the port module `name`, if there is one
+    pub fn port_named(&self, name: &str) -> Option<&str> {
+        self.port_names.get(name).map(String::as_str)
+    }
+
+    /// The name of the instance of a block
+    pub fn instance(&self, idx: ModuleIndex) -> &str {
+        &self.instances[&idx]
+    }
+
+    /// The name of the net driven by the output pin `idx`.
+    /// Nets driven by subgraph inputs are just the port itself.
+    pub fn net(&self, idx: PinIndex) -> &str {
+        &self.nets[&idx]
+    }
+
+    /// The name a block's pin has on the module it instantiates
+    pub fn formal(&self, idx: PinIndex) -> &str {
+        &self.formals[&idx]
+    }
+
+    /// The name of the port the `n`th bundle of a library block becomes
+    pub fn bundle_port(&self, idx: ModuleIndex, n: usize) -> &str {
+        &self.bundles[&(idx, n)].0
+    }
+
+    /// The name of an interface instance carrying the `n`th bundle of a library block
+    pub fn bundle_instance(&self, idx: ModuleIndex, n: usize) -> &str {
+        &self.bundles[&(idx, n)].1
+    }
+}
+
+/// The identifiers of every module, instance, net and port in a project, worked out once so that every
+/// backend agrees and nothing collides
+#[derive(Debug)]
+pub struct Names {
+    modules: HashMap<String, String>,
+    interfaces: HashMap<String, String>,
+    subgraphs: HashMap<String, SubgraphNames>,
+}
+
+impl Names {
+    pub fn new(project: &Project, language: &Language) -> anyhow::Result<Self> {
+        let order = project.hierarchy_order()?;
+        // Subgraphs get first pick of the module names, as library blocks are defined elsewhere anyway
+        let mut scope = Namespace::new(language);
+        let mut modules = HashMap::new();
+        for sg in &order {
+            modules.insert(sg.name().to_owned(), scope.claim(sg.name()));
+        }
+        for sg in &order {
+            for (_, m) in sg.netlist().modules() {
+                if !modules.contains_key(m.name()) {
+                    modules.insert(m.name().to_owned(), scope.claim(m.name()));
+                }
+            }
+        }
+        let mut interfaces = HashMap::new();
+        for sg in &order {
+            for (_, m) in sg.netlist().modules() {
+                for b in m.bundles() {
+                    if !interfaces.contains_key(&b.interface) {
+                        interfaces.insert(b.interface.clone(), scope.claim(&b.interface));
+                    }
+                }
+            }
+        }
+        let mut names = Self {
+            modules,
+            interfaces,
+            subgraphs: HashMap::new(),
+        };
+        // Children come first, so their port names are known by the time they're instantiated
+        for sg in order {
+            let sg_names = names.name_subgraph(project, sg, language);
+            names.subgraphs.insert(sg.name().to_owned(), sg_names);
+        }
+        Ok(names)
+    }
+
+    fn name_subgraph(
+        &self,
+        project: &Project,
+        sg: &Subgraph,
+        language: &Language,
+    ) -> SubgraphNames {
+        let netlist = sg.netlist();
+        let mut scope = Namespace::new(language);
+        let mut ports = HashMap::new();
+        let mut port_names = HashMap::new();
+        let mut nets = HashMap::new();
+        for idx in sg.inputs().chain(sg.outputs()) {
+            let m = netlist.get_module(*idx).expect("Ports always exist");
+            let port = scope.claim(m.name());
+            if let Some(pi) = m.outputs().next() {
+                nets.insert(*pi, port.clone());
+            }
+            port_names.insert(m.name().to_owned(), port.clone());
+            ports.insert(*idx, port);
+        }
+        let blocks: Vec<_> = sorted_modules(netlist)
+            .into_iter()
+            .filter(|(i, _)| !sg.is_port(*i))
+            .collect();
+        let mut instances = HashMap::new();
+        for (i, m) in &blocks {
+            instances.insert(*i, scope.claim(m.instance()));
+        }
+        let mut formals = HashMap::new();
+        let mut bundles = HashMap::new();
+        for (i, m) in &blocks {
+            for pi in m.outputs() {
+                let p = netlist.get_pin(*pi).expect("Module pins always exist");
+                nets.insert(*pi, scope.claim(&format!("{}_{}", instances[i], p.name())));
+            }
+            // Pins of subgraph instances take the child's port names, anything else names them itself
+            let child = project
+                .subgraph(m.name())
+                .map(|x| &self.subgraphs[x.name()]);
+            let mut pin_scope = Namespace::new(language);
+            for pi in m.inputs().chain(m.outputs()) {
+                let p = netlist.get_pin(*pi).expect("Module pins always exist");
+                let formal = match child.and_then(|c| c.port_named(p.name())) {
+                    Some(x) => x.to_owned(),
+                    None => pin_scope.claim(p.name()),
+                };
+                formals.insert(*pi, formal);
+            }
+            if child.is_none() {
+                for (n, b) in m.bundles().iter().enumerate() {
+                    let instance = scope.claim(&format!("{}_{}", instances[i], b.name));
+                    bundles.insert((*i, n), (pin_scope.claim(&b.name), instance));
+                }
+            }
+        }
+        SubgraphNames {
+            module: self.modules[sg.name()].clone(),
+            ports,
+            port_names,
+            instances,
+            nets,
+            formals,
+            bundles,
+        }
+    }
+
+    /// The name of the module a block or subgraph `name` becomes
+    pub fn module(&self, name: &str) -> &str {
+        &self.modules[name]
+    }
+
+    /// The name of the bundle interface `name`
+    pub fn interface(&self, name: &str) -> &str {
+        &self.interfaces[name]
+    }
+
+    /// Get the names within a subgraph
+    pub fn subgraph(&self, sg: &Subgraph) -> &SubgraphNames {
+        &self.subgraphs[sg.name()]
+    }
+}
+
+/// Get the kind of the single pin of a port module
+pub fn port_kind(netlist: &Netlist, idx: ModuleIndex) -> PinKind {
+    let m = netlist.get_module(idx).expect("Ports always exist");
+    let pi = m.inputs().chain(m.outputs()).next();
+    let pi = pi.expect("Ports always have a pin");
+    netlist.get_pin(*pi).expect("Pins always exist").kind()
+}
+
+/// Get the modules of a netlist ordered by id, so generated files are stable
+pub fn sorted_modules(netlist: &Netlist) -> Vec<(ModuleIndex, &Module)> {
+    let mut modules: Vec<_> = netlist
+        .modules()
+        .map(|(i, m)| (ModuleIndex(i), m))
+        .collect();
+    modules.sort_by_key(|(_, m)| m.id());
+    modules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identifier() {
+        assert_eq!(identifier("10 GbE"), "_10_GbE");
+        assert_eq!(identifier("Input Data"), "Input_Data");
+        assert_eq!(identifier("ok_name"), "ok_name");
+        assert_eq!(identifier(""), "x");
+    }
+
+    #[test]
+    fn test_namespace() {
+        let mut scope = Namespace::new(&VERILOG);
+        assert_eq!(scope.claim("10 GbE"), "_10_GbE");
+        assert_eq!(scope.claim("10-GbE"), "_10_GbE_1");
+        assert_eq!(scope.claim("reg"), "reg_1");
+        assert_eq!(scope.claim("REG"), "REG");
+
+        let mut scope = Namespace::new(&RUST);
+        assert_eq!(scope.claim("_"), "__1");
+        assert_eq!(scope.claim(""), "x");
+    }
+
+    #[test]
+    fn test_names() {
+        // Two copies of the same block, one of which has a pin that's a keyword
+        let mut netlist = Netlist::new();
+        for _ in 0..2 {
+            let m = netlist.add_module("10 GbE".to_owned());
+            netlist.add_pin(
+                m,
+                "wire".to_owned(),
+                crate::ffi::PinKind::Wire,
+                crate::ffi::InterconnectDirection::Output,
+            );
+            netlist.add_pin(
+                m,
+                "wire ".to_owned(),
+                crate::ffi::PinKind::Wire,
+                crate::ffi::InterconnectDirection::Input,
+            );
+        }
+        let top = Subgraph::new("top".to_owned(), vec![], vec![], netlist).unwrap();
+        let project = Project::new("p".to_owned(), top);
+        let names = Names::new(&project, &VERILOG).unwrap();
+        let local = names.subgraph(project.top());
+        let modules = sorted_modules(project.top().netlist());
+        assert_eq!(names.module("10 GbE"), "_10_GbE");
+        assert_eq!(local.instance(modules[0].0), "_10_GbE_0");
+        assert_eq!(local.instance(modules[1].0), "_10_GbE_1");
+        let pins: Vec<_> = modules[0]
+            .1
+            .inputs()
+            .chain(modules[0].1.outputs())
+            .collect();
+        assert_eq!(local.formal(*pins[0]), "wire_");
+        assert_eq!(local.formal(*pins[1]), "wire_1");
+        assert_eq!(local.net(*pins[1]), "_10_GbE_0_wire");
+    }
+
+    #[test]
+    fn test_vhdl_identifiers() {
+        let mut scope = Namespace::new(&VHDL);
+        assert_eq!(scope.claim("10 GbE"), "x10_GbE");
+        assert_eq!(scope.claim("a  b_"), "a_b");
+        assert_eq!(scope.claim("A B"), "A_B_1");
+        assert_eq!(scope.claim("Signal"), "Signal_1");
+    }
+}
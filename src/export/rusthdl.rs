@@ -0,0 +1,181 @@
+//! RustHDL backend, so designs can be simulated from plain Rust tests
+//! Every subgraph becomes a `LogicBlock` struct with a signal per port and a field per block,
+//! wired together in its `update`. Library blocks are expected to be structs of the same name, with a
+//! `new` taking their parameters in name order if they have any, and a field per pin.
+
+use super::verilog::parameter_value;
+use super::{port_kind, sorted_modules, Names, SubgraphNames, RUST};
+use crate::ffi::PinKind;
+use crate::netlist::{Module, PinIndex};
+use crate::project::{Project, Subgraph};
+use std::fmt::Write;
+
+/// The bits used to carry a pin kind
+fn bits(kind: PinKind) -> &'static str {
+    match kind {
+        PinKind::Wire => "Bits<1>",
+        // Integers are two's complement
+        PinKind::Integer => "Bits<32>",
+        // Reals travel as their IEEE-754 bits
+        PinKind::Real => "Bits<64>",
+        _ => unreachable!(),
+    }
+}
+
+/// The expression reading the value driven by the output pin `idx`, which is either a port or a block's pin
+fn source(sg: &Subgraph, local: &SubgraphNames, idx: PinIndex) -> String {
+    let netlist = sg.netlist();
+    let parent = netlist.get_pin(idx).expect("Drivers always exist").parent();
+    if sg.is_port(parent) {
+        format!("self.{}.val()", local.port(parent))
+    } else {
+        format!(
+            "self.{}.{}.val()",
+            local.instance(parent),
+            local.formal(idx)
+        )
+    }
+}
+
+fn write_struct(
+    out: &mut String,
+    project: &Project,
+    sg: &Subgraph,
+    names: &Names,
+) -> std::fmt::Result {
+    let netlist = sg.netlist();
+    let local = names.subgraph(sg);
+    let modules = sorted_modules(netlist);
+    let blocks: Vec<_> = modules.iter().filter(|(i, _)| !sg.is_port(*i)).collect();
+    // Blocks with parameters need constructing, which rules out deriving `Default`
+    let constructed =
+        |m: &Module| project.subgraph(m.name()).is_none() && !m.parameters().is_empty();
+    let derive_default = !blocks.iter().any(|(_, m)| constructed(m));
+
+    if derive_default {
+        writeln!(out, "#[derive(LogicBlock, Default)]")?;
+    } else {
+        writeln!(out, "#[derive(LogicBlock)]")?;
+    }
+    writeln!(out, "pub struct {} {{", local.module)?;
+    for (idx, direction) in sg
+        .inputs()
+        .map(|x| (x, "In"))
+        .chain(sg.outputs().map(|x| (x, "Out")))
+    {
+        writeln!(
+            out,
+            "    pub {}: Signal<{}, {}>,",
+            local.port(*idx),
+            direction,
+            bits(port_kind(netlist, *idx))
+        )?;
+    }
+    for (i, m) in &blocks {
+        writeln!(
+            out,
+            "    pub {}: {},",
+            local.instance(*i),
+            names.module(m.name())
+        )?;
+    }
+    writeln!(out, "}}")?;
+
+    if !derive_default {
+        writeln!(out)?;
+        writeln!(out, "impl Default for {} {{", local.module)?;
+        writeln!(out, "    fn default() -> Self {{")?;
+        writeln!(out, "        Self {{")?;
+        for idx in sg.inputs().chain(sg.outputs()) {
+            writeln!(out, "            {}: Default::default(),", local.port(*idx))?;
+        }
+        for (i, m) in &blocks {
+            let value = if constructed(m) {
+                let arguments: Vec<_> = m
+                    .parameters()
+                    .values()
+                    .map(|v| parameter_value(v))
+                    .collect();
+                format!("{}::new({})", names.module(m.name()), arguments.join(", "))
+            } else {
+                "Default::default()".to_owned()
+            };
+            writeln!(out, "            {}: {},", local.instance(*i), value)?;
+        }
+        writeln!(out, "        }}")?;
+        writeln!(out, "    }}")?;
+        writeln!(out, "}}")?;
+    }
+
+    writeln!(out)?;
+    writeln!(out, "impl Logic for {} {{", local.module)?;
+    writeln!(out, "    #[hdl_gen]")?;
+    writeln!(out, "    fn update(&mut self) {{")?;
+    for (i, m) in &blocks {
+        for pi in m.inputs() {
+            if let Some(d) = netlist.driver(*pi) {
+                writeln!(
+                    out,
+                    "        self.{}.{}.next = {};",
+                    local.instance(*i),
+                    local.formal(*pi),
+                    source(sg, local, d)
+                )?;
+            }
+        }
+    }
+    for idx in sg.outputs() {
+        let m = netlist.get_module(*idx).expect("Ports always exist");
+        let pi = m.inputs().next().expect("Output ports have a single input");
+        if let Some(d) = netlist.driver(*pi) {
+            writeln!(
+                out,
+                "        self.{}.next = {};",
+                local.port(*idx),
+                source(sg, local, d)
+            )?;
+        }
+    }
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")
+}
+
+/// Emit a RustHDL struct for every subgraph in the project's hierarchy, children first
+pub fn rusthdl(project: &Project) -> anyhow::Result<String> {
+    let names = Names::new(project, &RUST)?;
+    let mut out = format!(
+        "// Generated by CasperFlow from project `{}`\n",
+        project.name()
+    );
+    // Identifiers keep the names from the design, which rarely follow Rust's conventions
+    out.push_str("#![allow(non_camel_case_types, non_snake_case)]\n\n");
+    out.push_str("use rust_hdl::prelude::*;\n");
+    for sg in project.hierarchy_order()? {
+        out.push('\n');
+        write_struct(&mut out, project, sg, &names)?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rusthdl() {
+        let project = Project::from_json(include_str!("../../resources/project.json")).unwrap();
+        assert_eq!(
+            rusthdl(&project).unwrap(),
+            include_str!("../../resources/project.rs")
+        );
+    }
+
+    #[test]
+    fn test_parameters() {
+        let project = Project::from_json(include_str!("../../resources/stream.json")).unwrap();
+        let out = rusthdl(&project).unwrap();
+        assert!(out.contains("#[derive(LogicBlock)]\npub struct top {"));
+        assert!(out.contains("stream_source_0: stream_source::new(32),"));
+        assert!(out.contains("fifo_0: Default::default(),"));
+    }
+}
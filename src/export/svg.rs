@@ -0,0 +1,217 @@
+//! Self-contained SVG schematics, drawn from the positions modules were given in the editor
+//! Modules that haven't been placed are lined up in a grid below the rest, so nothing is drawn on top of each other.
+
+use super::sorted_modules;
+use crate::netlist::{Module, ModuleIndex, Netlist, PinIndex};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const WIDTH: f32 = 160.0;
+const TITLE: f32 = 24.0;
+const ROW: f32 = 18.0;
+const GAP: f32 = 60.0;
+const MARGIN: f32 = 20.0;
+// Unplaced modules per row of the fallback grid
+const COLUMNS: usize = 4;
+
+const STYLE: &str = "    .module rect { fill: #f8f8f8; stroke: #444; }
+    .module .title { font-weight: bold; text-anchor: middle; }
+    .module .output { text-anchor: end; }
+    .wire { fill: none; stroke-width: 1.5; }
+    .wire.kind-wire { stroke: #333; }
+    .wire.kind-integer { stroke: #1f77b4; }
+    .wire.kind-real { stroke: #d62728; }";
+
+/// Escape text for SVG content and attributes
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn height(m: &Module) -> f32 {
+    TITLE + m.inputs().len().max(m.outputs().len()).max(1) as f32 * ROW + 6.0
+}
+
+/// The top left corner of every module, placing any that haven't been placed
+fn positions(modules: &[(ModuleIndex, &Module)]) -> HashMap<ModuleIndex, (f32, f32)> {
+    let mut positions: HashMap<_, _> = modules
+        .iter()
+        .filter_map(|(i, m)| Some((*i, m.position()?)))
+        .collect();
+    let bottom = modules
+        .iter()
+        .filter_map(|(i, m)| positions.get(i).map(|(_, y)| y + height(m) + GAP))
+        .fold(0.0, f32::max);
+    let unplaced: Vec<_> = modules
+        .iter()
+        .filter(|(i, _)| !positions.contains_key(i))
+        .collect();
+    let row_height = unplaced.iter().map(|(_, m)| height(m)).fold(0.0, f32::max) + GAP;
+    for (n, (i, _)) in unplaced.into_iter().enumerate() {
+        let (column, row) = (n % COLUMNS, n / COLUMNS);
+        positions.insert(
+            *i,
+            (
+                column as f32 * (WIDTH + GAP),
+                bottom + row as f32 * row_height,
+            ),
+        );
+    }
+    positions
+}
+
+/// Where a pin meets the edge of its module
+fn anchor(
+    netlist: &Netlist,
+    positions: &HashMap<ModuleIndex, (f32, f32)>,
+    idx: PinIndex,
+) -> (f32, f32) {
+    let p = netlist.get_pin(idx).expect("Wired pins always exist");
+    let m = netlist
+        .get_module(p.parent())
+        .expect("Pins always have a module");
+    let (x, y) = positions[&p.parent()];
+    let (mut pins, x) = if p.is_input() {
+        (m.inputs(), x)
+    } else {
+        (m.outputs(), x + WIDTH)
+    };
+    let n = pins
+        .position(|x| *x == idx)
+        .expect("Modules list their pins");
+    (x, y + TITLE + (n as f32 + 0.5) * ROW)
+}
+
+/// Render a netlist as a standalone SVG document
+pub fn svg(netlist: &Netlist) -> String {
+    let modules = sorted_modules(netlist);
+    let positions = positions(&modules);
+    // The bounding box of every module, or just the margin for an empty netlist
+    let (mut min_x, mut min_y) = (f32::INFINITY, f32::INFINITY);
+    let (mut max_x, mut max_y) = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+    for (i, m) in &modules {
+        let (x, y) = positions[i];
+        min_x = min_x.min(x);
+        min_y = min_y.min(y);
+        max_x = max_x.max(x + WIDTH);
+        max_y = max_y.max(y + height(m));
+    }
+    if modules.is_empty() {
+        (min_x, min_y, max_x, max_y) = (0.0, 0.0, 0.0, 0.0);
+    }
+    let (min_x, min_y) = (min_x - MARGIN, min_y - MARGIN);
+    let (width, height_) = (max_x + MARGIN - min_x, max_y + MARGIN - min_y);
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\" width=\"{}\" height=\"{}\" \
+         font-family=\"Helvetica, Arial, sans-serif\" font-size=\"11\">",
+        min_x, min_y, width, height_, width, height_
+    )
+    .unwrap();
+    writeln!(out, "  <style>\n{}\n  </style>", STYLE).unwrap();
+
+    // Wires go underneath the modules
+    let mut wires: Vec<_> = netlist.wires().map(|(_, w)| *w).collect();
+    wires.sort_by_key(|(input, _)| {
+        let p = netlist.get_pin(*input).expect("Wired pins always exist");
+        (
+            netlist.get_module(p.parent()).map(|m| m.id()),
+            p.name().to_owned(),
+        )
+    });
+    for (input, output) in wires {
+        let (x1, y1) = anchor(netlist, &positions, output);
+        let (x2, y2) = anchor(netlist, &positions, input);
+        let dx = ((x2 - x1).abs() / 2.0).max(40.0);
+        let kind = netlist
+            .get_pin(output)
+            .expect("Wired pins always exist")
+            .kind();
+        writeln!(
+            out,
+            "  <path class=\"wire kind-{}\" d=\"M {} {} C {} {}, {} {}, {} {}\"/>",
+            kind,
+            x1,
+            y1,
+            x1 + dx,
+            y1,
+            x2 - dx,
+            y2,
+            x2,
+            y2
+        )
+        .unwrap();
+    }
+
+    for (i, m) in &modules {
+        let (x, y) = positions[i];
+        writeln!(out, "  <g class=\"module\" id=\"module-{}\">", m.id()).unwrap();
+        writeln!(
+            out,
+            "    <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"4\"/>",
+            x,
+            y,
+            WIDTH,
+            height(m)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "    <text class=\"title\" x=\"{}\" y=\"{}\">{} ({})</text>",
+            x + WIDTH / 2.0,
+            y + 16.0,
+            escape(m.instance()),
+            escape(m.name())
+        )
+        .unwrap();
+        for (class, pins, text_x) in [
+            ("input", m.inputs(), x + 6.0),
+            ("output", m.outputs(), x + WIDTH - 6.0),
+        ] {
+            for (n, pi) in pins.enumerate() {
+                let p = netlist.get_pin(*pi).expect("Module pins always exist");
+                writeln!(
+                    out,
+                    "    <text class=\"{}\" x=\"{}\" y=\"{}\">{}</text>",
+                    class,
+                    text_x,
+                    y + TITLE + (n as f32 + 0.5) * ROW + 4.0,
+                    escape(p.name())
+                )
+                .unwrap();
+            }
+        }
+        writeln!(out, "  </g>").unwrap();
+    }
+    writeln!(out, "</svg>").unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_svg() {
+        let netlist = Netlist::from_json(include_str!("../../resources/netlist.json")).unwrap();
+        assert_eq!(svg(&netlist), include_str!("../../resources/netlist.svg"));
+    }
+
+    #[test]
+    fn test_positions() {
+        let mut netlist = Netlist::from_json(include_str!("../../resources/netlist.json")).unwrap();
+        let source = netlist.find_module(0).unwrap();
+        netlist.set_position(source, Some((100.0, -50.0)));
+        let modules = sorted_modules(&netlist);
+        let positions = positions(&modules);
+        assert_eq!(positions[&source], (100.0, -50.0));
+        // The unplaced sink goes below the source
+        let sink = netlist.find_module(1).unwrap();
+        assert_eq!(positions[&sink], (0.0, -50.0 + 48.0 + GAP));
+        assert!(svg(&netlist).contains("<rect x=\"100\" y=\"-50\""));
+    }
+}
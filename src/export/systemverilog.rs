@@ -0,0 +1,485 @@
+//! Structural SystemVerilog backend
+//! Like the Verilog backend, except the pin bundles of library blocks become interface ports.
+//! Two bundles wired together role for role share a single interface instance, any other bundle gets its own
+//! and has its inputs assigned member by member.
+
+use super::verilog::parameter_value;
+use super::{port_kind, sorted_modules, Names, Namespace, SubgraphNames, SYSTEMVERILOG};
+use crate::ffi::{InterconnectDirection, PinKind};
+use crate::library::Bundle;
+use crate::netlist::{Module, ModuleIndex, Netlist, PinIndex};
+use crate::project::{Project, Subgraph};
+use anyhow::{anyhow, bail};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// The variable type used to carry a pin kind
+fn logic_type(kind: PinKind) -> &'static str {
+    match kind {
+        PinKind::Wire => "logic",
+        PinKind::Integer => "logic signed [31:0]",
+        // Reals travel as their IEEE-754 bits, as with $realtobits
+        PinKind::Real => "logic [63:0]",
+        _ => unreachable!(),
+    }
+}
+
+fn flip(direction: InterconnectDirection) -> InterconnectDirection {
+    match direction {
+        InterconnectDirection::Input => InterconnectDirection::Output,
+        _ => InterconnectDirection::Input,
+    }
+}
+
+/// An interface type, as seen from its `source` side, which is whichever side outputs the first role
+struct Interface {
+    name: String,
+    roles: Vec<String>,
+    kinds: Vec<PinKind>,
+    directions: Vec<InterconnectDirection>,
+    /// The identifiers of the roles within the interface
+    members: Vec<String>,
+}
+
+impl Interface {
+    /// Work out which way round a bundle with pins `directions` is
+    fn is_source(&self, directions: &[InterconnectDirection]) -> Option<bool> {
+        if directions == self.directions {
+            Some(true)
+        } else if directions
+            .iter()
+            .zip(&self.directions)
+            .all(|(a, b)| *a == flip(*b))
+        {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+/// A bundle of one block, with its pins in role order
+struct BundlePins {
+    module: ModuleIndex,
+    index: usize,
+    interface: String,
+    pins: Vec<PinIndex>,
+    source: bool,
+}
+
+/// Find the pins of a bundle, checking they exist
+fn bundle_pins(netlist: &Netlist, idx: ModuleIndex, b: &Bundle) -> anyhow::Result<Vec<PinIndex>> {
+    let m = netlist.get_module(idx).expect("Blocks always exist");
+    b.signals
+        .iter()
+        .map(|s| {
+            netlist
+                .find_pin(idx, &s.pin, InterconnectDirection::Input)
+                .or_else(|| netlist.find_pin(idx, &s.pin, InterconnectDirection::Output))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Bundle `{}` of `{}` has no pin `{}`",
+                        b.name,
+                        m.instance(),
+                        s.pin
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Collect the interface types of every bundle in the project, making sure bundles of the same type agree
+fn interfaces(project: &Project) -> anyhow::Result<Vec<Interface>> {
+    let mut interfaces: Vec<Interface> = vec![];
+    for sg in project.hierarchy_order()? {
+        let netlist = sg.netlist();
+        for (i, m) in sorted_modules(netlist) {
+            if project.subgraph(m.name()).is_some() {
+                continue;
+            }
+            for b in m.bundles() {
+                let pins = bundle_pins(netlist, i, b)?;
+                let pins: Vec<_> = pins
+                    .iter()
+                    .map(|pi| netlist.get_pin(*pi).expect("Module pins always exist"))
+                    .collect();
+                let roles: Vec<_> = b.signals.iter().map(|s| s.role.clone()).collect();
+                let kinds: Vec<_> = pins.iter().map(|p| p.kind()).collect();
+                let directions: Vec<_> = pins.iter().map(|p| p.direction()).collect();
+                match interfaces.iter().find(|x| x.name == b.interface) {
+                    Some(x) => {
+                        if x.roles != roles
+                            || x.kinds != kinds
+                            || x.is_source(&directions).is_none()
+                        {
+                            bail!(
+                                "Bundle `{}` of `{}` doesn't match other `{}` bundles",
+                                b.name,
+                                m.instance(),
+                                b.interface
+                            );
+                        }
+                    }
+                    None => {
+                        // The modports share the interface's scope with its members
+                        let mut scope = Namespace::new(&SYSTEMVERILOG);
+                        scope.claim("source");
+                        scope.claim("sink");
+                        let directions =
+                            if directions.first() == Some(&InterconnectDirection::Input) {
+                                directions.into_iter().map(flip).collect()
+                            } else {
+                                directions
+                            };
+                        interfaces.push(Interface {
+                            name: b.interface.clone(),
+                            members: roles.iter().map(|r| scope.claim(r)).collect(),
+                            roles,
+                            kinds,
+                            directions,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(interfaces)
+}
+
+fn write_interface(out: &mut String, x: &Interface, names: &Names) -> std::fmt::Result {
+    writeln!(out, "interface {};", names.interface(&x.name))?;
+    for (member, kind) in x.members.iter().zip(&x.kinds) {
+        writeln!(out, "    {} {};", logic_type(*kind), member)?;
+    }
+    let modport = |flipped: bool| {
+        x.members
+            .iter()
+            .zip(&x.directions)
+            .map(|(member, direction)| {
+                let direction = if flipped {
+                    flip(*direction)
+                } else {
+                    *direction
+                };
+                match direction {
+                    InterconnectDirection::Input => format!("input {}", member),
+                    _ => format!("output {}", member),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    if !x.members.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "    modport source ({});", modport(false))?;
+        writeln!(out, "    modport sink ({});", modport(true))?;
+    }
+    writeln!(out, "endinterface")
+}
+
+/// The bundles of the library blocks in a subgraph, and which interface instance carries each of them
+struct Bundles<'a> {
+    bundles: Vec<BundlePins>,
+    // Bundle position to the bundle whose interface instance it uses
+    carriers: HashMap<(ModuleIndex, usize), (ModuleIndex, usize)>,
+    // Bundled pins to their bundle and role
+    roles: HashMap<PinIndex, (usize, usize)>,
+    interfaces: &'a [Interface],
+}
+
+impl<'a> Bundles<'a> {
+    fn new(
+        project: &Project,
+        sg: &Subgraph,
+        blocks: &[&(ModuleIndex, &Module)],
+        interfaces: &'a [Interface],
+    ) -> anyhow::Result<Self> {
+        let netlist = sg.netlist();
+        let mut bundles = vec![];
+        let mut roles = HashMap::new();
+        for (i, m) in blocks {
+            if project.subgraph(m.name()).is_some() {
+                continue;
+            }
+            for (n, b) in m.bundles().iter().enumerate() {
+                let pins = bundle_pins(netlist, *i, b)?;
+                let directions: Vec<_> = pins
+                    .iter()
+                    .map(|pi| {
+                        netlist
+                            .get_pin(*pi)
+                            .expect("Module pins always exist")
+                            .direction()
+                    })
+                    .collect();
+                let x = interfaces
+                    .iter()
+                    .find(|x| x.name == b.interface)
+                    .expect("Every interface was collected");
+                for (r, pi) in pins.iter().enumerate() {
+                    if roles.insert(*pi, (bundles.len(), r)).is_some() {
+                        bail!(
+                            "Pin `{}` of `{}` is in two bundles",
+                            b.signals[r].pin,
+                            m.instance()
+                        );
+                    }
+                }
+                bundles.push(BundlePins {
+                    module: *i,
+                    index: n,
+                    interface: b.interface.clone(),
+                    pins,
+                    source: x.is_source(&directions).expect("Checked when collecting"),
+                });
+            }
+        }
+        let mut this = Self {
+            bundles,
+            carriers: HashMap::new(),
+            roles,
+            interfaces,
+        };
+        // Sinks wired role for role to a source share its interface instance
+        for sink in this.bundles.iter().filter(|b| !b.source) {
+            let source = this.bundles.iter().find(|source| {
+                source.source
+                    && source.interface == sink.interface
+                    && source.pins.iter().zip(&sink.pins).all(|(s, k)| {
+                        netlist.driver(*k) == Some(*s) || netlist.driver(*s) == Some(*k)
+                    })
+            });
+            let carrier = source.map_or((sink.module, sink.index), |s| (s.module, s.index));
+            this.carriers.insert((sink.module, sink.index), carrier);
+        }
+        for source in this.bundles.iter().filter(|b| b.source) {
+            this.carriers
+                .insert((source.module, source.index), (source.module, source.index));
+        }
+        Ok(this)
+    }
+
+    fn interface(&self, b: &BundlePins) -> &Interface {
+        self.interfaces
+            .iter()
+            .find(|x| x.name == b.interface)
+            .expect("Every interface was collected")
+    }
+
+    /// The interface instance carrying a bundle
+    fn carrier<'n>(&self, local: &'n SubgraphNames, b: &BundlePins) -> &'n str {
+        let (i, n) = self.carriers[&(b.module, b.index)];
+        local.bundle_instance(i, n)
+    }
+
+    /// Whether a bundle has its own interface instance
+    fn owns_carrier(&self, b: &BundlePins) -> bool {
+        self.carriers[&(b.module, b.index)] == (b.module, b.index)
+    }
+
+    /// The expression for the value of a pin, which is a member of an interface instance for bundled pins
+    fn net(&self, local: &SubgraphNames, pi: PinIndex) -> String {
+        match self.roles.get(&pi) {
+            Some((b, r)) => {
+                let b = &self.bundles[*b];
+                format!(
+                    "{}.{}",
+                    self.carrier(local, b),
+                    self.interface(b).members[*r]
+                )
+            }
+            None => local.net(pi).to_owned(),
+        }
+    }
+}
+
+fn write_module(
+    out: &mut String,
+    project: &Project,
+    sg: &Subgraph,
+    names: &Names,
+    interfaces: &[Interface],
+) -> anyhow::Result<()> {
+    let netlist = sg.netlist();
+    let local = names.subgraph(sg);
+    let ports: Vec<_> = sg
+        .inputs()
+        .map(|x| {
+            format!(
+                "input {} {}",
+                logic_type(port_kind(netlist, *x)),
+                local.port(*x)
+            )
+        })
+        .chain(sg.outputs().map(|x| {
+            format!(
+                "output {} {}",
+                logic_type(port_kind(netlist, *x)),
+                local.port(*x)
+            )
+        }))
+        .collect();
+    if ports.is_empty() {
+        writeln!(out, "module {} ();", local.module)?;
+    } else {
+        writeln!(out, "module {} (", local.module)?;
+        writeln!(out, "    {}", ports.join(",\n    "))?;
+        writeln!(out, ");")?;
+    }
+
+    let modules = sorted_modules(netlist);
+    let blocks: Vec<_> = modules.iter().filter(|(i, _)| !sg.is_port(*i)).collect();
+    let bundles = Bundles::new(project, sg, &blocks, interfaces)?;
+
+    // Declare the interface instances, then the nets of the pins outside bundles
+    let mut nets = vec![];
+    for b in bundles.bundles.iter().filter(|b| bundles.owns_carrier(b)) {
+        nets.push(format!(
+            "    {} {} ();",
+            names.interface(&b.interface),
+            local.bundle_instance(b.module, b.index)
+        ));
+    }
+    for (_, m) in &blocks {
+        for pi in m.outputs().filter(|x| !bundles.roles.contains_key(x)) {
+            let kind = netlist
+                .get_pin(*pi)
+                .expect("Module pins always exist")
+                .kind();
+            nets.push(format!("    {} {};", logic_type(kind), local.net(*pi)));
+        }
+    }
+    if !nets.is_empty() {
+        writeln!(out, "\n{}", nets.join("\n"))?;
+    }
+
+    // Instantiate the blocks
+    for (i, m) in &blocks {
+        let mut connections = vec![];
+        for pi in m.inputs().filter(|x| !bundles.roles.contains_key(x)) {
+            let net = netlist
+                .driver(*pi)
+                .map(|d| bundles.net(local, d))
+                .unwrap_or_default();
+            connections.push(format!("        .{}({})", local.formal(*pi), net));
+        }
+        for pi in m.outputs().filter(|x| !bundles.roles.contains_key(x)) {
+            connections.push(format!(
+                "        .{}({})",
+                local.formal(*pi),
+                local.net(*pi)
+            ));
+        }
+        for b in bundles.bundles.iter().filter(|b| b.module == *i) {
+            connections.push(format!(
+                "        .{}({})",
+                local.bundle_port(*i, b.index),
+                bundles.carrier(local, b)
+            ));
+        }
+        let mut scope = Namespace::new(&SYSTEMVERILOG);
+        let parameters: Vec<_> = m
+            .parameters()
+            .iter()
+            .map(|(k, v)| format!(".{}({})", scope.claim(k), parameter_value(v)))
+            .collect();
+        writeln!(out)?;
+        if parameters.is_empty() {
+            writeln!(
+                out,
+                "    {} {} (",
+                names.module(m.name()),
+                local.instance(*i)
+            )?;
+        } else {
+            writeln!(
+                out,
+                "    {} #({}) {} (",
+                names.module(m.name()),
+                parameters.join(", "),
+                local.instance(*i)
+            )?;
+        }
+        writeln!(out, "{}", connections.join(",\n"))?;
+        writeln!(out, "    );")?;
+    }
+
+    // Drive the inputs of bundles that don't share an instance with their driver, then the output ports
+    let mut assigns = vec![];
+    for b in bundles.bundles.iter().filter(|b| bundles.owns_carrier(b)) {
+        for pi in &b.pins {
+            if let Some(d) = netlist.driver(*pi) {
+                let (net, driver) = (bundles.net(local, *pi), bundles.net(local, d));
+                if net != driver {
+                    assigns.push(format!("    assign {} = {};", net, driver));
+                }
+            }
+        }
+    }
+    for idx in sg.outputs() {
+        let m = netlist.get_module(*idx).expect("Ports always exist");
+        let pi = m.inputs().next().expect("Output ports have a single input");
+        if let Some(d) = netlist.driver(*pi) {
+            assigns.push(format!(
+                "    assign {} = {};",
+                local.port(*idx),
+                bundles.net(local, d)
+            ));
+        }
+    }
+    if !assigns.is_empty() {
+        writeln!(out, "\n{}", assigns.join("\n"))?;
+    }
+    writeln!(out, "\nendmodule")?;
+    Ok(())
+}
+
+/// Emit an interface for every kind of pin bundle and the generated blocks kept with the project, then a module
+/// for every subgraph in the project's hierarchy, children first
+pub fn systemverilog(project: &Project) -> anyhow::Result<String> {
+    let names = Names::new(project, &SYSTEMVERILOG)?;
+    let interfaces = interfaces(project)?;
+    let mut out = format!(
+        "// Generated by CasperFlow from project `{}`\n",
+        project.name()
+    );
+    for x in &interfaces {
+        out.push('\n');
+        write_interface(&mut out, x, &names)?;
+    }
+    // Generated blocks are Verilog-2001, which is also SystemVerilog
+    for source in project.sources().values() {
+        out.push('\n');
+        out.push_str(source);
+    }
+    for sg in project.hierarchy_order()? {
+        out.push('\n');
+        write_module(&mut out, project, sg, &names, &interfaces)?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_systemverilog() {
+        let project = Project::from_json(include_str!("../../resources/stream.json")).unwrap();
+        assert_eq!(
+            systemverilog(&project).unwrap(),
+            include_str!("../../resources/stream.sv")
+        );
+    }
+
+    #[test]
+    fn test_mismatched_bundles() {
+        let mut project = Project::from_json(include_str!("../../resources/stream.json")).unwrap();
+        let mut saved = project.to_saved();
+        saved.subgraphs[0].netlist.modules[1].bundles[0]
+            .signals
+            .pop();
+        project = Project::from_saved(&saved).unwrap();
+        assert!(systemverilog(&project).is_err());
+    }
+}
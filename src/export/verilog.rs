@@ -0,0 +1,170 @@
+//! Structural Verilog-2001 backend
+//! Every subgraph becomes a module instantiating its blocks, with a net per output pin
+
+use super::{port_kind, sorted_modules, Names, Namespace, VERILOG};
+use crate::ffi::PinKind;
+use crate::project::{Project, Subgraph};
+use std::fmt::Write;
+
+/// The net type used to carry a pin kind
+fn net_type(kind: PinKind) -> &'static str {
+    match kind {
+        PinKind::Wire => "wire",
+        PinKind::Integer => "wire signed [31:0]",
+        // Reals travel as their IEEE-754 bits, as with $realtobits
+        PinKind::Real => "wire [63:0]",
+        _ => unreachable!(),
+    }
+}
+
+/// Format a parameter value, leaving integers bare and quoting everything else
+pub(super) fn parameter_value(value: &str) -> String {
+    if value.parse::<i64>().is_ok() {
+        value.to_owned()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+fn write_module(out: &mut String, sg: &Subgraph, names: &Names) -> std::fmt::Result {
+    let netlist = sg.netlist();
+    let local = names.subgraph(sg);
+    let ports: Vec<_> = sg
+        .inputs()
+        .map(|x| {
+            format!(
+                "input {} {}",
+                net_type(port_kind(netlist, *x)),
+                local.port(*x)
+            )
+        })
+        .chain(sg.outputs().map(|x| {
+            format!(
+                "output {} {}",
+                net_type(port_kind(netlist, *x)),
+                local.port(*x)
+            )
+        }))
+        .collect();
+    if ports.is_empty() {
+        writeln!(out, "module {} ();", local.module)?;
+    } else {
+        writeln!(out, "module {} (", local.module)?;
+        writeln!(out, "    {}", ports.join(",\n    "))?;
+        writeln!(out, ");")?;
+    }
+
+    let modules = sorted_modules(netlist);
+    let blocks: Vec<_> = modules.iter().filter(|(i, _)| !sg.is_port(*i)).collect();
+
+    // Declare the nets
+    let mut nets = vec![];
+    for (_, m) in &blocks {
+        for pi in m.outputs() {
+            let kind = netlist
+                .get_pin(*pi)
+                .expect("Module pins always exist")
+                .kind();
+            nets.push(format!("    {} {};", net_type(kind), local.net(*pi)));
+        }
+    }
+    if !nets.is_empty() {
+        writeln!(out, "\n{}", nets.join("\n"))?;
+    }
+
+    // Instantiate the blocks
+    for (i, m) in &blocks {
+        let mut connections = vec![];
+        for pi in m.inputs() {
+            let net = netlist
+                .driver(*pi)
+                .map(|d| local.net(d))
+                .unwrap_or_default();
+            connections.push(format!("        .{}({})", local.formal(*pi), net));
+        }
+        for pi in m.outputs() {
+            connections.push(format!(
+                "        .{}({})",
+                local.formal(*pi),
+                local.net(*pi)
+            ));
+        }
+        let mut scope = Namespace::new(&VERILOG);
+        let parameters: Vec<_> = m
+            .parameters()
+            .iter()
+            .map(|(k, v)| format!(".{}({})", scope.claim(k), parameter_value(v)))
+            .collect();
+        writeln!(out)?;
+        if parameters.is_empty() {
+            writeln!(
+                out,
+                "    {} {} (",
+                names.module(m.name()),
+                local.instance(*i)
+            )?;
+        } else {
+            writeln!(
+                out,
+                "    {} #({}) {} (",
+                names.module(m.name()),
+                parameters.join(", "),
+                local.instance(*i)
+            )?;
+        }
+        writeln!(out, "{}", connections.join(",\n"))?;
+        writeln!(out, "    );")?;
+    }
+
+    // Drive the output ports
+    let mut assigns = vec![];
+    for idx in sg.outputs() {
+        let m = netlist.get_module(*idx).expect("Ports always exist");
+        let pi = m.inputs().next().expect("Output ports have a single input");
+        if let Some(d) = netlist.driver(*pi) {
+            assigns.push(format!(
+                "    assign {} = {};",
+                local.port(*idx),
+                local.net(d)
+            ));
+        }
+    }
+    if !assigns.is_empty() {
+        writeln!(out, "\n{}", assigns.join("\n"))?;
+    }
+    writeln!(out, "\nendmodule")
+}
+
+/// Emit the generated blocks kept with the project, then a Verilog module for every subgraph in the project's
+/// hierarchy, children first
+pub fn verilog(project: &Project) -> anyhow::Result<String> {
+    let names = Names::new(project, &VERILOG)?;
+    let mut out = format!(
+        "// Generated by CasperFlow from project `{}`\n",
+        project.name()
+    );
+    // Generated blocks only instantiate their own helpers, so they can go first
+    for source in project.sources().values() {
+        out.push('\n');
+        out.push_str(source);
+    }
+    for sg in project.hierarchy_order()? {
+        out.push('\n');
+        write_module(&mut out, sg, &names)?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verilog() {
+        let project = Project::from_json(include_str!("../../resources/project.json")).unwrap();
+        assert_eq!(
+            verilog(&project).unwrap(),
+            include_str!("../../resources/project.v")
+        );
+    }
+}
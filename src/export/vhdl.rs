@@ -0,0 +1,329 @@
+//! Structural VHDL-2008 backend
+//! Every subgraph becomes an entity and a structural architecture, with a signal per output pin.
+//! Library blocks are instantiated through a component declaration, subgraphs directly as `entity work.<name>`.
+
+use super::{port_kind, sorted_modules, Names, Namespace, SubgraphNames, VHDL};
+use crate::ffi::{InterconnectDirection, PinKind};
+use crate::netlist::{Module, Netlist, PinIndex};
+use crate::project::{Project, Subgraph};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// The signal type used to carry a pin kind
+fn signal_type(kind: PinKind) -> &'static str {
+    match kind {
+        PinKind::Wire => "std_logic",
+        PinKind::Integer => "signed(31 downto 0)",
+        // Reals carry the bits of a double, as in the other backends
+        PinKind::Real => "std_logic_vector(63 downto 0)",
+        _ => unreachable!(),
+    }
+}
+
+/// Format a parameter value, leaving integers bare and quoting everything else
+fn generic_value(value: &str) -> String {
+    if value.parse::<i64>().is_ok() {
+        value.to_owned()
+    } else {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    }
+}
+
+fn generic_type(value: &str) -> &'static str {
+    if value.parse::<i64>().is_ok() {
+        "integer"
+    } else {
+        "string"
+    }
+}
+
+fn port_mode(direction: InterconnectDirection) -> &'static str {
+    match direction {
+        InterconnectDirection::Input => "in",
+        InterconnectDirection::Output => "out",
+        _ => unreachable!(),
+    }
+}
+
+/// The generics of a library block, with their identifiers, collected from every instance in a subgraph
+struct Generics<'a> {
+    // Parameter name to its identifier and an example value, which picks the type and is the default for instances
+    // that don't set it
+    generics: BTreeMap<&'a str, (String, &'a str)>,
+}
+
+impl<'a> Generics<'a> {
+    fn new(instances: &[&'a Module]) -> Self {
+        let mut values = BTreeMap::new();
+        for m in instances {
+            for (k, v) in m.parameters() {
+                values.entry(k.as_str()).or_insert(v.as_str());
+            }
+        }
+        let mut scope = Namespace::new(&VHDL);
+        Self {
+            generics: values
+                .into_iter()
+                .map(|(k, v)| (k, (scope.claim(k), v)))
+                .collect(),
+        }
+    }
+}
+
+/// Write a `port (...)` or `port map (...)` list at the given indentation
+fn write_list(out: &mut String, indent: &str, header: &str, items: &[String]) -> std::fmt::Result {
+    if items.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "{}{} (", indent, header)?;
+    writeln!(
+        out,
+        "{}    {}",
+        indent,
+        items.join(&format!(";\n{}    ", indent))
+    )?;
+    writeln!(out, "{});", indent)
+}
+
+/// Declare the component of a library block, taking its pins from its first instance
+fn write_component(
+    out: &mut String,
+    netlist: &Netlist,
+    local: &SubgraphNames,
+    name: &str,
+    m: &Module,
+    generics: &Generics,
+) -> std::fmt::Result {
+    writeln!(out, "    component {} is", name)?;
+    let generics: Vec<_> = generics
+        .generics
+        .values()
+        .map(|(ident, value)| {
+            format!(
+                "{} : {} := {}",
+                ident,
+                generic_type(value),
+                generic_value(value)
+            )
+        })
+        .collect();
+    write_list(out, "        ", "generic", &generics)?;
+    let ports: Vec<_> = m
+        .inputs()
+        .chain(m.outputs())
+        .map(|pi| {
+            let p = netlist.get_pin(*pi).expect("Module pins always exist");
+            format!(
+                "{} : {} {}",
+                local.formal(*pi),
+                port_mode(p.direction()),
+                signal_type(p.kind())
+            )
+        })
+        .collect();
+    write_list(out, "        ", "port", &ports)?;
+    writeln!(out, "    end component;")
+}
+
+fn write_entity(
+    out: &mut String,
+    project: &Project,
+    sg: &Subgraph,
+    names: &Names,
+) -> std::fmt::Result {
+    let netlist = sg.netlist();
+    let local = names.subgraph(sg);
+    writeln!(out, "library ieee;")?;
+    writeln!(out, "use ieee.std_logic_1164.all;")?;
+    writeln!(out, "use ieee.numeric_std.all;")?;
+    writeln!(out)?;
+    writeln!(out, "entity {} is", local.module)?;
+    let ports: Vec<_> = sg
+        .inputs()
+        .map(|x| (x, "in"))
+        .chain(sg.outputs().map(|x| (x, "out")))
+        .map(|(x, mode)| {
+            format!(
+                "{} : {} {}",
+                local.port(*x),
+                mode,
+                signal_type(port_kind(netlist, *x))
+            )
+        })
+        .collect();
+    write_list(out, "    ", "port", &ports)?;
+    writeln!(out, "end entity {};", local.module)?;
+    writeln!(out)?;
+
+    let modules = sorted_modules(netlist);
+    let blocks: Vec<_> = modules.iter().filter(|(i, _)| !sg.is_port(*i)).collect();
+
+    writeln!(out, "architecture structural of {} is", local.module)?;
+    // One component per library block, in order of first use
+    let mut components: Vec<(&str, Vec<&Module>)> = vec![];
+    for (_, m) in &blocks {
+        if project.subgraph(m.name()).is_some() {
+            continue;
+        }
+        match components.iter_mut().find(|(name, _)| *name == m.name()) {
+            Some((_, instances)) => instances.push(m),
+            None => components.push((m.name(), vec![m])),
+        }
+    }
+    let generics: BTreeMap<_, _> = components
+        .iter()
+        .map(|(name, instances)| (*name, Generics::new(instances)))
+        .collect();
+    for (name, instances) in &components {
+        write_component(
+            out,
+            netlist,
+            local,
+            names.module(name),
+            instances[0],
+            &generics[name],
+        )?;
+    }
+    // A signal per block output
+    for (_, m) in &blocks {
+        for pi in m.outputs() {
+            let kind = netlist
+                .get_pin(*pi)
+                .expect("Module pins always exist")
+                .kind();
+            writeln!(
+                out,
+                "    signal {} : {};",
+                local.net(*pi),
+                signal_type(kind)
+            )?;
+        }
+    }
+    writeln!(out, "begin")?;
+
+    // Instantiate the blocks
+    for (i, m) in &blocks {
+        let mut lines = vec![];
+        match project.subgraph(m.name()) {
+            Some(_) => lines.push(format!(
+                "    {} : entity work.{}",
+                local.instance(*i),
+                names.module(m.name())
+            )),
+            None => {
+                lines.push(format!(
+                    "    {} : {}",
+                    local.instance(*i),
+                    names.module(m.name())
+                ));
+                let generics: Vec<_> = m
+                    .parameters()
+                    .iter()
+                    .map(|(k, v)| {
+                        let (ident, _) = &generics[m.name()].generics[k.as_str()];
+                        format!("{} => {}", ident, generic_value(v))
+                    })
+                    .collect();
+                if !generics.is_empty() {
+                    lines.push(format!("        generic map ({})", generics.join(", ")));
+                }
+            }
+        }
+        let connection = |pi: &PinIndex| {
+            let actual = if netlist.get_pin(*pi).is_some_and(|p| p.is_input()) {
+                netlist.driver(*pi).map(|d| local.net(d)).unwrap_or("open")
+            } else {
+                local.net(*pi)
+            };
+            format!("{} => {}", local.formal(*pi), actual)
+        };
+        let connections: Vec<_> = m.inputs().chain(m.outputs()).map(connection).collect();
+        if !connections.is_empty() {
+            lines.push(format!(
+                "        port map (\n            {}\n        )",
+                connections.join(",\n            ")
+            ));
+        }
+        writeln!(out, "\n{};", lines.join("\n"))?;
+    }
+
+    // Drive the output ports
+    let mut assigns = vec![];
+    for idx in sg.outputs() {
+        let m = netlist.get_module(*idx).expect("Ports always exist");
+        let pi = m.inputs().next().expect("Output ports have a single input");
+        if let Some(d) = netlist.driver(*pi) {
+            assigns.push(format!("    {} <= {};", local.port(*idx), local.net(d)));
+        }
+    }
+    if !assigns.is_empty() {
+        writeln!(out, "\n{}", assigns.join("\n"))?;
+    }
+    writeln!(out, "\nend architecture structural;")
+}
+
+/// Emit an entity and architecture for every subgraph in the project's hierarchy, children first
+pub fn vhdl(project: &Project) -> anyhow::Result<String> {
+    let names = Names::new(project, &VHDL)?;
+    let mut out = format!(
+        "-- Generated by CasperFlow from project `{}`\n",
+        project.name()
+    );
+    for sg in project.hierarchy_order()? {
+        out.push('\n');
+        write_entity(&mut out, project, sg, &names)?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vhdl() {
+        let project = Project::from_json(include_str!("../../resources/project.json")).unwrap();
+        assert_eq!(
+            vhdl(&project).unwrap(),
+            include_str!("../../resources/project.vhd")
+        );
+    }
+
+    #[test]
+    fn test_generic_defaults() {
+        // Only one of the two constants sets its width, so the component has to default it for the other
+        let mut netlist = Netlist::new();
+        for value in ["1", "2"] {
+            let m = netlist.add_module("Constant".to_owned());
+            netlist.set_parameter(m, "value".to_owned(), value.to_owned());
+            if value == "1" {
+                netlist.set_parameter(m, "width".to_owned(), "8".to_owned());
+            }
+            netlist.add_pin(
+                m,
+                "O".to_owned(),
+                PinKind::Integer,
+                InterconnectDirection::Output,
+            );
+        }
+        let top = Subgraph::new("top".to_owned(), vec![], vec![], netlist).unwrap();
+        let vhdl = vhdl(&Project::new("p".to_owned(), top)).unwrap();
+        assert!(
+            vhdl.contains("            value : integer := 1;\n            width : integer := 8\n"),
+            "{}",
+            vhdl
+        );
+        assert!(
+            vhdl.contains("        generic map (value => 2)\n"),
+            "{}",
+            vhdl
+        );
+    }
+
+    #[test]
+    fn test_signal_types() {
+        // Reals are as wide as the other backends declare them
+        assert_eq!(signal_type(PinKind::Real), "std_logic_vector(63 downto 0)");
+        assert_eq!(PinKind::Real.width(), 64);
+    }
+}
@@ -0,0 +1,86 @@
+//! Vivado XDC constraints backend
+//! Constraints live as parameters on the port modules of the top subgraph:
+//! - `package_pin`: the package pin, or a whitespace separated list of them for a bus
+//! - `iostandard`: the IO standard of every pin of the port
+//! - `period`: the period of the clock on this port, in ns
+
+use super::{Names, VERILOG};
+use crate::project::Project;
+use std::fmt::Write;
+
+/// Emit the constraints for the top-level ports of the project
+pub fn xdc(project: &Project) -> anyhow::Result<String> {
+    let top = project.top();
+    let netlist = top.netlist();
+    // The ports have to match up with the ones in the generated Verilog
+    let names = Names::new(project, &VERILOG)?;
+    let local = names.subgraph(top);
+    let mut out = format!(
+        "# Generated by CasperFlow from project `{}`\n",
+        project.name()
+    );
+    for idx in top.inputs().chain(top.outputs()) {
+        let m = netlist.get_module(*idx).expect("Ports always exist");
+        if m.parameters().is_empty() {
+            continue;
+        }
+        let port = local.port(*idx).to_owned();
+        writeln!(out, "\n# {}", m.name())?;
+        let pins: Vec<_> = m
+            .parameter("package_pin")
+            .map(|x| x.split_whitespace().collect())
+            .unwrap_or_default();
+        let ports: Vec<_> = if pins.len() > 1 {
+            (0..pins.len())
+                .map(|i| format!("{{{}[{}]}}", port, i))
+                .collect()
+        } else {
+            vec![port.clone()]
+        };
+        for (pin, port) in pins.iter().zip(&ports) {
+            writeln!(out, "set_property PACKAGE_PIN {} [get_ports {}]", pin, port)?;
+        }
+        if let Some(standard) = m.parameter("iostandard") {
+            for port in &ports {
+                writeln!(
+                    out,
+                    "set_property IOSTANDARD {} [get_ports {}]",
+                    standard, port
+                )?;
+            }
+        }
+        if let Some(period) = m.parameter("period") {
+            let period: f64 = period
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Bad clock period `{}` on `{}`", period, m.name()))?;
+            writeln!(
+                out,
+                "create_clock -period {:.3} -name {} [get_ports {}]",
+                period, port, port
+            )?;
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xdc() {
+        let project = Project::from_json(include_str!("../../resources/project.json")).unwrap();
+        assert_eq!(
+            xdc(&project).unwrap(),
+            "# Generated by CasperFlow from project `blinky`\n\
+             \n\
+             # x\n\
+             set_property PACKAGE_PIN E3 [get_ports x]\n\
+             set_property IOSTANDARD LVCMOS33 [get_ports x]\n\
+             \n\
+             # led\n\
+             set_property PACKAGE_PIN H5 [get_ports led]\n\
+             set_property IOSTANDARD LVCMOS33 [get_ports led]\n"
+        );
+    }
+}
@@ -0,0 +1,89 @@
+//! Delaying a signal by a number of clock cycles, as latency balancing puts in front of early inputs
+
+use super::{header, pin, Generated, Generator, Parameters};
+use crate::ffi::PinKind;
+use crate::library::{LibraryModule, ResourceModel};
+use anyhow::bail;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+pub(super) const DELAY: Generator = Generator {
+    name: "delay",
+    description: "Delay a `width` bit signal by `latency` clock cycles",
+    parameters: &[("width", "32"), ("latency", "1")],
+    build,
+};
+
+/// The kind of pin carrying `width` bits
+fn kind(width: u32) -> anyhow::Result<PinKind> {
+    Ok(match width {
+        1 => PinKind::Wire,
+        32 => PinKind::Integer,
+        64 => PinKind::Real,
+        _ => bail!(
+            "`width` of `delay` must be 1, 32 or 64 bits like a wire, integer or real pin, not `{}`",
+            width
+        ),
+    })
+}
+
+fn build(params: &Parameters) -> anyhow::Result<Generated> {
+    let kind = kind(params.integer("width", 1..=64)?)?;
+    let cycles = params.integer("latency", 1..=4096)? as u64;
+    let width = kind.width() as u64;
+    // Short delays are registers, and longer ones shift registers of 32 cycles a LUT ahead of an output register
+    let (luts, ffs) = if cycles > 2 {
+        (width * (cycles - 1).div_ceil(32), width)
+    } else {
+        (0, width * cycles)
+    };
+    let count = |x: u64| Some(x.to_string());
+    // There's no frame to mark, so unlike the other blocks this one has no sync
+    let module = LibraryModule {
+        name: params.module_name(),
+        inputs: vec![pin("clk", PinKind::Wire), pin("din", kind)],
+        outputs: vec![pin("dout", kind)],
+        parameters: BTreeMap::new(),
+        bundles: vec![],
+        resources: Some(ResourceModel {
+            luts: count(luts),
+            ffs: count(ffs),
+            dsp48s: None,
+            brams: None,
+        }),
+        latency: Some(cycles.to_string()),
+        ranges: BTreeMap::from([("dout".to_owned(), "inputs.din".to_owned())]),
+        script: None,
+        generator: None,
+    };
+
+    let mut out = String::new();
+    header(
+        &mut out,
+        &module,
+        &format!("Delay of {} bits by {} clock cycles", width, cycles),
+        "reg",
+    )?;
+    if cycles == 1 {
+        writeln!(out, "    always @(posedge clk) dout <= din;")?;
+    } else {
+        let decl = match kind {
+            PinKind::Integer => "signed [31:0] ",
+            PinKind::Real => "[63:0] ",
+            _ => "",
+        };
+        writeln!(out, "    reg {}stages [0:{}];", decl, cycles - 2)?;
+        writeln!(out, "    integer i;")?;
+        writeln!(out, "    always @(posedge clk) begin")?;
+        writeln!(out, "        stages[0] <= din;")?;
+        writeln!(out, "        for (i = 1; i < {}; i = i + 1)", cycles - 1)?;
+        writeln!(out, "            stages[i] <= stages[i - 1];")?;
+        writeln!(out, "        dout <= stages[{}];", cycles - 2)?;
+        writeln!(out, "    end")?;
+    }
+    writeln!(out, "endmodule")?;
+    Ok(Generated {
+        module,
+        verilog: out,
+    })
+}
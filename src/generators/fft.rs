@@ -0,0 +1,656 @@
+//! Streaming FFTs, built from radix-2 single-path delay feedback pipelines taking a complex sample per clock.
+//! Each stage's butterflies either halve their results or let them grow as the `shift` schedule says, wrapping
+//! beyond 32 bits. Twiddles are signed `coeff_width` bit integers, with 1 at a quarter of their range.
+//!
+//! A pipeline's results come out in bit-reversed order: the `c`th result after `sync_out` is bin `bitrev(c)`.
+
+use super::{
+    bit_reverse, block, delay, dsp48s, header, memory, pin, rom, Generated, Generator, Parameters,
+};
+use crate::ffi::PinKind;
+use crate::library::LibraryPin;
+use crate::resources::Resources;
+use anyhow::bail;
+use std::collections::BTreeMap;
+use std::f64::consts::{PI, SQRT_2};
+use std::fmt::Write;
+
+pub(super) const BIPLEX: Generator = Generator {
+    name: "fft_biplex",
+    description:
+        "FFTs of 2^fft_size points over two streams of complex samples, a sample of each per clock",
+    parameters: &[("fft_size", "10"), ("shift", "all"), ("coeff_width", "18")],
+    build: biplex,
+};
+
+pub(super) const WIDEBAND: Generator = Generator {
+    name: "fft_wideband",
+    description:
+        "An FFT of 2^fft_size points over 2^parallel consecutive complex samples per clock",
+    parameters: &[
+        ("fft_size", "10"),
+        ("parallel", "2"),
+        ("shift", "all"),
+        ("coeff_width", "18"),
+    ],
+    build: wideband,
+};
+
+/// The twiddle factor `e^(-2 pi i k / points)`
+fn twiddle(k: usize, points: usize) -> (f64, f64) {
+    let angle = -2.0 * PI * k as f64 / points as f64;
+    (angle.cos(), angle.sin())
+}
+
+/// A twiddle factor as a pair of signed `width` bit integers
+fn quantise((re, im): (f64, f64), width: u32) -> (i64, i64) {
+    let one = (1i64 << (width - 2)) as f64;
+    ((re * one).round() as i64, (im * one).round() as i64)
+}
+
+/// The part of a wide sum that's left in 32 bits after dropping the lowest `shift` bits
+fn scaled(name: &str, shift: u32) -> String {
+    format!("{}[{}:{}]", name, 31 + shift, shift)
+}
+
+/// The `(re, im)` input pins of every stream, named `prefix` and the stream number
+fn complex_pins(prefix: &str, streams: usize) -> Vec<LibraryPin> {
+    (0..streams)
+        .flat_map(|n| {
+            [
+                pin(&format!("{}{}_re", prefix, n), PinKind::Integer),
+                pin(&format!("{}{}_im", prefix, n), PinKind::Integer),
+            ]
+        })
+        .collect()
+}
+
+/// The range of every output, growing from the largest input by `gain`
+fn ranges(streams: usize, inputs: &[String], gain: f64) -> BTreeMap<String, String> {
+    complex_pins("out", streams)
+        .into_iter()
+        .map(|x| {
+            (
+                x.name,
+                format!("hull({}) * range(-{}, {})", inputs.join(", "), gain, gain),
+            )
+        })
+        .collect()
+}
+
+/// Write the module `name`, a pipeline of `2^bits` points, returning its cost and latency
+fn pipeline(
+    out: &mut String,
+    name: &str,
+    bits: u32,
+    shifts: &[bool],
+    width: u32,
+) -> anyhow::Result<(Resources, u64)> {
+    let points = 1usize << bits;
+    let mut resources = Resources::default();
+    let mut latency = 0;
+    writeln!(
+        out,
+        "// Radix-2 single-path delay feedback FFT of {} points",
+        points
+    )?;
+    writeln!(out, "module {} (", name)?;
+    writeln!(out, "    input wire clk,")?;
+    writeln!(out, "    input wire sync,")?;
+    writeln!(out, "    input wire signed [31:0] in_re,")?;
+    writeln!(out, "    input wire signed [31:0] in_im,")?;
+    writeln!(out, "    output wire sync_out,")?;
+    writeln!(out, "    output wire signed [31:0] out_re,")?;
+    writeln!(out, "    output wire signed [31:0] out_im")?;
+    writeln!(out, ");")?;
+    writeln!(out, "    wire sync0 = sync;")?;
+    writeln!(out, "    wire signed [31:0] re0 = in_re;")?;
+    writeln!(out, "    wire signed [31:0] im0 = in_im;")?;
+    for s in 0..bits {
+        // Butterflies are `span` apart, with the position in the span picking the delay and twiddle
+        let k = bits - 1 - s;
+        let span = 1usize << k;
+        let j = if k == 0 {
+            "0".to_owned()
+        } else {
+            format!("index{}[{}:0]", s, k - 1)
+        };
+        let (next, shift) = (s + 1, shifts[s as usize] as u32);
+        writeln!(out, "\n    // Stage {}: butterflies {} apart", s, span)?;
+        writeln!(out, "    reg [{}:0] last{} = 0;", bits - 1, s)?;
+        writeln!(
+            out,
+            "    wire [{}:0] index{} = sync{} ? 0 : last{} + 1;",
+            bits - 1,
+            s,
+            s,
+            s
+        )?;
+        writeln!(out, "    wire second{} = index{}[{}];", s, s, k)?;
+        for part in ["re", "im"] {
+            writeln!(
+                out,
+                "    reg signed [31:0] fifo_{}{} [0:{}];",
+                part,
+                s,
+                span - 1
+            )?;
+            writeln!(
+                out,
+                "    wire signed [31:0] a_{}{} = fifo_{}{}[{}];",
+                part, s, part, s, j
+            )?;
+            for (x, op) in [("sum", "+"), ("diff", "-")] {
+                writeln!(
+                    out,
+                    "    wire signed [32:0] {}_{}{} = a_{}{} {} {}{};",
+                    x, part, s, part, s, op, part, s
+                )?;
+            }
+            writeln!(out, "    reg signed [31:0] {}{};", part, next)?;
+        }
+        // The last stage's twiddles are all 1
+        if k > 0 {
+            let twiddles: Vec<_> = (0..span)
+                .map(|x| quantise(twiddle(x << s, points), width))
+                .collect();
+            let re: Vec<_> = twiddles.iter().map(|x| x.0).collect();
+            let im: Vec<_> = twiddles.iter().map(|x| x.1).collect();
+            let decl = format!("signed [{}:0]", width - 1);
+            rom(out, &format!("twiddle_re{}", s), &decl, &re)?;
+            rom(out, &format!("twiddle_im{}", s), &decl, &im)?;
+            for part in ["re", "im"] {
+                writeln!(
+                    out,
+                    "    wire signed [{}:0] w_{}{} = twiddle_{}{}[{}];",
+                    width - 1,
+                    part,
+                    s,
+                    part,
+                    s,
+                    j
+                )?;
+            }
+            writeln!(
+                out,
+                "    wire signed [{}:0] rot_re{} = a_re{} * w_re{} - a_im{} * w_im{};",
+                32 + width,
+                s,
+                s,
+                s,
+                s,
+                s
+            )?;
+            writeln!(
+                out,
+                "    wire signed [{}:0] rot_im{} = a_re{} * w_im{} + a_im{} * w_re{};",
+                32 + width,
+                s,
+                s,
+                s,
+                s,
+                s
+            )?;
+            resources += memory(span as u64, width) + memory(span as u64, width);
+            resources.dsp48s += 4 * dsp48s(32, width);
+            resources.luts += 2 * (33 + width as u64);
+        }
+        writeln!(out, "    reg sync{} = 0;", next)?;
+        writeln!(out, "    always @(posedge clk) begin")?;
+        writeln!(out, "        last{} <= index{};", s, s)?;
+        for part in ["re", "im"] {
+            let rotated = if k > 0 {
+                scaled(&format!("rot_{}{}", part, s), width - 2)
+            } else {
+                format!("a_{}{}", part, s)
+            };
+            writeln!(
+                out,
+                "        fifo_{}{}[{}] <= second{} ? {} : {}{};",
+                part,
+                s,
+                j,
+                s,
+                scaled(&format!("diff_{}{}", part, s), shift),
+                part,
+                s
+            )?;
+            writeln!(
+                out,
+                "        {}{} <= second{} ? {} : {};",
+                part,
+                next,
+                s,
+                scaled(&format!("sum_{}{}", part, s), shift),
+                rotated
+            )?;
+        }
+        writeln!(out, "    end")?;
+        delay(
+            out,
+            &format!("sync{}", s),
+            &format!("sync{}", next),
+            span as u64 + 1,
+        )?;
+        resources += memory(span as u64, 32) + memory(span as u64, 32);
+        resources.luts += 4 * 33 + bits as u64;
+        resources.ffs += 64 + bits as u64 + span as u64 + 1;
+        latency += span as u64 + 1;
+    }
+    writeln!(out, "\n    assign sync_out = sync{};", bits)?;
+    writeln!(out, "    assign out_re = re{};", bits)?;
+    writeln!(out, "    assign out_im = im{};", bits)?;
+    writeln!(out, "endmodule\n")?;
+    Ok((resources, latency))
+}
+
+fn biplex(params: &Parameters) -> anyhow::Result<Generated> {
+    let bits = params.integer("fft_size", 1..=16)?;
+    let shifts = params.shifts(bits)?;
+    let width = params.integer("coeff_width", 4..=25)?;
+    let name = params.module_name();
+
+    let mut out = String::new();
+    let pipeline_name = format!("{}_pipeline", name);
+    let (resources, latency) = pipeline(&mut out, &pipeline_name, bits, &shifts, width)?;
+    let growth = shifts.iter().filter(|x| !**x).count() as i32;
+    let gain = SQRT_2 * 2f64.powi(growth);
+    let mut ranges = BTreeMap::new();
+    for n in 0..2 {
+        let inputs = [format!("inputs.in{}_re", n), format!("inputs.in{}_im", n)];
+        ranges.extend(
+            self::ranges(2, &inputs, gain)
+                .into_iter()
+                .filter(|(k, _)| k.starts_with(&format!("out{}_", n))),
+        );
+    }
+    let module = block(
+        name,
+        complex_pins("in", 2),
+        complex_pins("out", 2),
+        latency,
+        resources + resources,
+        ranges,
+    );
+    header(
+        &mut out,
+        &module,
+        &format!("Biplex FFT of {} points over two streams", 1 << bits),
+        "wire",
+    )?;
+    for n in 0..2 {
+        let sync_out = if n == 0 { "sync_out" } else { "" };
+        writeln!(
+            out,
+            "    {} stream{} (.clk(clk), .sync(sync), .in_re(in{}_re), .in_im(in{}_im), .sync_out({}), \
+             .out_re(out{}_re), .out_im(out{}_im));",
+            pipeline_name, n, n, n, sync_out, n, n
+        )?;
+    }
+    writeln!(out, "endmodule")?;
+    Ok(Generated {
+        module,
+        verilog: out,
+    })
+}
+
+/// The power of the twiddle factor that rotates the `c`th result of stream `p`, when the `2^bits` point FFT is
+/// split over streams with `2^stream_bits` points each
+fn rotation(p: usize, c: usize, stream_bits: u32) -> usize {
+    p * bit_reverse(c, stream_bits)
+}
+
+fn wideband(params: &Parameters) -> anyhow::Result<Generated> {
+    let bits = params.integer("fft_size", 2..=16)?;
+    let parallel = params.integer("parallel", 1..=3)?;
+    if parallel >= bits {
+        bail!(
+            "`fft_wideband` needs more than 2^{} points to take {} samples per clock",
+            parallel,
+            1 << parallel
+        );
+    }
+    let shifts = params.shifts(bits)?;
+    let width = params.integer("coeff_width", 4..=25)?;
+    let name = params.module_name();
+    let (points, streams) = (1usize << bits, 1usize << parallel);
+    // Each stream is an FFT of its own, which the direct stage then combines
+    let stream_bits = bits - parallel;
+    let stream_points = 1usize << stream_bits;
+    let direct_shift = shifts[stream_bits as usize..]
+        .iter()
+        .filter(|x| **x)
+        .count() as u32;
+
+    let mut out = String::new();
+    let pipeline_name = format!("{}_pipeline", name);
+    let (stream_cost, stream_latency) = pipeline(
+        &mut out,
+        &pipeline_name,
+        stream_bits,
+        &shifts[..stream_bits as usize],
+        width,
+    )?;
+    let mut resources = Resources::default();
+    for _ in 0..streams {
+        resources += stream_cost;
+    }
+    for _ in 1..streams {
+        resources += memory(stream_points as u64, width) + memory(stream_points as u64, width);
+        resources.dsp48s += 4 * dsp48s(32, width);
+    }
+    let dft_width = 33 + width + parallel;
+    resources.luts += (streams * streams) as u64 * 2 * dft_width as u64;
+    resources.ffs += (4 * streams * 32) as u64 + stream_bits as u64 + 2;
+
+    let inputs: Vec<_> = complex_pins("in", streams)
+        .into_iter()
+        .map(|x| format!("inputs.{}", x.name))
+        .collect();
+    let growth = shifts.iter().filter(|x| !**x).count() as i32;
+    let module = block(
+        name,
+        complex_pins("in", streams),
+        complex_pins("out", streams),
+        stream_latency + 2,
+        resources,
+        ranges(streams, &inputs, SQRT_2 * 2f64.powi(growth)),
+    );
+    header(
+        &mut out,
+        &module,
+        &format!(
+            "Wideband FFT of {} points over {} samples per clock. The `c`th result after `sync_out` on `out<q>` \
+             is bin `bitrev(c) + {} * q`.",
+            points, streams, stream_points
+        ),
+        "reg",
+    )?;
+    writeln!(out, "    wire stream_sync;")?;
+    for p in 0..streams {
+        writeln!(out, "    wire signed [31:0] y_re{}, y_im{};", p, p)?;
+        let sync_out = if p == 0 { "stream_sync" } else { "" };
+        writeln!(
+            out,
+            "    {} stream{} (.clk(clk), .sync(sync), .in_re(in{}_re), .in_im(in{}_im), .sync_out({}), \
+             .out_re(y_re{}), .out_im(y_im{}));",
+            pipeline_name, p, p, p, sync_out, p, p
+        )?;
+    }
+    writeln!(
+        out,
+        "\n    // Rotate each stream's results by the twiddle for its bin"
+    )?;
+    writeln!(out, "    reg [{}:0] last = 0;", stream_bits - 1)?;
+    writeln!(
+        out,
+        "    wire [{}:0] index = stream_sync ? 0 : last + 1;",
+        stream_bits - 1
+    )?;
+    for p in 0..streams {
+        writeln!(out, "    reg signed [31:0] z_re{}, z_im{};", p, p)?;
+        if p == 0 {
+            continue;
+        }
+        let twiddles: Vec<_> = (0..stream_points)
+            .map(|c| quantise(twiddle(rotation(p, c, stream_bits), points), width))
+            .collect();
+        let decl = format!("signed [{}:0]", width - 1);
+        rom(
+            &mut out,
+            &format!("twiddle_re{}", p),
+            &decl,
+            &twiddles.iter().map(|x| x.0).collect::<Vec<_>>(),
+        )?;
+        rom(
+            &mut out,
+            &format!("twiddle_im{}", p),
+            &decl,
+            &twiddles.iter().map(|x| x.1).collect::<Vec<_>>(),
+        )?;
+        writeln!(
+            out,
+            "    wire signed [{}:0] w_re{} = twiddle_re{}[index];",
+            width - 1,
+            p,
+            p
+        )?;
+        writeln!(
+            out,
+            "    wire signed [{}:0] w_im{} = twiddle_im{}[index];",
+            width - 1,
+            p,
+            p
+        )?;
+        writeln!(
+            out,
+            "    wire signed [{}:0] rot_re{} = y_re{} * w_re{} - y_im{} * w_im{};",
+            32 + width,
+            p,
+            p,
+            p,
+            p,
+            p
+        )?;
+        writeln!(
+            out,
+            "    wire signed [{}:0] rot_im{} = y_re{} * w_im{} + y_im{} * w_re{};",
+            32 + width,
+            p,
+            p,
+            p,
+            p,
+            p
+        )?;
+    }
+
+    writeln!(out, "\n    // Combine the streams with a DFT across them")?;
+    for q in 0..streams {
+        let mut sums = [String::new(), String::new()];
+        for p in 0..streams {
+            let (c_re, c_im) = quantise(twiddle(p * q, streams), width);
+            for (n, c, x) in [
+                (0, c_re, "z_re"),
+                (0, -c_im, "z_im"),
+                (1, c_im, "z_re"),
+                (1, c_re, "z_im"),
+            ] {
+                if c != 0 {
+                    let sign = if c < 0 { "-" } else { "+" };
+                    write!(sums[n], " {} {}{} * {}", sign, x, p, c.abs())?;
+                }
+            }
+        }
+        for (part, sum) in ["re", "im"].iter().zip(&sums) {
+            writeln!(
+                out,
+                "    wire signed [{}:0] dft_{}{} ={};",
+                dft_width - 1,
+                part,
+                q,
+                sum.trim_start_matches(" +")
+            )?;
+        }
+    }
+    writeln!(out, "\n    always @(posedge clk) begin")?;
+    writeln!(out, "        last <= index;")?;
+    writeln!(out, "        z_re0 <= y_re0;")?;
+    writeln!(out, "        z_im0 <= y_im0;")?;
+    for p in 1..streams {
+        for part in ["re", "im"] {
+            writeln!(
+                out,
+                "        z_{}{} <= {};",
+                part,
+                p,
+                scaled(&format!("rot_{}{}", part, p), width - 2)
+            )?;
+        }
+    }
+    for q in 0..streams {
+        for part in ["re", "im"] {
+            writeln!(
+                out,
+                "        out{}_{} <= dft_{}{} >>> {};",
+                q,
+                part,
+                part,
+                q,
+                width - 2 + direct_shift
+            )?;
+        }
+    }
+    writeln!(out, "    end")?;
+    delay(&mut out, "stream_sync", "sync_out", 2)?;
+    writeln!(out, "endmodule")?;
+    Ok(Generated {
+        module,
+        verilog: out,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Complex = (f64, f64);
+
+    fn mul(a: Complex, b: Complex) -> Complex {
+        (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+    }
+
+    fn add(a: Complex, b: Complex) -> Complex {
+        (a.0 + b.0, a.1 + b.1)
+    }
+
+    fn dft(x: &[Complex]) -> Vec<Complex> {
+        (0..x.len())
+            .map(|k| {
+                x.iter().enumerate().fold((0.0, 0.0), |sum, (n, v)| {
+                    add(sum, mul(*v, twiddle(n * k % x.len(), x.len())))
+                })
+            })
+            .collect()
+    }
+
+    fn close(a: Complex, b: Complex) -> bool {
+        (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9
+    }
+
+    fn samples(n: usize) -> Vec<Complex> {
+        (0..n)
+            .map(|i| {
+                (
+                    ((i * 7 + 3) % 11) as f64 - 5.0,
+                    ((i * 5 + 1) % 13) as f64 - 6.0,
+                )
+            })
+            .collect()
+    }
+
+    /// Run a stream of frames through a model of the pipeline, clock by clock as the Verilog does
+    fn model(bits: u32, mut input: Vec<Complex>) -> Vec<Complex> {
+        let points = 1 << bits;
+        for s in 0..bits {
+            let span = 1usize << (bits - 1 - s);
+            let mut fifo = vec![(0.0, 0.0); span];
+            let mut output = vec![];
+            for (t, x) in input.iter().enumerate() {
+                let index = t % points;
+                let j = index % span;
+                let a = fifo[j];
+                if index & span != 0 {
+                    output.push(add(a, *x));
+                    fifo[j] = add(a, (-x.0, -x.1));
+                } else {
+                    output.push(mul(a, twiddle(j << s, points)));
+                    fifo[j] = *x;
+                }
+            }
+            // The next stage's sync comes `span + 1` clocks later, when this stage's first result is registered
+            input = output.split_off(span);
+        }
+        input
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let bits = 4;
+        // A third frame pushes the second all the way through
+        let x = samples(48);
+        let results = model(bits, x.clone());
+        for frame in 0..2 {
+            let expected = dft(&x[frame * 16..(frame + 1) * 16]);
+            for c in 0..16 {
+                assert!(close(
+                    results[frame * 16 + c],
+                    expected[bit_reverse(c, bits)]
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_wideband_split() {
+        // Streams of every 4th sample, rotated and combined, make up the whole FFT
+        let (bits, parallel) = (5, 2);
+        let x = samples(32);
+        let expected = dft(&x);
+        let stream_bits = bits - parallel;
+        let streams: Vec<_> = (0..4)
+            .map(|p| dft(&x.iter().skip(p).step_by(4).copied().collect::<Vec<_>>()))
+            .collect();
+        for c in 0..8 {
+            let z: Vec<_> = (0..4)
+                .map(|p| {
+                    let y = streams[p][bit_reverse(c, stream_bits)];
+                    mul(y, twiddle(rotation(p, c, stream_bits), 32))
+                })
+                .collect();
+            for q in 0..4 {
+                let out = (0..4).fold((0.0, 0.0), |sum, p| add(sum, mul(z[p], twiddle(p * q, 4))));
+                assert!(close(out, expected[bit_reverse(c, stream_bits) + 8 * q]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_generated() {
+        let mut values = BTreeMap::new();
+        values.insert("fft_size".to_owned(), "4".to_owned());
+        let generated = BIPLEX.generate(&values).unwrap();
+        let m = &generated.module;
+        assert_eq!(m.name, "fft_biplex_4_all_18");
+        // 8 + 4 + 2 + 1 clocks of delay, and a register per stage
+        assert_eq!(m.latency.as_deref(), Some("19"));
+        assert_eq!(
+            m.ranges["out1_im"],
+            format!(
+                "hull(inputs.in1_re, inputs.in1_im) * range(-{}, {})",
+                SQRT_2, SQRT_2
+            )
+        );
+        assert!(generated
+            .verilog
+            .contains("module fft_biplex_4_all_18_pipeline ("));
+
+        values.insert("parallel".to_owned(), "1".to_owned());
+        values.insert("shift".to_owned(), "3".to_owned());
+        let generated = WIDEBAND.generate(&values).unwrap();
+        let m = &generated.module;
+        assert_eq!(m.inputs.len(), 2 + 4);
+        assert_eq!(m.latency.as_deref(), Some("12"));
+        // The last two stages let the results grow
+        assert!(m.ranges["out0_re"].ends_with(&format!(
+            "range(-{}, {})",
+            SQRT_2 * 4.0,
+            SQRT_2 * 4.0
+        )));
+        assert!(generated.verilog.contains("out1_re <= dft_re1 >>> 16;"));
+
+        values.insert("shift".to_owned(), "16".to_owned());
+        assert!(WIDEBAND.generate(&values).is_err());
+        values.insert("parallel".to_owned(), "4".to_owned());
+        assert!(WIDEBAND.generate(&values).is_err());
+    }
+}
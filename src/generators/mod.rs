@@ -0,0 +1,413 @@
+//! This module generates the core blocks of the CASPER DSP library from their parameters, without MATLAB
+//! Each generator makes a library block, with its latency, cost and output ranges worked out for the analyses,
+//! along with the Verilog-2001 module implementing it. The parameter values are built in, so every set of values
+//! makes its own module.
+//!
+//! Every block has a `clk` input, and all but `delay` a `sync` input pulsing with the first sample of each frame,
+//! which comes out of `sync_out` alongside the first sample of the results. Samples are 32 bit signed integers like
+//! any other integer pin, with complex samples split over a pair of `_re` and `_im` pins.
+
+mod delay;
+mod fft;
+mod pfb;
+mod power;
+mod reorder;
+
+use crate::ffi::PinKind;
+use crate::library::{LibraryModule, LibraryPin, ResourceModel};
+use crate::project::Project;
+use crate::resources::Resources;
+use anyhow::{anyhow, bail};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+
+/// A block generated from one set of parameter values
+#[derive(Debug)]
+pub struct Generated {
+    /// The block to instantiate, which has no parameters as their values are built in
+    pub module: LibraryModule,
+    /// The Verilog defining the block, along with any helper modules it instantiates
+    pub verilog: String,
+}
+
+/// One of the built-in blocks
+pub struct Generator {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Every parameter the block takes, with its default value
+    pub parameters: &'static [(&'static str, &'static str)],
+    build: fn(&Parameters) -> anyhow::Result<Generated>,
+}
+
+/// Every built-in block
+pub const GENERATORS: &[Generator] = &[
+    pfb::PFB_FIR,
+    fft::BIPLEX,
+    fft::WIDEBAND,
+    power::POWER,
+    power::VACC,
+    reorder::REORDER,
+    delay::DELAY,
+];
+
+/// Find the built-in block `name`
+pub fn find(name: &str) -> Option<&'static Generator> {
+    GENERATORS.iter().find(|x| x.name == name)
+}
+
+impl Generator {
+    /// Generate the block with the given parameter values, the others taking their defaults
+    pub fn generate(&self, values: &BTreeMap<String, String>) -> anyhow::Result<Generated> {
+        if let Some(name) = values
+            .keys()
+            .find(|k| !self.parameters.iter().any(|(x, _)| x == k))
+        {
+            bail!("`{}` has no parameter `{}`", self.name, name);
+        }
+        let values = self
+            .parameters
+            .iter()
+            .map(|(name, default)| {
+                let value = values.get(*name).map_or(*default, String::as_str);
+                (*name, value.trim().to_owned())
+            })
+            .collect();
+        (self.build)(&Parameters {
+            generator: self.name,
+            values,
+        })
+    }
+}
+
+/// The parameter values a block is being generated with, in the order the generator declares them
+pub(super) struct Parameters {
+    generator: &'static str,
+    values: Vec<(&'static str, String)>,
+}
+
+impl Parameters {
+    pub fn get(&self, name: &str) -> &str {
+        self.values
+            .iter()
+            .find(|(x, _)| *x == name)
+            .map(|(_, v)| v.as_str())
+            .expect("Generators only ask for the parameters they declare")
+    }
+
+    /// Get a whole number parameter, which has to be within `range`
+    pub fn integer(&self, name: &str, range: RangeInclusive<u32>) -> anyhow::Result<u32> {
+        let value = self.get(name);
+        value
+            .parse()
+            .ok()
+            .filter(|x| range.contains(x))
+            .ok_or_else(|| {
+                anyhow!(
+                    "`{}` of `{}` must be a whole number from {} to {}, not `{}`",
+                    name,
+                    self.generator,
+                    range.start(),
+                    range.end(),
+                    value
+                )
+            })
+    }
+
+    /// Get a positive number parameter
+    pub fn number(&self, name: &str) -> anyhow::Result<f64> {
+        let value = self.get(name);
+        value
+            .parse()
+            .ok()
+            .filter(|x: &f64| x.is_finite() && *x > 0.0)
+            .ok_or_else(|| {
+                anyhow!(
+                    "`{}` of `{}` must be a positive number, not `{}`",
+                    name,
+                    self.generator,
+                    value
+                )
+            })
+    }
+
+    /// Get the stages of a pipeline of `stages` that halve their results, from a `shift` of `all`, `none` or a mask
+    /// with a bit per stage, first stage lowest
+    pub fn shifts(&self, stages: u32) -> anyhow::Result<Vec<bool>> {
+        let mask = match self.get("shift") {
+            "all" => u64::MAX,
+            "none" => 0,
+            value => value
+                .parse::<u64>()
+                .ok()
+                .filter(|x| *x >> stages == 0)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "`shift` of `{}` must be `all`, `none` or a mask of {} bits, not `{}`",
+                        self.generator,
+                        stages,
+                        value
+                    )
+                })?,
+        };
+        Ok((0..stages).map(|s| (mask >> s) & 1 == 1).collect())
+    }
+
+    /// The name of the module these values generate, like `pfb_fir_10_4_hamming_1_18`
+    pub fn module_name(&self) -> String {
+        let mut name = self.generator.to_owned();
+        for (_, value) in &self.values {
+            name.push('_');
+            name.extend(
+                value
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }),
+            );
+        }
+        name
+    }
+}
+
+pub(super) fn pin(name: &str, kind: PinKind) -> LibraryPin {
+    LibraryPin {
+        name: name.to_owned(),
+        kind,
+    }
+}
+
+/// Make the interface of a generated block, adding the clock and sync pins to the data pins
+pub(super) fn block(
+    name: String,
+    inputs: Vec<LibraryPin>,
+    outputs: Vec<LibraryPin>,
+    latency: u64,
+    resources: Resources,
+    ranges: BTreeMap<String, String>,
+) -> LibraryModule {
+    let mut all_inputs = vec![pin("clk", PinKind::Wire), pin("sync", PinKind::Wire)];
+    all_inputs.extend(inputs);
+    let mut all_outputs = vec![pin("sync_out", PinKind::Wire)];
+    all_outputs.extend(outputs);
+    let count = |x: u64| Some(x.to_string());
+    LibraryModule {
+        name,
+        inputs: all_inputs,
+        outputs: all_outputs,
+        parameters: BTreeMap::new(),
+        bundles: vec![],
+        resources: Some(ResourceModel {
+            luts: count(resources.luts),
+            ffs: count(resources.ffs),
+            dsp48s: count(resources.dsp48s),
+            brams: count(resources.brams),
+        }),
+        latency: Some(latency.to_string()),
+        ranges,
+        script: None,
+        generator: None,
+    }
+}
+
+/// Write the header of the module implementing `module`, its outputs being `reg`s or `wire`s
+pub(super) fn header(
+    out: &mut String,
+    module: &LibraryModule,
+    summary: &str,
+    outputs: &str,
+) -> std::fmt::Result {
+    let net = |kind: PinKind| match kind {
+        PinKind::Integer => " signed [31:0]",
+        PinKind::Real => " [63:0]",
+        _ => "",
+    };
+    let ports: Vec<_> = module
+        .inputs
+        .iter()
+        .map(|x| format!("input wire{} {}", net(x.kind), x.name))
+        .chain(
+            module
+                .outputs
+                .iter()
+                .map(|x| format!("output {}{} {}", outputs, net(x.kind), x.name)),
+        )
+        .collect();
+    writeln!(out, "// {}", summary)?;
+    writeln!(out, "module {} (", module.name)?;
+    writeln!(out, "    {}", ports.join(",\n    "))?;
+    writeln!(out, ");")
+}
+
+/// Declare a ROM `name` of `values`, each of the type `decl` like `signed [17:0]`
+pub(super) fn rom(out: &mut String, name: &str, decl: &str, values: &[i64]) -> std::fmt::Result {
+    writeln!(out, "    reg {} {} [0:{}];", decl, name, values.len() - 1)?;
+    writeln!(out, "    initial begin")?;
+    for (n, x) in values.iter().enumerate() {
+        writeln!(out, "        {}[{}] = {};", name, n, x)?;
+    }
+    writeln!(out, "    end")
+}
+
+/// Drive the existing `reg` `to` with `from`, `cycles` clocks late
+pub(super) fn delay(out: &mut String, from: &str, to: &str, cycles: u64) -> std::fmt::Result {
+    if cycles == 1 {
+        return writeln!(out, "    always @(posedge clk) {} <= {};", to, from);
+    }
+    writeln!(out, "    reg [{}:0] {}_delay = 0;", cycles - 2, to)?;
+    writeln!(
+        out,
+        "    always @(posedge clk) {{{}, {}_delay}} <= {{{}_delay, {}}};",
+        to, to, to, from
+    )
+}
+
+/// Reverse the lowest `bits` bits of `x`
+pub(super) fn bit_reverse(x: usize, bits: u32) -> usize {
+    if bits == 0 {
+        return 0;
+    }
+    x.reverse_bits() >> (usize::BITS - bits)
+}
+
+/// The bits it takes to count to `n`
+pub(super) fn clog2(n: u64) -> u32 {
+    n.next_power_of_two().trailing_zeros()
+}
+
+/// The DSP48s in a signed multiplier of `a` by `b` bits, each DSP48 taking 25 by 18
+pub(super) fn dsp48s(a: u32, b: u32) -> u64 {
+    let (a, b) = (a.max(b), a.min(b));
+    (a.div_ceil(25) * b.div_ceil(18)) as u64
+}
+
+/// The resources of a memory of `words` of `width` bits, small ones in LUTs and the rest in 36 Kb BRAMs
+pub(super) fn memory(words: u64, width: u32) -> Resources {
+    if words <= 64 {
+        Resources {
+            luts: width as u64,
+            ..Default::default()
+        }
+    } else {
+        Resources {
+            brams: (words * width as u64).div_ceil(36 * 1024),
+            ..Default::default()
+        }
+    }
+}
+
+/// Generate the built-in block the library block `module` names, with its parameter values
+pub fn generate(module: &LibraryModule) -> anyhow::Result<Generated> {
+    let name = module
+        .generator
+        .as_ref()
+        .ok_or_else(|| anyhow!("`{}` isn't a generated block", module.name))?;
+    let generator = find(name).ok_or_else(|| anyhow!("There's no built-in block `{}`", name))?;
+    generator.generate(&module.parameters)
+}
+
+impl Project {
+    /// Generate the built-in block `module` names, keeping its Verilog with the project.
+    /// Returns the block to instantiate.
+    pub fn add_generated_module(
+        &mut self,
+        module: &LibraryModule,
+    ) -> anyhow::Result<LibraryModule> {
+        let generated = generate(module)?;
+        // Backends name modules after subgraphs first, which would leave the Verilog defining the wrong module
+        if self.subgraph(&generated.module.name).is_some() {
+            bail!("A subgraph is already named `{}`", generated.module.name);
+        }
+        self.set_source(generated.module.name.clone(), generated.verilog);
+        Ok(generated.module)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::netlist::Netlist;
+    use crate::project::Subgraph;
+    use crate::ranges::Issue;
+
+    #[test]
+    fn test_parameters() {
+        let pfb = find("pfb_fir").unwrap();
+        let mut values = BTreeMap::new();
+        values.insert("taps".to_owned(), "8".to_owned());
+        let generated = pfb.generate(&values).unwrap();
+        assert_eq!(generated.module.name, "pfb_fir_10_8_hamming_1_18");
+        assert!(generated.module.parameters.is_empty());
+
+        values.insert("taps".to_owned(), "lots".to_owned());
+        let e = pfb.generate(&values).unwrap_err().to_string();
+        assert!(
+            e.contains("`taps` of `pfb_fir` must be a whole number"),
+            "{}",
+            e
+        );
+        values.clear();
+        values.insert("tap".to_owned(), "8".to_owned());
+        assert!(pfb.generate(&values).is_err());
+    }
+
+    #[test]
+    fn test_every_generator() {
+        // Every block's defaults make a module whose ports are its pins, and whose models can be evaluated
+        let mut netlist = Netlist::new();
+        for generator in GENERATORS {
+            let generated = generator.generate(&BTreeMap::new()).unwrap();
+            let m = &generated.module;
+            assert!(generated.verilog.contains(&format!("module {} (", m.name)));
+            for p in m.inputs.iter().chain(&m.outputs) {
+                assert!(
+                    generated.verilog.contains(&format!(" {},\n", p.name))
+                        || generated.verilog.contains(&format!(" {}\n);", p.name)),
+                    "{} has no port {}",
+                    m.name,
+                    p.name
+                );
+            }
+            assert_eq!(
+                generated.verilog.matches("endmodule").count(),
+                generated.verilog.matches("\nmodule ").count()
+                    + generated.verilog.starts_with("module ") as usize
+            );
+            netlist.add_module_from_library(generated.module).unwrap();
+        }
+        netlist.resources("top").unwrap();
+        netlist.latency_report().unwrap();
+        let report = netlist.range_report().unwrap();
+        // Undriven inputs carry any 32 bit value, which only the reorder passes on without growing
+        assert!(report.findings.iter().all(|x| x.issue == Issue::Overflow));
+        assert!(!report
+            .findings
+            .iter()
+            .any(|x| x.name == "dout" && x.instance.starts_with("reorder")));
+    }
+
+    #[test]
+    fn test_add_generated_module() {
+        let top = Subgraph::new("top".to_owned(), vec![], vec![], Netlist::new()).unwrap();
+        let mut project = Project::new("p".to_owned(), top);
+        let module: LibraryModule =
+            serde_json::from_str(r#"{"name": "power", "generator": "power"}"#).unwrap();
+        let block = project.add_generated_module(&module).unwrap();
+        assert_eq!(block.name, "power_0");
+
+        // The block's Verilog is exported along with the design instantiating it
+        let json = project.to_json();
+        let mut project = Project::from_json(&json).unwrap();
+        project
+            .edit_subgraph("top", |netlist| {
+                netlist.add_module_from_library(block)?;
+                Ok(())
+            })
+            .unwrap();
+        let verilog = crate::export::verilog::verilog(&project).unwrap();
+        assert!(verilog.contains("module power_0 ("));
+        assert!(verilog.contains("    power_0 power_0_0 ("), "{}", verilog);
+
+        let unknown: LibraryModule =
+            serde_json::from_str(r#"{"name": "x", "generator": "xeng"}"#).unwrap();
+        assert!(project.add_generated_module(&unknown).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+//! The polyphase filter bank FIR, which shapes the channels of the FFT it feeds so they're flat and don't leak
+//! into each other. The window lies over the last `taps` frames, oldest first.
+
+use super::{
+    block, clog2, delay, dsp48s, header, memory, pin, rom, Generated, Generator, Parameters,
+};
+use crate::ffi::PinKind;
+use crate::resources::Resources;
+use anyhow::bail;
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::fmt::Write;
+
+pub(super) const PFB_FIR: Generator = Generator {
+    name: "pfb_fir",
+    description:
+        "Polyphase filter bank FIR, filtering frames of samples for an FFT of 2^fft_size points",
+    parameters: &[
+        ("fft_size", "10"),
+        ("taps", "4"),
+        ("window", "hamming"),
+        ("fwidth", "1"),
+        ("coeff_width", "18"),
+    ],
+    build,
+};
+
+/// The window function at `x`, from 0 at the first coefficient to 1 at the last
+fn window(name: &str) -> Option<fn(f64) -> f64> {
+    match name {
+        "hamming" => Some(|x| 0.54 - 0.46 * (2.0 * PI * x).cos()),
+        "hann" => Some(|x| 0.5 - 0.5 * (2.0 * PI * x).cos()),
+        "blackman" => Some(|x| 0.42 - 0.5 * (2.0 * PI * x).cos() + 0.08 * (4.0 * PI * x).cos()),
+        "rectangular" => Some(|_| 1.0),
+        _ => None,
+    }
+}
+
+/// The windowed sinc over `taps` frames of `points`, as the CASPER library makes it.
+/// `fwidth` scales the width of each channel.
+fn coefficients(points: usize, taps: usize, window: fn(f64) -> f64, fwidth: f64) -> Vec<f64> {
+    let len = points * taps;
+    (0..len)
+        .map(|n| {
+            let x = fwidth * (n as f64 / points as f64 - taps as f64 / 2.0);
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                (PI * x).sin() / (PI * x)
+            };
+            sinc * window(n as f64 / (len - 1) as f64)
+        })
+        .collect()
+}
+
+/// Scale the coefficients to signed `width` bit integers, the largest taking the full range
+fn quantise(coefficients: &[f64], width: u32) -> Vec<i64> {
+    let largest = coefficients.iter().fold(0f64, |a, x| a.max(x.abs()));
+    let full = ((1i64 << (width - 1)) - 1) as f64;
+    coefficients
+        .iter()
+        .map(|x| (x / largest * full).round() as i64)
+        .collect()
+}
+
+fn build(params: &Parameters) -> anyhow::Result<Generated> {
+    let bits = params.integer("fft_size", 1..=16)?;
+    let taps = params.integer("taps", 2..=16)? as usize;
+    let Some(shape) = window(params.get("window")) else {
+        bail!(
+            "`window` of `pfb_fir` must be hamming, hann, blackman or rectangular, not `{}`",
+            params.get("window")
+        );
+    };
+    let fwidth = params.number("fwidth")?;
+    let width = params.integer("coeff_width", 4..=25)?;
+    let points = 1usize << bits;
+    let coeffs = quantise(&coefficients(points, taps, shape, fwidth), width);
+    // The coefficients for the sample `t` frames back, by its position in the frame
+    let tap = |t: usize| &coeffs[(taps - 1 - t) * points..(taps - t) * points];
+
+    // The worst case gain is at the position whose coefficients add up to the most
+    let gain = (0..points)
+        .map(|p| (0..taps).map(|t| tap(t)[p].abs()).sum::<i64>())
+        .max()
+        .unwrap_or_default() as f64
+        / (1u64 << (width - 1)) as f64;
+    let sum_width = 32 + width + clog2(taps as u64);
+    let mut resources = Resources {
+        luts: (taps as u64 - 1) * sum_width as u64 + 2 * bits as u64,
+        ffs: taps as u64 * (64 + 2 * width as u64) + 32 + bits as u64 + 3,
+        dsp48s: taps as u64 * dsp48s(32, width),
+        brams: 0,
+    };
+    for _ in 1..taps {
+        resources += memory(points as u64, 32);
+    }
+    for _ in 0..taps {
+        resources += memory(points as u64, width);
+    }
+    let module = block(
+        params.module_name(),
+        vec![pin("din", PinKind::Integer)],
+        vec![pin("dout", PinKind::Integer)],
+        3,
+        resources,
+        BTreeMap::from([(
+            "dout".to_owned(),
+            format!("inputs.din * range(-{}, {})", gain, gain),
+        )]),
+    );
+
+    let mut out = String::new();
+    header(
+        &mut out,
+        &module,
+        &format!(
+            "Polyphase filter bank FIR: {} taps of {} points, {} window",
+            taps,
+            points,
+            params.get("window")
+        ),
+        "reg",
+    )?;
+    writeln!(out, "    reg [{}:0] last = 0;", bits - 1)?;
+    writeln!(
+        out,
+        "    wire [{}:0] index = sync ? 0 : last + 1;",
+        bits - 1
+    )?;
+    for t in 0..taps {
+        writeln!(out, "\n    // The sample {} frames back", t)?;
+        writeln!(out, "    reg signed [31:0] x{};", t)?;
+        if t > 0 {
+            writeln!(out, "    reg signed [31:0] line{} [0:{}];", t, points - 1)?;
+        }
+        rom(
+            &mut out,
+            &format!("coeff{}", t),
+            &format!("signed [{}:0]", width - 1),
+            tap(t),
+        )?;
+        writeln!(out, "    reg signed [{}:0] c{};", width - 1, t)?;
+        writeln!(out, "    reg signed [{}:0] p{};", 31 + width, t)?;
+    }
+    let products: Vec<_> = (0..taps).map(|t| format!("p{}", t)).collect();
+    writeln!(
+        out,
+        "\n    wire signed [{}:0] total = {};",
+        sum_width - 1,
+        products.join(" + ")
+    )?;
+    writeln!(out, "\n    always @(posedge clk) begin")?;
+    writeln!(out, "        last <= index;")?;
+    writeln!(out, "        x0 <= din;")?;
+    for t in 1..taps {
+        writeln!(out, "        x{} <= line{}[index];", t, t)?;
+        writeln!(out, "        line{}[last] <= x{};", t, t - 1)?;
+    }
+    for t in 0..taps {
+        writeln!(out, "        c{} <= coeff{}[index];", t, t)?;
+        writeln!(out, "        p{} <= x{} * c{};", t, t, t)?;
+    }
+    writeln!(out, "        dout <= total >>> {};", width - 1)?;
+    writeln!(out, "    end")?;
+    delay(&mut out, "sync", "sync_out", 3)?;
+    writeln!(out, "endmodule")?;
+    Ok(Generated {
+        module,
+        verilog: out,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coefficients() {
+        let coeffs = coefficients(64, 4, window("hamming").unwrap(), 1.0);
+        assert_eq!(coeffs.len(), 256);
+        // The sinc peaks in the middle, and crosses zero a frame either side
+        let (peak, _) = coeffs
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+        assert_eq!(peak, 128);
+        assert!(coeffs[64].abs() < 1e-12 && coeffs[192].abs() < 1e-12);
+
+        let q = quantise(&coeffs, 18);
+        assert_eq!(q[128], (1 << 17) - 1);
+        assert!(q.iter().all(|x| x.abs() < 1 << 17));
+    }
+
+    #[test]
+    fn test_pfb_fir() {
+        let mut values = BTreeMap::new();
+        values.insert("fft_size".to_owned(), "5".to_owned());
+        let generated = PFB_FIR.generate(&values).unwrap();
+        let m = &generated.module;
+        assert_eq!(m.name, "pfb_fir_5_4_hamming_1_18");
+        assert_eq!(m.latency.as_deref(), Some("3"));
+        // Every tap's multiplier is 32 by 18 bits
+        assert_eq!(m.resources.as_ref().unwrap().dsp48s.as_deref(), Some("8"));
+        assert!(m.ranges["dout"].starts_with("inputs.din * range(-"));
+        let v = &generated.verilog;
+        assert!(v.contains("reg signed [31:0] line3 [0:31];"));
+        assert!(v.contains("wire signed [51:0] total = p0 + p1 + p2 + p3;"));
+        assert!(v.contains("dout <= total >>> 17;"));
+
+        values.insert("window".to_owned(), "kaiser".to_owned());
+        assert!(PFB_FIR.generate(&values).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+//! Detecting the power of complex samples, and accumulating it over many frames to integrate a spectrum
+
+use super::{block, clog2, delay, dsp48s, header, memory, pin, Generated, Generator, Parameters};
+use crate::ffi::PinKind;
+use crate::resources::Resources;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+pub(super) const POWER: Generator = Generator {
+    name: "power",
+    description: "The power of complex samples, shifted right by `shift` bits",
+    parameters: &[("shift", "0")],
+    build: power,
+};
+
+pub(super) const VACC: Generator = Generator {
+    name: "vacc",
+    description: "Vector accumulator, adding up `acc_len` frames of 2^vector_len samples, position by position",
+    parameters: &[("vector_len", "10"), ("acc_len", "1024")],
+    build: vacc,
+};
+
+fn power(params: &Parameters) -> anyhow::Result<Generated> {
+    let shift = params.integer("shift", 0..=63)?;
+    let scale = 2f64.powi(shift as i32);
+    let largest = |x| format!("max(-inputs.{}.min, inputs.{}.max)^2", x, x);
+    let module = block(
+        params.module_name(),
+        vec![pin("re", PinKind::Integer), pin("im", PinKind::Integer)],
+        vec![pin("power", PinKind::Integer)],
+        2,
+        Resources {
+            luts: 65,
+            ffs: 128 + 32 + 2,
+            dsp48s: 2 * dsp48s(32, 32),
+            brams: 0,
+        },
+        BTreeMap::from([(
+            "power".to_owned(),
+            format!(
+                "range(0, ({} + {}) / {})",
+                largest("re"),
+                largest("im"),
+                scale
+            ),
+        )]),
+    );
+
+    let mut out = String::new();
+    header(
+        &mut out,
+        &module,
+        &format!("Power of complex samples, shifted right by {} bits", shift),
+        "reg",
+    )?;
+    writeln!(out, "    reg signed [63:0] re_squared, im_squared;")?;
+    writeln!(
+        out,
+        "    wire signed [64:0] total = re_squared + im_squared;"
+    )?;
+    writeln!(out, "    always @(posedge clk) begin")?;
+    writeln!(out, "        re_squared <= re * re;")?;
+    writeln!(out, "        im_squared <= im * im;")?;
+    writeln!(out, "        power <= total >>> {};", shift)?;
+    writeln!(out, "    end")?;
+    delay(&mut out, "sync", "sync_out", 2)?;
+    writeln!(out, "endmodule")?;
+    Ok(Generated {
+        module,
+        verilog: out,
+    })
+}
+
+fn vacc(params: &Parameters) -> anyhow::Result<Generated> {
+    let bits = params.integer("vector_len", 1..=16)?;
+    let frames = params.integer("acc_len", 1..=65536)? as u64;
+    let points = 1u64 << bits;
+    let count_bits = clog2(frames).max(1);
+    let module = block(
+        params.module_name(),
+        vec![pin("din", PinKind::Integer)],
+        vec![pin("dout", PinKind::Integer), pin("valid", PinKind::Wire)],
+        1,
+        memory(points, 32)
+            + Resources {
+                luts: 32 + (bits + count_bits) as u64,
+                ffs: 32 + 2 + (bits + count_bits) as u64,
+                dsp48s: 0,
+                brams: 0,
+            },
+        BTreeMap::from([("dout".to_owned(), format!("inputs.din * {}", frames))]),
+    );
+
+    let mut out = String::new();
+    header(
+        &mut out,
+        &module,
+        &format!(
+            "Vector accumulator over {} frames of {} samples. Each sum comes out with `valid` as the last frame is \
+             added, with `sync_out` marking the first.",
+            frames, points
+        ),
+        "reg",
+    )?;
+    writeln!(out, "    reg [{}:0] last = 0;", bits - 1)?;
+    writeln!(
+        out,
+        "    wire [{}:0] index = sync ? 0 : last + 1;",
+        bits - 1
+    )?;
+    writeln!(out, "    reg [{}:0] last_frame = 0;", count_bits - 1)?;
+    writeln!(
+        out,
+        "    wire [{}:0] frame = sync ? 0 : index != 0 ? last_frame : last_frame == {} ? 0 : last_frame + 1;",
+        count_bits - 1,
+        frames - 1
+    )?;
+    writeln!(out, "    reg signed [31:0] sums [0:{}];", points - 1)?;
+    writeln!(
+        out,
+        "    wire signed [31:0] sum = (frame == 0 ? 0 : sums[index]) + din;"
+    )?;
+    writeln!(out, "    always @(posedge clk) begin")?;
+    writeln!(out, "        last <= index;")?;
+    writeln!(out, "        last_frame <= frame;")?;
+    writeln!(out, "        sums[index] <= sum;")?;
+    writeln!(out, "        dout <= sum;")?;
+    writeln!(out, "        valid <= frame == {};", frames - 1)?;
+    writeln!(
+        out,
+        "        sync_out <= frame == {} && index == 0;",
+        frames - 1
+    )?;
+    writeln!(out, "    end")?;
+    writeln!(out, "endmodule")?;
+    Ok(Generated {
+        module,
+        verilog: out,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_power() {
+        let mut values = BTreeMap::new();
+        values.insert("shift".to_owned(), "16".to_owned());
+        let generated = POWER.generate(&values).unwrap();
+        assert_eq!(generated.module.name, "power_16");
+        assert!(generated.verilog.contains("power <= total >>> 16;"));
+        assert_eq!(
+            generated.module.ranges["power"],
+            "range(0, (max(-inputs.re.min, inputs.re.max)^2 + max(-inputs.im.min, inputs.im.max)^2) / 65536)"
+        );
+    }
+
+    #[test]
+    fn test_vacc() {
+        let mut values = BTreeMap::new();
+        values.insert("vector_len".to_owned(), "3".to_owned());
+        values.insert("acc_len".to_owned(), "5".to_owned());
+        let generated = VACC.generate(&values).unwrap();
+        assert_eq!(generated.module.name, "vacc_3_5");
+        assert_eq!(generated.module.ranges["dout"], "inputs.din * 5");
+        assert!(generated
+            .verilog
+            .contains("last_frame == 4 ? 0 : last_frame + 1;"));
+        assert!(generated.verilog.contains("reg signed [31:0] sums [0:7];"));
+    }
+}
@@ -0,0 +1,146 @@
+//! Reordering the samples of each frame, like putting an FFT's bit-reversed results back in order
+
+use super::{
+    bit_reverse, block, delay, header, memory, pin, rom, Generated, Generator, Parameters,
+};
+use crate::ffi::PinKind;
+use crate::resources::Resources;
+use anyhow::bail;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+pub(super) const REORDER: Generator = Generator {
+    name: "reorder",
+    description: "Reorder frames of 2^vector_len samples, by `bitreverse` or a comma separated list of positions",
+    parameters: &[("vector_len", "10"), ("map", "bitreverse")],
+    build,
+};
+
+/// The position in the incoming frame of each outgoing sample
+fn order(params: &Parameters, bits: u32) -> anyhow::Result<Vec<usize>> {
+    let points = 1usize << bits;
+    let map = params.get("map");
+    if map == "bitreverse" {
+        return Ok((0..points).map(|x| bit_reverse(x, bits)).collect());
+    }
+    // Lists can be written like Matlab vectors too, as Simulink masks have them
+    let Ok(order) = map
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|x| !x.is_empty())
+        .map(str::parse::<usize>)
+        .collect::<Result<Vec<_>, _>>()
+    else {
+        bail!(
+            "`map` of `reorder` must be `bitreverse` or a list of positions, not `{}`",
+            map
+        );
+    };
+    let mut seen = vec![false; points];
+    if order.len() != points
+        || !order
+            .iter()
+            .all(|x| *x < points && !std::mem::replace(&mut seen[*x], true))
+    {
+        bail!(
+            "`map` of `reorder` must list each of the {} positions once",
+            points
+        );
+    }
+    Ok(order)
+}
+
+fn build(params: &Parameters) -> anyhow::Result<Generated> {
+    let bits = params.integer("vector_len", 1..=16)?;
+    let order = order(params, bits)?;
+    let points = 1u64 << bits;
+    let module = block(
+        params.module_name(),
+        vec![pin("din", PinKind::Integer)],
+        vec![pin("dout", PinKind::Integer)],
+        points + 1,
+        memory(2 * points, 32)
+            + memory(points, bits)
+            + Resources {
+                luts: 2 * bits as u64,
+                ffs: 32 + 2 * bits as u64 + points + 2,
+                dsp48s: 0,
+                brams: 0,
+            },
+        BTreeMap::from([("dout".to_owned(), "inputs.din".to_owned())]),
+    );
+
+    let mut out = String::new();
+    header(
+        &mut out,
+        &module,
+        &format!(
+            "Reorder frames of {} samples, each coming out as the next goes in",
+            points
+        ),
+        "reg",
+    )?;
+    writeln!(out, "    reg [{}:0] last = 0;", bits - 1)?;
+    writeln!(
+        out,
+        "    wire [{}:0] index = sync ? 0 : last + 1;",
+        bits - 1
+    )?;
+    writeln!(
+        out,
+        "    // Frames alternate between the halves of the buffer"
+    )?;
+    writeln!(out, "    reg last_half = 0;")?;
+    writeln!(
+        out,
+        "    wire half = sync ? 0 : index == 0 ? !last_half : last_half;"
+    )?;
+    writeln!(out, "    reg signed [31:0] buffer [0:{}];", 2 * points - 1)?;
+    let order: Vec<_> = order.into_iter().map(|x| x as i64).collect();
+    rom(&mut out, "order", &format!("[{}:0]", bits - 1), &order)?;
+    writeln!(out, "    always @(posedge clk) begin")?;
+    writeln!(out, "        last <= index;")?;
+    writeln!(out, "        last_half <= half;")?;
+    writeln!(out, "        buffer[{{half, index}}] <= din;")?;
+    writeln!(out, "        dout <= buffer[{{!half, order[index]}}];")?;
+    writeln!(out, "    end")?;
+    delay(&mut out, "sync", "sync_out", points + 1)?;
+    writeln!(out, "endmodule")?;
+    Ok(Generated {
+        module,
+        verilog: out,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reorder() {
+        let mut values = BTreeMap::new();
+        values.insert("vector_len".to_owned(), "2".to_owned());
+        values.insert("map".to_owned(), "3, 2, 1, 0".to_owned());
+        let generated = REORDER.generate(&values).unwrap();
+        assert_eq!(generated.module.name, "reorder_2_3__2__1__0");
+        assert_eq!(generated.module.latency.as_deref(), Some("5"));
+        assert!(generated.verilog.contains("        order[0] = 3;\n"));
+
+        values.insert("map".to_owned(), "[3 2 1 0]".to_owned());
+        assert!(REORDER
+            .generate(&values)
+            .unwrap()
+            .verilog
+            .contains("        order[0] = 3;\n"));
+
+        values.insert("map".to_owned(), "bitreverse".to_owned());
+        let generated = REORDER.generate(&values).unwrap();
+        assert!(generated.verilog.contains("        order[1] = 2;\n"));
+
+        for map in ["0, 1, 2", "0, 1, 1, 2", "0, 1, 2, 4", "backwards", "[]"] {
+            values.insert("map".to_owned(), map.to_owned());
+            assert!(REORDER.generate(&values).is_err(), "{}", map);
+        }
+    }
+}
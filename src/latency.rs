@@ -0,0 +1,350 @@
+//! This module works out when data reaches every pin, in clock cycles, to find datapaths that need balancing
+//! Library blocks declare their latency, and anything that doesn't is taken to be combinational. A module whose
+//! inputs arrive at different times is out of balance, which delays on the early inputs put right.
+
+use crate::ffi::InterconnectDirection;
+use crate::generators::{self, Generated};
+use crate::netlist::{ModuleIndex, Netlist, PinIndex, WireIndex};
+use crate::project::Project;
+use crate::resources::evaluate;
+use anyhow::bail;
+use mlua::Lua;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+
+/// The input clocking a block, which is left out of the balance
+const CLOCK: &str = "clk";
+
+/// One input of an out of balance module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Arrival {
+    pub pin: PinIndex,
+    pub name: String,
+    /// The clock cycle data arrives on
+    pub cycle: u64,
+}
+
+/// A module whose inputs arrive on different clock cycles
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Imbalance {
+    pub module: ModuleIndex,
+    pub id: i32,
+    pub instance: String,
+    /// Every driven input but the clock, in pin order
+    pub inputs: Vec<Arrival>,
+    /// The cycle the last input arrives on
+    pub latest: u64,
+}
+
+impl Imbalance {
+    /// Get the early inputs, along with how many cycles each needs delaying by
+    pub fn delays(&self) -> impl Iterator<Item = (&Arrival, u64)> {
+        self.inputs
+            .iter()
+            .filter(|x| x.cycle < self.latest)
+            .map(|x| (x, self.latest - x.cycle))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyReport {
+    /// The cycle data arrives on at every driven pin but the clocks, counting from the modules with no driven inputs
+    pub arrivals: HashMap<PinIndex, u64>,
+    /// The modules out of balance, ordered by id
+    pub imbalances: Vec<Imbalance>,
+}
+
+impl Display for LatencyReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.imbalances.is_empty() {
+            return writeln!(f, "Every module's inputs arrive together");
+        }
+        for x in &self.imbalances {
+            writeln!(
+                f,
+                "module {} `{}`: inputs arrive between cycles {} and {}",
+                x.id,
+                x.instance,
+                x.inputs.iter().map(|x| x.cycle).min().unwrap_or_default(),
+                x.latest
+            )?;
+            for (input, delay) in x.delays() {
+                writeln!(
+                    f,
+                    "  `{}` arrives on cycle {}, delay it by {}",
+                    input.name, input.cycle, delay
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Netlist {
+    /// Work out when data arrives at every pin, and which modules have inputs arriving on different cycles.
+    /// Errors if a latency can't be evaluated, or the netlist has a feedback loop.
+    pub fn latency_report(&self) -> anyhow::Result<LatencyReport> {
+        let Some(order) = self.topological_order() else {
+            bail!("The netlist has a feedback loop, so its latency can't be balanced");
+        };
+        let lua = Lua::new();
+        let mut arrivals = HashMap::new();
+        let mut imbalances = vec![];
+        for mi in order {
+            let m = self.get_module(mi).expect("Modules in order exist");
+            let inputs: Vec<_> = m
+                .inputs()
+                .filter_map(|pi| {
+                    let p = self.get_pin(*pi).expect("Module pins always exist");
+                    // Clocks aren't data, so there's nothing to line up. Syncs are, marking the sample they arrive with.
+                    if p.name() == CLOCK {
+                        return None;
+                    }
+                    // Drivers come earlier in the order, so they already have their arrival
+                    let cycle = arrivals[&self.driver(*pi)?];
+                    Some(Arrival {
+                        pin: *pi,
+                        name: p.name().to_owned(),
+                        cycle,
+                    })
+                })
+                .collect();
+            let latest = inputs.iter().map(|x| x.cycle).max().unwrap_or_default();
+            let latency = match m.latency() {
+                Some(expr) => evaluate(&lua, self, m, "latency", expr)?,
+                None => 0,
+            };
+            arrivals.extend(inputs.iter().map(|x| (x.pin, x.cycle)));
+            arrivals.extend(m.outputs().map(|x| (*x, latest + latency)));
+            if inputs.iter().any(|x| x.cycle != latest) {
+                imbalances.push(Imbalance {
+                    module: mi,
+                    id: m.id(),
+                    instance: m.instance().to_owned(),
+                    inputs,
+                    latest,
+                });
+            }
+        }
+        imbalances.sort_by_key(|x| x.id);
+        Ok(LatencyReport {
+            arrivals,
+            imbalances,
+        })
+    }
+
+    /// Put a built-in `delay` block in front of every input that arrives early, so every module's inputs arrive
+    /// together. The delays are clocked like the modules they feed, and placed halfway along the wire if both ends
+    /// have been. Returns each new delay block along with the generated block it instantiates.
+    pub fn balance_latency(&mut self) -> anyhow::Result<Vec<(ModuleIndex, Generated)>> {
+        let report = self.latency_report()?;
+        let mut added = vec![];
+        for imbalance in &report.imbalances {
+            for (input, delay) in imbalance.delays() {
+                added.push(self.insert_delay(input.pin, delay)?);
+            }
+        }
+        Ok(added)
+    }
+
+    /// Cut the wire into the input `idx` with a block delaying it by `cycles`
+    fn insert_delay(
+        &mut self,
+        idx: PinIndex,
+        cycles: u64,
+    ) -> anyhow::Result<(ModuleIndex, Generated)> {
+        let driver = self.driver(idx).expect("Only driven inputs are delayed");
+        let pin = self.get_pin(idx).expect("The input exists");
+        let (kind, parent) = (pin.kind(), pin.parent());
+        let generated = generators::find("delay")
+            .expect("Delays are built in")
+            .generate(&BTreeMap::from([
+                ("width".to_owned(), kind.width().to_string()),
+                ("latency".to_owned(), cycles.to_string()),
+            ]))?;
+        let (wi, _) = self
+            .wires()
+            .find(|(_, (input, _))| *input == idx)
+            .expect("Driven inputs have a wire");
+        self.remove_wire(WireIndex(wi));
+
+        let mi = self.add_module_from_library(generated.module.clone())?;
+        let pin = |name, direction| self.find_pin(mi, name, direction).expect("Delays have it");
+        let clk = pin(CLOCK, InterconnectDirection::Input);
+        let din = pin("din", InterconnectDirection::Input);
+        let dout = pin("dout", InterconnectDirection::Output);
+        self.add_wire(driver, din)
+            .expect("The delay takes the input's kind");
+        self.add_wire(dout, idx)
+            .expect("The delay takes the input's kind");
+        let clock = self
+            .find_pin(parent, CLOCK, InterconnectDirection::Input)
+            .and_then(|x| self.driver(x));
+        if let Some(clock) = clock {
+            self.add_wire(clock, clk)?;
+        }
+
+        let position = |pi: PinIndex| {
+            let p = self.get_pin(pi).expect("Wired pins exist");
+            self.get_module(p.parent()).and_then(|m| m.position())
+        };
+        if let (Some(a), Some(b)) = (position(driver), position(idx)) {
+            self.set_position(mi, Some(((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0)));
+        }
+        Ok((mi, generated))
+    }
+}
+
+impl Project {
+    /// Balance the latency of the subgraph `name`, keeping the Verilog of the delays it takes with the project.
+    /// Returns how many delays were put in.
+    pub fn balance_latency(&mut self, name: &str) -> anyhow::Result<usize> {
+        let added = self.edit_subgraph(name, |netlist| netlist.balance_latency())?;
+        for (_, generated) in &added {
+            self.set_source(generated.module.name.clone(), generated.verilog.clone());
+        }
+        Ok(added.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::PinKind;
+    use crate::project::Subgraph;
+
+    /// Add a block with the given latency, pins and wires in from `drivers`
+    fn add_block(
+        netlist: &mut Netlist,
+        latency: Option<&str>,
+        drivers: &[PinIndex],
+    ) -> (ModuleIndex, PinIndex) {
+        let m = netlist.add_module("block".to_owned());
+        netlist.set_latency(m, latency.map(str::to_owned));
+        for (n, d) in drivers.iter().enumerate() {
+            let pi = netlist
+                .add_pin(
+                    m,
+                    format!("in{}", n),
+                    PinKind::Integer,
+                    InterconnectDirection::Input,
+                )
+                .unwrap();
+            netlist.add_wire(*d, pi).unwrap();
+        }
+        let out = netlist
+            .add_pin(
+                m,
+                "out".to_owned(),
+                PinKind::Integer,
+                InterconnectDirection::Output,
+            )
+            .unwrap();
+        (m, out)
+    }
+
+    #[test]
+    fn test_balance() {
+        // A source feeding a multiplier straight away, and through an FFT taking a cycle per bit
+        let mut netlist = Netlist::new();
+        let (_, source) = add_block(&mut netlist, None, &[]);
+        let (fft, fft_out) = add_block(&mut netlist, Some("outputs.out / 8"), &[source]);
+        let (mult, mult_out) = add_block(&mut netlist, Some("3"), &[source, fft_out]);
+        netlist.set_position(fft, Some((0.0, 0.0)));
+        netlist.set_position(mult, Some((100.0, 50.0)));
+
+        let report = netlist.latency_report().unwrap();
+        assert_eq!(report.arrivals[&fft_out], 4);
+        assert_eq!(report.arrivals[&mult_out], 7);
+        assert_eq!(report.imbalances.len(), 1);
+        let delays: Vec<_> = report.imbalances[0]
+            .delays()
+            .map(|(x, d)| (x.name.as_str(), d))
+            .collect();
+        assert_eq!(delays, vec![("in0", 4)]);
+        assert!(report
+            .to_string()
+            .contains("`in0` arrives on cycle 0, delay it by 4"));
+
+        let mut project = Project::new(
+            "p".to_owned(),
+            Subgraph::new("top".to_owned(), vec![], vec![], netlist).unwrap(),
+        );
+        assert_eq!(project.balance_latency("top").unwrap(), 1);
+        assert!(project.sources()["delay_32_4"].contains("module delay_32_4 ("));
+        let netlist = project.top().netlist();
+        let (_, delay) = netlist
+            .modules()
+            .find(|(_, m)| m.name() == "delay_32_4")
+            .unwrap();
+        assert_eq!(delay.latency(), Some("4"));
+        // The source isn't placed, so neither is the delay
+        assert_eq!(delay.position(), None);
+        netlist.assert_invariants();
+        let report = netlist.latency_report().unwrap();
+        assert!(report.imbalances.is_empty());
+        assert_eq!(report.arrivals[&mult_out], 7);
+    }
+
+    #[test]
+    fn test_delay_clock() {
+        // A clocked block whose data arrives late, so its early input is delayed on the same clock
+        let mut netlist = Netlist::new();
+        let oscillator = netlist.add_module("oscillator".to_owned());
+        let clock = netlist
+            .add_pin(
+                oscillator,
+                "clk".to_owned(),
+                PinKind::Wire,
+                InterconnectDirection::Output,
+            )
+            .unwrap();
+        let (_, source) = add_block(&mut netlist, None, &[]);
+        let (_, late) = add_block(&mut netlist, Some("2"), &[source]);
+        let (m, _) = add_block(&mut netlist, None, &[source, late]);
+        let clk = netlist
+            .add_pin(
+                m,
+                "clk".to_owned(),
+                PinKind::Wire,
+                InterconnectDirection::Input,
+            )
+            .unwrap();
+        netlist.add_wire(clock, clk).unwrap();
+
+        // The clock comes straight from the oscillator, but only the data gets delayed
+        let report = netlist.latency_report().unwrap();
+        let inputs: Vec<_> = report.imbalances[0]
+            .inputs
+            .iter()
+            .map(|x| x.name.as_str())
+            .collect();
+        assert_eq!(inputs, vec!["in0", "in1"]);
+        let added = netlist.balance_latency().unwrap();
+        assert_eq!(added.len(), 1);
+        netlist.assert_invariants();
+        let delay = netlist.get_module(added[0].0).unwrap();
+        let delay_clk = netlist
+            .find_pin(added[0].0, "clk", InterconnectDirection::Input)
+            .unwrap();
+        assert_eq!(delay.name(), "delay_32_2");
+        assert_eq!(netlist.driver(delay_clk), Some(clock));
+    }
+
+    #[test]
+    fn test_loop() {
+        let mut netlist = Netlist::new();
+        let (a, out_a) = add_block(&mut netlist, Some("1"), &[]);
+        let (_, out_b) = add_block(&mut netlist, None, &[out_a]);
+        let feedback = netlist
+            .add_pin(
+                a,
+                "feedback".to_owned(),
+                PinKind::Integer,
+                InterconnectDirection::Input,
+            )
+            .unwrap();
+        netlist.add_wire(out_b, feedback).unwrap();
+        assert!(netlist.latency_report().is_err());
+    }
+}
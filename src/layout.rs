@@ -0,0 +1,391 @@
+//! This module lays out netlists that arrive without positions, with signals flowing left to right
+//! It's the classic layered (Sugiyama) approach: break any loops, put every module in a layer to the right of its
+//! drivers, thread wires that skip layers through placeholder nodes, then reorder each layer to untangle crossings.
+
+use crate::netlist::{Module, ModuleIndex, Netlist};
+use std::collections::HashMap;
+
+/// Distance between the left edges of neighbouring layers
+const LAYER_SPACING: f32 = 240.0;
+/// Vertical space between modules in the same layer
+const NODE_SPACING: f32 = 40.0;
+const TITLE_HEIGHT: f32 = 30.0;
+const ROW_HEIGHT: f32 = 20.0;
+/// The height a wire passing through a layer takes up
+const DUMMY_HEIGHT: f32 = 10.0;
+/// How many times to sweep the layers when reordering
+const SWEEPS: usize = 24;
+
+/// The height of a module as drawn, estimated from its pins
+fn height(m: &Module) -> f32 {
+    TITLE_HEIGHT + m.inputs().len().max(m.outputs().len()) as f32 * ROW_HEIGHT
+}
+
+/// The graph being laid out. Nodes are modules, or placeholders for wires crossing a layer
+struct Layered {
+    modules: Vec<Option<ModuleIndex>>,
+    layers: Vec<Vec<usize>>,
+    // Every edge only spans one layer, once long wires are split
+    edges: Vec<(usize, usize)>,
+}
+
+/// Find the edges that close loops, with a depth-first search from each node in turn
+fn back_edges(successors: &[Vec<usize>]) -> Vec<(usize, usize)> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        New,
+        Active,
+        Done,
+    }
+    let mut state = vec![State::New; successors.len()];
+    let mut back = vec![];
+    for root in 0..successors.len() {
+        if state[root] != State::New {
+            continue;
+        }
+        // Explicit stack of (node, next successor to visit), so deep chains can't overflow
+        let mut stack = vec![(root, 0)];
+        state[root] = State::Active;
+        while let Some((u, next)) = stack.last_mut() {
+            let u = *u;
+            match successors[u].get(*next) {
+                Some(&v) => {
+                    *next += 1;
+                    match state[v] {
+                        State::New => {
+                            state[v] = State::Active;
+                            stack.push((v, 0));
+                        }
+                        State::Active => back.push((u, v)),
+                        State::Done => {}
+                    }
+                }
+                None => {
+                    state[u] = State::Done;
+                    stack.pop();
+                }
+            }
+        }
+    }
+    back
+}
+
+/// Assign each node the longest path to it from a source, given acyclic edges
+fn longest_path(n: usize, edges: &[(usize, usize)]) -> Vec<usize> {
+    let mut in_degree = vec![0; n];
+    let mut successors = vec![vec![]; n];
+    for &(u, v) in edges {
+        in_degree[v] += 1;
+        successors[u].push(v);
+    }
+    let mut layer = vec![0; n];
+    let mut queue: Vec<_> = (0..n).filter(|x| in_degree[*x] == 0).collect();
+    while let Some(u) = queue.pop() {
+        for &v in &successors[u] {
+            layer[v] = layer[v].max(layer[u] + 1);
+            in_degree[v] -= 1;
+            if in_degree[v] == 0 {
+                queue.push(v);
+            }
+        }
+    }
+    // Modules that only drive others move up next to the first thing they drive, rather than
+    // all piling up in the first layer
+    for u in 0..n {
+        if !edges.iter().any(|(_, v)| *v == u) {
+            if let Some(first) = successors[u].iter().map(|v| layer[*v]).min() {
+                layer[u] = first - 1;
+            }
+        }
+    }
+    layer
+}
+
+/// Count the wires that cross between layer `l` and the next
+fn crossings(graph: &Layered, position: &[usize], l: usize) -> usize {
+    let between: Vec<_> = graph
+        .edges
+        .iter()
+        .filter(|(u, _)| graph.layers[l].contains(u))
+        .map(|(u, v)| (position[*u], position[*v]))
+        .collect();
+    let mut count = 0;
+    for (i, a) in between.iter().enumerate() {
+        for b in &between[i + 1..] {
+            if (a.0 < b.0 && a.1 > b.1) || (a.0 > b.0 && a.1 < b.1) {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+impl Layered {
+    fn new(netlist: &Netlist) -> Self {
+        let mut sorted: Vec<_> = netlist
+            .modules()
+            .map(|(i, m)| (m.id(), ModuleIndex(i)))
+            .collect();
+        sorted.sort_by_key(|(id, _)| *id);
+        let mut modules: Vec<_> = sorted.iter().map(|(_, i)| Some(*i)).collect();
+        let node: HashMap<_, _> = sorted
+            .iter()
+            .enumerate()
+            .map(|(n, (_, i))| (*i, n))
+            .collect();
+        let successors: Vec<Vec<_>> = sorted
+            .iter()
+            .map(|(_, i)| {
+                netlist
+                    .successors(*i)
+                    .expect("The module exists")
+                    .iter()
+                    .map(|x| node[x])
+                    .filter(|x| *x != node[i])
+                    .collect()
+            })
+            .collect();
+
+        // Turn loops around so the rest of the graph still flows left to right
+        let back = back_edges(&successors);
+        let mut edges = vec![];
+        for (u, vs) in successors.iter().enumerate() {
+            for &v in vs {
+                let edge = if back.contains(&(u, v)) {
+                    (v, u)
+                } else {
+                    (u, v)
+                };
+                if !edges.contains(&edge) {
+                    edges.push(edge);
+                }
+            }
+        }
+        let mut layer = longest_path(modules.len(), &edges);
+
+        // Split wires spanning more than one layer with a placeholder in every layer they cross
+        let mut short = vec![];
+        for (u, v) in edges {
+            let mut from = u;
+            for l in layer[u] + 1..layer[v] {
+                modules.push(None);
+                layer.push(l);
+                short.push((from, modules.len() - 1));
+                from = modules.len() - 1;
+            }
+            short.push((from, v));
+        }
+
+        let mut layers = vec![vec![]; layer.iter().max().map_or(0, |x| x + 1)];
+        for (n, l) in layer.iter().enumerate() {
+            layers[*l].push(n);
+        }
+        Self {
+            modules,
+            layers,
+            edges: short,
+        }
+    }
+
+    fn positions(&self) -> Vec<usize> {
+        let mut position = vec![0; self.modules.len()];
+        for layer in &self.layers {
+            for (p, n) in layer.iter().enumerate() {
+                position[*n] = p;
+            }
+        }
+        position
+    }
+
+    fn total_crossings(&self) -> usize {
+        let position = self.positions();
+        (0..self.layers.len().saturating_sub(1))
+            .map(|l| crossings(self, &position, l))
+            .sum()
+    }
+
+    /// Sort layer `l` by the average position of each node's neighbours in the layer before (`downwards`) or after
+    fn reorder(&mut self, l: usize, downwards: bool) {
+        let position = self.positions();
+        let mut keys: Vec<_> = self.layers[l]
+            .iter()
+            .map(|&n| {
+                let neighbours: Vec<_> = self
+                    .edges
+                    .iter()
+                    .filter_map(|&(u, v)| match downwards {
+                        true if v == n => Some(position[u]),
+                        false if u == n => Some(position[v]),
+                        _ => None,
+                    })
+                    .collect();
+                // Nodes with nothing to go by stay where they are
+                let key = if neighbours.is_empty() {
+                    position[n] as f32
+                } else {
+                    neighbours.iter().sum::<usize>() as f32 / neighbours.len() as f32
+                };
+                (key, n)
+            })
+            .collect();
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.layers[l] = keys.into_iter().map(|(_, n)| n).collect();
+    }
+
+    /// Alternate sweeps down and back up the layers, keeping the order with the fewest crossings
+    fn minimise_crossings(&mut self) {
+        let mut best = (self.total_crossings(), self.layers.clone());
+        for sweep in 0..SWEEPS {
+            if best.0 == 0 {
+                break;
+            }
+            if sweep % 2 == 0 {
+                for l in 1..self.layers.len() {
+                    self.reorder(l, true);
+                }
+            } else {
+                for l in (0..self.layers.len().saturating_sub(1)).rev() {
+                    self.reorder(l, false);
+                }
+            }
+            let crossings = self.total_crossings();
+            if crossings < best.0 {
+                best = (crossings, self.layers.clone());
+            }
+        }
+        self.layers = best.1;
+    }
+}
+
+impl Netlist {
+    /// Work out a left to right layered layout for every module, as the top left corner of each
+    pub fn layout(&self) -> HashMap<ModuleIndex, (f32, f32)> {
+        let mut graph = Layered::new(self);
+        graph.minimise_crossings();
+
+        let node_height = |n: usize| match graph.modules[n] {
+            Some(mi) => height(self.get_module(mi).expect("The module exists")),
+            None => DUMMY_HEIGHT,
+        };
+        let layer_height = |layer: &[usize]| {
+            layer.iter().map(|n| node_height(*n)).sum::<f32>()
+                + layer.len().saturating_sub(1) as f32 * NODE_SPACING
+        };
+        let tallest = graph
+            .layers
+            .iter()
+            .map(|x| layer_height(x))
+            .fold(0.0, f32::max);
+
+        // Stack each layer, centred against the tallest
+        let mut positions = HashMap::new();
+        for (l, layer) in graph.layers.iter().enumerate() {
+            let mut y = (tallest - layer_height(layer)) / 2.0;
+            for &n in layer {
+                if let Some(mi) = graph.modules[n] {
+                    positions.insert(mi, (l as f32 * LAYER_SPACING, y));
+                }
+                y += node_height(n) + NODE_SPACING;
+            }
+        }
+        positions
+    }
+
+    /// Move every module to its place in [`Netlist::layout`]
+    pub fn tidy_layout(&mut self) {
+        for (mi, position) in self.layout() {
+            self.set_position(mi, Some(position));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::{InterconnectDirection, PinKind};
+    use crate::netlist::PinIndex;
+
+    fn add_module(
+        netlist: &mut Netlist,
+        inputs: usize,
+        outputs: usize,
+    ) -> (ModuleIndex, Vec<PinIndex>, Vec<PinIndex>) {
+        let m = netlist.add_module("m".to_owned());
+        let mut add = |n, prefix, direction| -> Vec<_> {
+            (0..n)
+                .map(|x| {
+                    netlist
+                        .add_pin(m, format!("{}{}", prefix, x), PinKind::Wire, direction)
+                        .unwrap()
+                })
+                .collect()
+        };
+        let i = add(inputs, "I", InterconnectDirection::Input);
+        let o = add(outputs, "O", InterconnectDirection::Output);
+        (m, i, o)
+    }
+
+    #[test]
+    fn test_layers() {
+        // a drives b and c, b drives c, d is on its own, and e only feeds c
+        let mut netlist = Netlist::new();
+        let (a, _, out_a) = add_module(&mut netlist, 0, 1);
+        let (b, in_b, out_b) = add_module(&mut netlist, 1, 1);
+        let (c, in_c, _) = add_module(&mut netlist, 3, 0);
+        let (d, _, _) = add_module(&mut netlist, 0, 0);
+        let (e, _, out_e) = add_module(&mut netlist, 0, 1);
+        netlist.add_wire(out_a[0], in_b[0]).unwrap();
+        netlist.add_wire(out_a[0], in_c[0]).unwrap();
+        netlist.add_wire(out_b[0], in_c[1]).unwrap();
+        netlist.add_wire(out_e[0], in_c[2]).unwrap();
+        netlist.tidy_layout();
+
+        let x = |m| netlist.get_module(m).unwrap().position().unwrap().0;
+        assert_eq!(
+            [x(a), x(b), x(c), x(d), x(e)],
+            [0.0, LAYER_SPACING, 2.0 * LAYER_SPACING, 0.0, LAYER_SPACING]
+        );
+    }
+
+    #[test]
+    fn test_crossings() {
+        // Two sources wired to two sinks the wrong way round, through a layer of buffers
+        let mut netlist = Netlist::new();
+        let sources: Vec<_> = (0..3).map(|_| add_module(&mut netlist, 0, 1)).collect();
+        let buffers: Vec<_> = (0..3).map(|_| add_module(&mut netlist, 1, 1)).collect();
+        let sinks: Vec<_> = (0..3).map(|_| add_module(&mut netlist, 1, 0)).collect();
+        for n in 0..3 {
+            netlist
+                .add_wire(sources[n].2[0], buffers[2 - n].1[0])
+                .unwrap();
+            netlist
+                .add_wire(buffers[n].2[0], sinks[2 - n].1[0])
+                .unwrap();
+        }
+        let mut graph = Layered::new(&netlist);
+        assert_eq!(graph.total_crossings(), 6);
+        graph.minimise_crossings();
+        assert_eq!(graph.total_crossings(), 0);
+    }
+
+    #[test]
+    fn test_loops() {
+        // a feeds b, which feeds back into a, with c hanging off b two layers on
+        let mut netlist = Netlist::new();
+        let (a, in_a, out_a) = add_module(&mut netlist, 1, 1);
+        let (b, in_b, out_b) = add_module(&mut netlist, 1, 1);
+        let (c, in_c, _) = add_module(&mut netlist, 2, 0);
+        netlist.add_wire(out_a[0], in_b[0]).unwrap();
+        netlist.add_wire(out_b[0], in_a[0]).unwrap();
+        netlist.add_wire(out_b[0], in_c[0]).unwrap();
+        netlist.add_wire(out_a[0], in_c[1]).unwrap();
+        let positions = netlist.layout();
+        assert_eq!(positions.len(), 3);
+        assert!(positions[&a].0 < positions[&b].0);
+        assert!(positions[&b].0 < positions[&c].0);
+        // The placeholder for a's wire into c sits alongside b without overlapping it
+        let graph = Layered::new(&netlist);
+        assert_eq!(graph.modules.len(), 4);
+        assert_eq!(graph.layers[1].len(), 2);
+    }
+}
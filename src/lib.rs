@@ -1,26 +1,40 @@
 //! This module contians the functions that we'll extern out to C, to be interacted with from the GUI code
+pub mod clipboard;
+pub mod console;
+pub mod diff;
+pub mod drc;
+pub mod export;
+pub mod generators;
+pub mod latency;
+pub mod layout;
 pub mod library;
+pub mod merge;
 pub mod netlist;
 pub mod project;
+pub mod ranges;
+pub mod resources;
+pub mod save;
+pub mod script;
+pub mod simulink;
 
-use crate::netlist::{ModuleIndex, Netlist, PinIndex, WireIndex};
-use anyhow::anyhow;
+use crate::library::LibraryModule;
+use crate::netlist::{
+    ConnectionError, ModuleIndex, NameError, Netlist, NetlistEvent, PinIndex, WireIndex,
+};
+use crate::project::{SavedProject, SavedSubgraph};
+use crate::resources::ResourceReport;
+use anyhow::bail;
 use bimap::BiMap;
-use ffi::{CGraph, CModIndex, CModule, CPort, CWire, InterconnectDirection, PinKind};
+use ffi::{
+    CError, CEvent, CGraph, CModIndex, CModule, CParameter, CPlacement, CPort, CWire, ErrorKind,
+    EventKind, InterconnectDirection, PinKind,
+};
 use generational_arena::Index;
-use lazy_static::lazy_static;
-use std::{fs::File, io::Read, sync::Mutex};
-
-lazy_static! {
-    /// Global netlist that we'll refer to
-    pub static ref NETLIST: Mutex<Netlist> = Mutex::new(Netlist::new());
-    pub static ref PIN_MAP: Mutex<BiMap<PinIndex,i32>> = Mutex::new(BiMap::new());
-    pub static ref MOD_MAP: Mutex<BiMap<ModuleIndex,i32>> = Mutex::new(BiMap::new());
-    pub static ref WIRE_MAP: Mutex<BiMap<WireIndex,i32>> = Mutex::new(BiMap::new());
-}
+use std::collections::BTreeMap;
+use std::{fs::File, hash::Hash, io::Read};
 
 #[cxx::bridge(namespace = "org::cfrs")]
-mod ffi {
+pub mod ffi {
 
     #[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
     pub enum InterconnectDirection {
@@ -41,12 +55,20 @@ mod ffi {
         name: String,
     }
 
+    #[derive(Debug)]
+    pub struct CParameter {
+        name: String,
+        value: String,
+    }
+
     #[derive(Debug)]
     pub struct CModule {
         id: i32,
         name: String,
+        instance: String,
         inputs: Vec<CPort>,
         outputs: Vec<CPort>,
+        parameters: Vec<CParameter>,
     }
 
     #[derive(Debug)]
@@ -56,12 +78,81 @@ mod ffi {
         y: i32,
     }
 
+    /// Where the module `id` should be drawn, in grid space
+    #[derive(Debug)]
+    pub struct CPlacement {
+        id: i32,
+        x: f32,
+        y: f32,
+    }
+
     #[derive(Debug)]
     pub struct CGraph {
         modules: Vec<CModule>,
         wires: Vec<CWire>,
     }
 
+    /// What went wrong in a call across the FFI, mirroring `ConnectionError` for wiring problems
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum ErrorKind {
+        Ok,
+        UnknownModule,
+        UnknownPin,
+        UnknownWire,
+        IdenticalPins,
+        Compatibility,
+        Direction,
+        InputDriven,
+        InvalidName,
+        DuplicateName,
+        Script,
+        Io,
+        Parse,
+        /// The design can't be analysed, like latencies around a feedback loop
+        Analysis,
+        /// The document can't hold it, like a scripted block needing a subgraph of its own
+        Unsupported,
+    }
+
+    /// The outcome of a call across the FFI, along with the GUI id of the offending object (or -1)
+    #[derive(Debug)]
+    pub struct CError {
+        kind: ErrorKind,
+        message: String,
+        id: i32,
+    }
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum EventKind {
+        ModuleAdded,
+        ModuleRemoved,
+        PinAdded,
+        PinRemoved,
+        WireAdded,
+        WireRemoved,
+        ParameterChanged,
+        ModuleRenamed,
+        InstanceRenamed,
+        PinRenamed,
+        AttributeChanged,
+    }
+
+    /// A single change to apply to the drawn graph. Fields that don't apply to the kind of event are -1 or empty.
+    /// Pin events carry their module, name and direction, wire events carry their pins in `x` and `y`,
+    /// parameter changes carry the parameter name and its new value, renames carry the new name and other
+    /// attribute changes carry the name of the attribute, like `latency` or `position`.
+    #[derive(Debug)]
+    pub struct CEvent {
+        kind: EventKind,
+        id: i32,
+        module: i32,
+        name: String,
+        value: String,
+        direction: InterconnectDirection,
+        x: i32,
+        y: i32,
+    }
+
     #[derive(Debug)]
     pub struct CModIndex {
         generation: u64,
@@ -70,23 +161,48 @@ mod ffi {
 
     // Rust types and signatures exposed to C++.
     extern "Rust" {
-        fn add_module(name: String);
-        fn remove_module(mod_id: i32) -> i32;
+        type Document;
+
+        fn new_document() -> Box<Document>;
+        fn open_document(path: String, doc: &mut Document) -> CError;
+        fn save(self: &Document, path: String) -> CError;
+
+        fn add_module(self: &mut Document, name: String);
+        fn remove_module(self: &mut Document, mod_id: i32) -> CError;
         fn add_pin(
+            self: &mut Document,
             mod_id: i32,
             name: String,
             kind: PinKind,
             direction: InterconnectDirection,
-        ) -> i32;
-        fn remove_pin(pin_id: i32) -> i32;
-        fn add_wire(in_a_id: i32, in_b_id: i32) -> Result<()>;
-        fn remove_wire(wire_id: i32) -> i32;
+        ) -> CError;
+        fn remove_pin(self: &mut Document, pin_id: i32) -> CError;
+        fn add_wire(self: &mut Document, in_a_id: i32, in_b_id: i32) -> CError;
+        fn remove_wire(self: &mut Document, wire_id: i32) -> CError;
+        fn remove_many(self: &mut Document, mod_ids: Vec<i32>, wire_ids: Vec<i32>) -> CError;
+        fn rename_module(self: &mut Document, mod_id: i32, name: String) -> CError;
+        fn set_instance_name(self: &mut Document, mod_id: i32, instance: String) -> CError;
+        fn rename_pin(self: &mut Document, pin_id: i32, name: String) -> CError;
+        fn set_module_position(self: &mut Document, mod_id: i32, x: f32, y: f32) -> CError;
+        fn get_module_position(self: &Document, mod_id: i32, x: &mut f32, y: &mut f32) -> CError;
+        fn tidy_layout(self: &mut Document, placements: &mut Vec<CPlacement>) -> CError;
+
+        fn get_graph(self: &mut Document) -> CGraph;
+        fn poll_events(self: &mut Document) -> Vec<CEvent>;
+        fn dump_netlist(self: &Document);
 
-        fn get_graph() -> CGraph;
-        fn dump_netlist();
+        fn add_module_from_json_path(self: &mut Document, path: String, mod_id: &mut i32)
+            -> CError;
+        fn get_json_module(self: &Document, mod_id: i32, json: &mut String) -> CError;
 
-        fn add_module_from_json_path(path: String) -> Result<i32>;
-        fn get_json_module(mod_id: i32) -> String;
+        fn copy_modules(self: &Document, mod_ids: Vec<i32>, json: &mut String) -> CError;
+        fn paste_modules(self: &mut Document, json: String, mod_ids: &mut Vec<i32>) -> CError;
+
+        fn run_script(self: &mut Document, source: String, output: &mut String) -> CError;
+        fn resource_report(self: &Document, report: &mut String) -> CError;
+        fn latency_report(self: &Document, report: &mut String) -> CError;
+        fn balance_latency(self: &mut Document, added: &mut i32) -> CError;
+        fn range_report(self: &Document, report: &mut String) -> CError;
     }
 }
 
@@ -100,183 +216,1175 @@ impl CModIndex {
     }
 }
 
-pub fn add_module(name: String) {
-    let mut netlist = NETLIST.lock().expect("Lock won't panic");
-    netlist.add_module(name);
+impl CError {
+    /// The result of a call that went through
+    pub fn ok() -> Self {
+        Self::new(ErrorKind::Ok, -1, String::new())
+    }
+
+    pub fn new(kind: ErrorKind, id: i32, message: String) -> Self {
+        Self { kind, message, id }
+    }
+
+    fn unknown_module(id: i32) -> Self {
+        Self::new(
+            ErrorKind::UnknownModule,
+            id,
+            format!("No module with id {}", id),
+        )
+    }
+
+    fn unknown_pin(id: i32) -> Self {
+        Self::new(ErrorKind::UnknownPin, id, format!("No pin with id {}", id))
+    }
+
+    fn unknown_wire(id: i32) -> Self {
+        Self::new(
+            ErrorKind::UnknownWire,
+            id,
+            format!("No wire with id {}", id),
+        )
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.kind == ErrorKind::Ok
+    }
 }
 
-pub fn remove_module(mod_id: i32) -> i32 {
-    let mut netlist = NETLIST.lock().expect("Lock won't panic");
-    let mod_map = MOD_MAP.lock().expect("Lock won't panic");
-    // Get mod index from id
-    let m = if let Some(m) = mod_map.get_by_right(&mod_id) {
-        m
-    } else {
-        return -1;
-    };
-    if netlist.remove_module(*m).is_none() {
-        -1
-    } else {
-        0
+impl From<std::io::Error> for CError {
+    fn from(e: std::io::Error) -> Self {
+        Self::new(ErrorKind::Io, -1, e.to_string())
     }
 }
 
-pub fn add_pin(mod_id: i32, name: String, kind: PinKind, direction: InterconnectDirection) -> i32 {
-    let mut netlist = NETLIST.lock().expect("Lock won't panic");
-    let mod_map = MOD_MAP.lock().expect("Lock won't panic");
-    // Get mod index from id
-    let m = if let Some(m) = mod_map.get_by_right(&mod_id) {
-        m
-    } else {
-        return -1;
-    };
-    if netlist.add_pin(*m, name, kind, direction).is_none() {
-        -1
-    } else {
-        0
+impl From<serde_json::Error> for CError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::new(ErrorKind::Parse, -1, e.to_string())
     }
 }
 
-pub fn remove_pin(pin_id: i32) -> i32 {
-    let mut netlist = NETLIST.lock().expect("Lock won't panic");
-    let pin_map = PIN_MAP.lock().expect("Lock won't panic");
-    // Get pin index from id
-    let m = if let Some(m) = pin_map.get_by_right(&pin_id) {
-        m
-    } else {
-        return -1;
+/// An open design, owning its netlist along with the ids we hand out to the GUI for drawing
+#[derive(Debug, Default)]
+pub struct Document {
+    netlist: Netlist,
+    pin_map: BiMap<PinIndex, i32>,
+    mod_map: BiMap<ModuleIndex, i32>,
+    wire_map: BiMap<WireIndex, i32>,
+    // Pin and wire ids are never reused, so the GUI can hold on to them between updates
+    next_pin_id: i32,
+    next_wire_id: i32,
+    // Verilog of the generated blocks in the design, keyed by module name
+    sources: BTreeMap<String, String>,
+}
+
+/// Get the id of `key`, handing out the next free one if it doesn't have one yet
+fn assign_id<T: Hash + Eq>(map: &mut BiMap<T, i32>, next: &mut i32, key: T) -> i32 {
+    if let Some(id) = map.get_by_left(&key) {
+        return *id;
+    }
+    let id = *next;
+    *next += 1;
+    map.insert(key, id);
+    id
+}
+
+/// Turn a failed rename of the object `id` into an FFI error
+fn name_error(e: anyhow::Error, id: i32) -> CError {
+    let message = e.to_string();
+    let kind = match e.downcast::<NameError>() {
+        Ok(NameError::BadModule(_)) => ErrorKind::UnknownModule,
+        Ok(NameError::BadPin(_)) => ErrorKind::UnknownPin,
+        Ok(NameError::Empty) => ErrorKind::InvalidName,
+        Ok(NameError::Taken(_)) => ErrorKind::DuplicateName,
+        Err(_) => unreachable!("Renaming only fails with name errors"),
     };
-    if netlist.remove_pin(*m).is_none() {
-        -1
-    } else {
-        0
+    CError::new(kind, id, message)
+}
+
+impl CEvent {
+    fn new(kind: EventKind, id: i32) -> Self {
+        Self {
+            kind,
+            id,
+            module: -1,
+            name: String::new(),
+            value: String::new(),
+            direction: InterconnectDirection::Input,
+            x: -1,
+            y: -1,
+        }
     }
 }
 
-fn add_wire(in_a_id: i32, in_b_id: i32) -> anyhow::Result<()> {
-    let mut netlist = NETLIST.lock().expect("Lock won't panic");
-    let pin_map = PIN_MAP.lock().expect("Lock won't panic");
-    // Get pin indices from ids
-    let a_idx = pin_map
-        .get_by_right(&in_a_id)
-        .ok_or(anyhow!("Pin a not found"))?;
-    let b_idx = pin_map
-        .get_by_right(&in_b_id)
-        .ok_or(anyhow!("Pin b not found"))?;
-    // Try to connect
-    netlist.add_wire(*a_idx, *b_idx)?;
-    Ok(())
+/// Create a new, empty document
+pub fn new_document() -> Box<Document> {
+    Box::new(Document::new())
 }
 
-pub fn remove_wire(wire_id: i32) -> i32 {
-    let mut netlist = NETLIST.lock().expect("Lock won't panic");
-    let wire_map = WIRE_MAP.lock().expect("Lock won't panic");
-    // Get wire index
-    let idx = if let Some(w) = wire_map.get_by_right(&wire_id) {
-        w
-    } else {
-        return -1;
+/// Open a saved netlist file into `doc`, leaving it as it was if the file can't be read or parsed
+pub fn open_document(path: String, doc: &mut Document) -> CError {
+    let json = match std::fs::read_to_string(path) {
+        Ok(x) => x,
+        Err(e) => return e.into(),
     };
-    // Try to remove
-    if netlist.remove_wire(*idx).is_none() {
-        -1
-    } else {
-        0
+    match Document::from_json(&json) {
+        Ok(x) => {
+            *doc = x;
+            CError::ok()
+        }
+        Err(e) => CError::new(ErrorKind::Parse, -1, e.to_string()),
     }
 }
 
-/// Print a debug output of the netlist to stdout
-pub fn dump_netlist() {
-    let netlist = NETLIST.lock().expect("Lock won't panic");
-    println!("{:#?}", &*netlist);
-}
+impl Document {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open a document from a saved netlist file
+    pub fn open(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Load a document from json, either a bare netlist or a project of a single subgraph keeping the Verilog of
+    /// its generated blocks, as [`Document::to_json`] writes
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        if value.get("subgraphs").is_none() {
+            return Ok(Self::from_netlist(Netlist::from_saved(
+                &serde_json::from_value(value)?,
+            )?));
+        }
+        let saved: SavedProject = serde_json::from_value(value)?;
+        let [top] = saved.subgraphs.as_slice() else {
+            bail!(
+                "The project has {} subgraphs, but only a single netlist can be edited",
+                saved.subgraphs.len()
+            );
+        };
+        Ok(Self {
+            sources: saved.sources,
+            ..Self::from_netlist(Netlist::from_saved(&top.netlist)?)
+        })
+    }
+
+    /// Serialize the document to pretty-printed json. It's a bare netlist unless there's generated Verilog to
+    /// keep, which needs the project format.
+    pub fn to_json(&self) -> String {
+        if self.sources.is_empty() {
+            return self.netlist.to_json();
+        }
+        let saved = SavedProject {
+            name: "top".to_owned(),
+            top: "top".to_owned(),
+            subgraphs: vec![SavedSubgraph {
+                name: "top".to_owned(),
+                inputs: vec![],
+                outputs: vec![],
+                netlist: self.netlist.to_saved(),
+            }],
+            scripts: BTreeMap::new(),
+            sources: self.sources.clone(),
+        };
+        serde_json::to_string_pretty(&saved).unwrap()
+    }
+
+    /// Wrap an existing netlist
+    pub fn from_netlist(netlist: Netlist) -> Self {
+        Self {
+            netlist,
+            ..Self::default()
+        }
+    }
+
+    /// Get the netlist of this document
+    pub fn netlist(&self) -> &Netlist {
+        &self.netlist
+    }
+
+    /// Get the Verilog of the generated blocks in the design, keyed by module name
+    pub fn sources(&self) -> &BTreeMap<String, String> {
+        &self.sources
+    }
+
+    /// Save the document to a file
+    pub fn save(&self, path: String) -> CError {
+        match std::fs::write(path, self.to_json()) {
+            Ok(()) => CError::ok(),
+            Err(e) => e.into(),
+        }
+    }
+
+    pub fn add_module(&mut self, name: String) {
+        let mi = self.netlist.add_module(name);
+        self.mod_map.insert(
+            mi,
+            self.netlist.get_module(mi).expect("We just added it").id(),
+        );
+    }
+
+    pub fn remove_module(&mut self, mod_id: i32) -> CError {
+        // Get mod index from id
+        match self.mod_map.get_by_right(&mod_id) {
+            Some(m) if self.netlist.remove_module(*m).is_some() => CError::ok(),
+            _ => CError::unknown_module(mod_id),
+        }
+    }
+
+    pub fn add_pin(
+        &mut self,
+        mod_id: i32,
+        name: String,
+        kind: PinKind,
+        direction: InterconnectDirection,
+    ) -> CError {
+        // Get mod index from id, which can outlive its module until the next poll
+        let Some(m) = self
+            .mod_map
+            .get_by_right(&mod_id)
+            .copied()
+            .filter(|m| self.netlist.get_module(*m).is_some())
+        else {
+            return CError::unknown_module(mod_id);
+        };
+        match self.netlist.add_pin(m, name.clone(), kind, direction) {
+            Some(_) => CError::ok(),
+            None => CError::new(
+                ErrorKind::DuplicateName,
+                mod_id,
+                NameError::Taken(name).to_string(),
+            ),
+        }
+    }
+
+    pub fn remove_pin(&mut self, pin_id: i32) -> CError {
+        // Get pin index from id
+        match self.pin_map.get_by_right(&pin_id) {
+            Some(p) if self.netlist.remove_pin(*p).is_some() => CError::ok(),
+            _ => CError::unknown_pin(pin_id),
+        }
+    }
+
+    pub fn add_wire(&mut self, in_a_id: i32, in_b_id: i32) -> CError {
+        // Get pin indices from ids
+        let a_idx = match self.pin_map.get_by_right(&in_a_id) {
+            Some(x) => *x,
+            None => return CError::unknown_pin(in_a_id),
+        };
+        let b_idx = match self.pin_map.get_by_right(&in_b_id) {
+            Some(x) => *x,
+            None => return CError::unknown_pin(in_b_id),
+        };
+        // Try to connect
+        let e = match self.netlist.add_wire(a_idx, b_idx) {
+            Ok(_) => return CError::ok(),
+            Err(e) => e,
+        };
+        let message = e.to_string();
+        match e.downcast::<ConnectionError>() {
+            Ok(e) => {
+                let (kind, id) = match e {
+                    ConnectionError::BadIndex(idx) => (
+                        ErrorKind::UnknownPin,
+                        *self.pin_map.get_by_left(&idx).unwrap_or(&-1),
+                    ),
+                    ConnectionError::IdenticalPins => (ErrorKind::IdenticalPins, in_a_id),
+                    ConnectionError::Compatibility(..) => (ErrorKind::Compatibility, in_b_id),
+                    ConnectionError::Direction => (ErrorKind::Direction, in_b_id),
+                    // Point at whichever pin is the driven input
+                    ConnectionError::InputDriven => (
+                        ErrorKind::InputDriven,
+                        if self.netlist.get_pin(a_idx).is_some_and(|p| p.is_input()) {
+                            in_a_id
+                        } else {
+                            in_b_id
+                        },
+                    ),
+                };
+                CError::new(kind, id, message)
+            }
+            Err(_) => unreachable!("Adding a wire only fails with connection errors"),
+        }
+    }
+
+    pub fn remove_wire(&mut self, wire_id: i32) -> CError {
+        // Get wire index
+        match self.wire_map.get_by_right(&wire_id) {
+            Some(w) if self.netlist.remove_wire(*w).is_some() => CError::ok(),
+            _ => CError::unknown_wire(wire_id),
+        }
+    }
+
+    /// Remove a whole selection of modules and wires, either removing all of them or none at all
+    pub fn remove_many(&mut self, mod_ids: Vec<i32>, wire_ids: Vec<i32>) -> CError {
+        // Check every id before removing anything
+        let mut modules = vec![];
+        for id in mod_ids {
+            match self.mod_map.get_by_right(&id) {
+                Some(m) if self.netlist.get_module(*m).is_some() => modules.push(*m),
+                _ => return CError::unknown_module(id),
+            }
+        }
+        let mut wires = vec![];
+        for id in wire_ids {
+            match self.wire_map.get_by_right(&id) {
+                Some(w) if self.netlist.get_wire(*w).is_some() => wires.push(*w),
+                _ => return CError::unknown_wire(id),
+            }
+        }
+        self.netlist
+            .remove_many(&modules, &wires)
+            .expect("Everything was checked to exist");
+        CError::ok()
+    }
+
+    /// Change the block name of a module
+    pub fn rename_module(&mut self, mod_id: i32, name: String) -> CError {
+        match self.mod_map.get_by_right(&mod_id) {
+            Some(m) => match self.netlist.rename_module(*m, name) {
+                Ok(_) => CError::ok(),
+                Err(e) => name_error(e, mod_id),
+            },
+            None => CError::unknown_module(mod_id),
+        }
+    }
+
+    /// Set the instance name of a module, which has to be unique in the document
+    pub fn set_instance_name(&mut self, mod_id: i32, instance: String) -> CError {
+        match self.mod_map.get_by_right(&mod_id) {
+            Some(m) => match self.netlist.set_instance_name(*m, instance) {
+                Ok(_) => CError::ok(),
+                Err(e) => name_error(e, mod_id),
+            },
+            None => CError::unknown_module(mod_id),
+        }
+    }
+
+    /// Rename a pin, which has to stay unique on its module
+    pub fn rename_pin(&mut self, pin_id: i32, name: String) -> CError {
+        match self.pin_map.get_by_right(&pin_id) {
+            Some(p) => match self.netlist.rename_pin(*p, name) {
+                Ok(_) => CError::ok(),
+                Err(e) => name_error(e, pin_id),
+            },
+            None => CError::unknown_pin(pin_id),
+        }
+    }
+
+    /// Record where the module `mod_id` is drawn, so exports and saved files match the editor
+    pub fn set_module_position(&mut self, mod_id: i32, x: f32, y: f32) -> CError {
+        match self.mod_map.get_by_right(&mod_id) {
+            Some(m) if self.netlist.set_position(*m, Some((x, y))).is_some() => CError::ok(),
+            _ => CError::unknown_module(mod_id),
+        }
+    }
+
+    /// Get where the module `mod_id` is drawn, leaving `x` and `y` alone if it hasn't been placed
+    pub fn get_module_position(&self, mod_id: i32, x: &mut f32, y: &mut f32) -> CError {
+        let Some(m) = self
+            .mod_map
+            .get_by_right(&mod_id)
+            .and_then(|mi| self.netlist.get_module(*mi))
+        else {
+            return CError::unknown_module(mod_id);
+        };
+        if let Some(position) = m.position() {
+            (*x, *y) = position;
+        }
+        CError::ok()
+    }
 
-pub fn get_graph() -> CGraph {
-    // Grab the globls
-    let netlist = NETLIST.lock().expect("Lock won't panic");
-    let mut mod_map = MOD_MAP.lock().expect("Lock won't panic");
-    let mut wire_map = WIRE_MAP.lock().expect("Lock won't panic");
-    let mut pin_map = PIN_MAP.lock().expect("Lock won't panic");
-
-    // Clear all our old drawing state
-    (*mod_map).clear();
-    (*pin_map).clear();
-    (*wire_map).clear();
-
-    // Counter for the ports
-    let mut pin_id = 0i32;
-
-    // Grab the modules
-    let modules = netlist
-        .modules()
-        .map(|(i, m)| {
-            mod_map.insert(ModuleIndex(i), m.id());
-            CModule {
+    /// Lay out every module again, filling `placements` with where each one now goes
+    pub fn tidy_layout(&mut self, placements: &mut Vec<CPlacement>) -> CError {
+        self.netlist.tidy_layout();
+        placements.clear();
+        for (_, m) in self.netlist.modules() {
+            let (x, y) = m.position().expect("Every module was just placed");
+            placements.push(CPlacement { id: m.id(), x, y });
+        }
+        CError::ok()
+    }
+
+    /// Print a debug output of the netlist to stdout
+    pub fn dump_netlist(&self) {
+        println!("{:#?}", self.netlist);
+    }
+
+    /// Get the whole graph for drawing, such as after opening a document.
+    /// Ids are kept from previous calls, and any pending events are dropped as the graph already includes them.
+    pub fn get_graph(&mut self) -> CGraph {
+        self.netlist.take_events();
+        let netlist = &self.netlist;
+        let pin_map = &mut self.pin_map;
+        let next_pin_id = &mut self.next_pin_id;
+
+        // Forget about anything that no longer exists
+        self.mod_map
+            .retain(|mi, _| netlist.get_module(*mi).is_some());
+        pin_map.retain(|pi, _| netlist.get_pin(*pi).is_some());
+        self.wire_map
+            .retain(|wi, _| netlist.get_wire(*wi).is_some());
+
+        let mut port = |x: &PinIndex| {
+            let pin = netlist.get_pin(*x).expect("These will always be valid");
+            CPort {
+                id: assign_id(pin_map, next_pin_id, *x),
+                name: pin.name().to_owned(),
+            }
+        };
+        // Grab the modules
+        let mut modules = vec![];
+        for (i, m) in netlist.modules() {
+            self.mod_map.insert(ModuleIndex(i), m.id());
+            modules.push(CModule {
                 id: m.id(),
                 name: m.name().to_owned(),
-                inputs: m
-                    .inputs()
-                    .map(|x| {
-                        let pin = netlist.get_pin(*x).expect("These will always be valid");
-                        let id = pin_id;
-                        let name = pin.name().to_owned();
-                        // Increment the global id counter
-                        pin_id += 1;
-                        // Create the lookups
-                        pin_map.insert(*x, id);
-                        CPort { id, name }
-                    })
-                    .collect(),
-                outputs: m
-                    .outputs()
-                    .map(|x| {
-                        let pin = netlist.get_pin(*x).expect("These will always be valid");
-                        let id = pin_id;
-                        let name = pin.name().to_owned();
-                        // Increment the global id counter
-                        pin_id += 1;
-                        // Create the lookup
-                        pin_map.insert(*x, id);
-                        CPort { id, name }
+                instance: m.instance().to_owned(),
+                inputs: m.inputs().map(&mut port).collect(),
+                outputs: m.outputs().map(&mut port).collect(),
+                parameters: m
+                    .parameters()
+                    .iter()
+                    .map(|(name, value)| CParameter {
+                        name: name.clone(),
+                        value: value.clone(),
                     })
                     .collect(),
+            });
+        }
+        let wires = netlist
+            .wires()
+            .map(|(idx, (x, y))| CWire {
+                id: assign_id(&mut self.wire_map, &mut self.next_wire_id, WireIndex(idx)),
+                x: *self.pin_map.get_by_left(x).unwrap(),
+                y: *self.pin_map.get_by_left(y).unwrap(),
+            })
+            .collect();
+        CGraph { modules, wires }
+    }
+
+    /// Get the changes to the graph since the last call to this or `get_graph`.
+    /// Objects that were added and removed again in between are left out entirely.
+    pub fn poll_events(&mut self) -> Vec<CEvent> {
+        let mut events = vec![];
+        for e in self.netlist.take_events() {
+            // New modules come with an instance name, which gets its own event
+            let added = match e {
+                NetlistEvent::ModuleAdded(mi) => Some(mi),
+                _ => None,
+            };
+            events.extend(self.translate_event(e));
+            if let Some(mi) = added {
+                events.extend(self.translate_event(NetlistEvent::InstanceRenamed(mi)));
             }
-        })
-        .collect();
-    let wires = netlist
-        .wires()
-        .enumerate()
-        .map(|(id, (idx, (x, y)))| {
-            wire_map.insert(WireIndex(idx), id as i32);
-            CWire {
-                id: id as i32,
-                x: *(*pin_map).get_by_left(x).unwrap(),
-                y: *(*pin_map).get_by_left(y).unwrap(),
+        }
+        events
+    }
+
+    fn translate_event(&mut self, event: NetlistEvent) -> Option<CEvent> {
+        let netlist = &self.netlist;
+        let event = match event {
+            NetlistEvent::ModuleAdded(mi) => {
+                let Some(m) = netlist.get_module(mi) else {
+                    // It's gone again before the GUI heard of it, so the GUI mustn't hear of the removal either
+                    self.mod_map.remove_by_left(&mi);
+                    return None;
+                };
+                self.mod_map.insert(mi, m.id());
+                CEvent {
+                    module: m.id(),
+                    name: m.name().to_owned(),
+                    ..CEvent::new(EventKind::ModuleAdded, m.id())
+                }
             }
-        })
-        .collect();
-    CGraph { modules, wires }
-}
+            NetlistEvent::ModuleRemoved(mi) => {
+                let (_, id) = self.mod_map.remove_by_left(&mi)?;
+                CEvent {
+                    module: id,
+                    ..CEvent::new(EventKind::ModuleRemoved, id)
+                }
+            }
+            NetlistEvent::PinAdded(pi) => {
+                let p = netlist.get_pin(pi)?;
+                let id = assign_id(&mut self.pin_map, &mut self.next_pin_id, pi);
+                CEvent {
+                    module: netlist.get_module(p.parent())?.id(),
+                    name: p.name().to_owned(),
+                    direction: if p.is_input() {
+                        InterconnectDirection::Input
+                    } else {
+                        InterconnectDirection::Output
+                    },
+                    ..CEvent::new(EventKind::PinAdded, id)
+                }
+            }
+            NetlistEvent::PinRemoved(pi) => {
+                let (_, id) = self.pin_map.remove_by_left(&pi)?;
+                CEvent::new(EventKind::PinRemoved, id)
+            }
+            NetlistEvent::WireAdded(wi) => {
+                let (x, y) = netlist.get_wire(wi)?;
+                let (x, y) = (*x, *y);
+                let id = assign_id(&mut self.wire_map, &mut self.next_wire_id, wi);
+                CEvent {
+                    x: assign_id(&mut self.pin_map, &mut self.next_pin_id, x),
+                    y: assign_id(&mut self.pin_map, &mut self.next_pin_id, y),
+                    ..CEvent::new(EventKind::WireAdded, id)
+                }
+            }
+            NetlistEvent::WireRemoved(wi) => {
+                let (_, id) = self.wire_map.remove_by_left(&wi)?;
+                CEvent::new(EventKind::WireRemoved, id)
+            }
+            NetlistEvent::ModuleRenamed(mi) => {
+                let m = netlist.get_module(mi)?;
+                CEvent {
+                    module: m.id(),
+                    name: m.name().to_owned(),
+                    ..CEvent::new(EventKind::ModuleRenamed, m.id())
+                }
+            }
+            NetlistEvent::InstanceRenamed(mi) => {
+                let m = netlist.get_module(mi)?;
+                CEvent {
+                    module: m.id(),
+                    name: m.instance().to_owned(),
+                    ..CEvent::new(EventKind::InstanceRenamed, m.id())
+                }
+            }
+            NetlistEvent::PinRenamed(pi) => {
+                let p = netlist.get_pin(pi)?;
+                CEvent {
+                    module: netlist.get_module(p.parent())?.id(),
+                    name: p.name().to_owned(),
+                    ..CEvent::new(EventKind::PinRenamed, *self.pin_map.get_by_left(&pi)?)
+                }
+            }
+            NetlistEvent::ParameterChanged(mi, name) => {
+                let m = netlist.get_module(mi)?;
+                CEvent {
+                    module: m.id(),
+                    value: m.parameter(&name)?.to_owned(),
+                    name,
+                    ..CEvent::new(EventKind::ParameterChanged, m.id())
+                }
+            }
+            NetlistEvent::AttributeChanged(mi, attribute) => {
+                let id = netlist.get_module(mi)?.id();
+                CEvent {
+                    module: id,
+                    name: attribute.to_string(),
+                    ..CEvent::new(EventKind::AttributeChanged, id)
+                }
+            }
+        };
+        Some(event)
+    }
+
+    pub fn add_module_from_json_path(&mut self, path: String, mod_id: &mut i32) -> CError {
+        let mut buf = String::new();
+        if let Err(e) = File::open(path).and_then(|mut f| f.read_to_string(&mut buf)) {
+            return e.into();
+        }
+        let module: LibraryModule = match serde_json::from_str(&buf) {
+            Ok(x) => x,
+            Err(e) => return e.into(),
+        };
+        // Scripted blocks elaborate to subgraphs, which only projects have
+        let kind = if module.script.is_some() {
+            ErrorKind::Unsupported
+        } else {
+            ErrorKind::DuplicateName
+        };
+        // Generated blocks are added as what they generate, keeping its Verilog for the exports
+        let module = if module.generator.is_some() {
+            match generators::generate(&module) {
+                Ok(generated) => {
+                    self.sources
+                        .insert(generated.module.name.clone(), generated.verilog);
+                    generated.module
+                }
+                Err(e) => return CError::new(ErrorKind::Parse, -1, e.to_string()),
+            }
+        } else {
+            module
+        };
+        match self.netlist.add_module_from_library(module) {
+            Ok(mi) => {
+                *mod_id = self.netlist.get_module(mi).expect("We just added it").id();
+                self.mod_map.insert(mi, *mod_id);
+                CError::ok()
+            }
+            Err(e) => CError::new(kind, -1, e.to_string()),
+        }
+    }
+
+    pub fn get_json_module(&self, mod_id: i32, json: &mut String) -> CError {
+        // Get mod index from id
+        match self
+            .mod_map
+            .get_by_right(&mod_id)
+            .and_then(|mi| self.netlist.dump_module_to_json(*mi))
+        {
+            Some(s) => {
+                *json = s;
+                CError::ok()
+            }
+            None => CError::unknown_module(mod_id),
+        }
+    }
+
+    /// Copy the modules `mod_ids` and the wires between them into a json clipboard fragment
+    pub fn copy_modules(&self, mod_ids: Vec<i32>, json: &mut String) -> CError {
+        let mut modules = vec![];
+        for id in mod_ids {
+            match self.mod_map.get_by_right(&id) {
+                Some(mi) => modules.push(*mi),
+                None => return CError::unknown_module(id),
+            }
+        }
+        match self.netlist.copy(&modules) {
+            Some(s) => {
+                *json = s;
+                CError::ok()
+            }
+            None => CError::new(
+                ErrorKind::UnknownModule,
+                -1,
+                "A copied module no longer exists".to_owned(),
+            ),
+        }
+    }
+
+    /// Paste a clipboard fragment, filling `mod_ids` with the ids of the new modules
+    pub fn paste_modules(&mut self, json: String, mod_ids: &mut Vec<i32>) -> CError {
+        match self.netlist.paste(&json) {
+            Ok(added) => {
+                mod_ids.clear();
+                for mi in added {
+                    let id = self.netlist.get_module(mi).expect("We just added it").id();
+                    self.mod_map.insert(mi, id);
+                    mod_ids.push(id);
+                }
+                CError::ok()
+            }
+            Err(e) => CError::new(ErrorKind::Parse, -1, e.to_string()),
+        }
+    }
+
+    /// Run a Lua script typed into the console against the netlist, putting what it printed in `output`.
+    /// The changes it makes arrive through [`Document::poll_events`].
+    pub fn run_script(&mut self, source: String, output: &mut String) -> CError {
+        output.clear();
+        match console::run(&mut self.netlist, "console", &source, output) {
+            Ok(()) => CError::ok(),
+            Err(e) => CError::new(ErrorKind::Script, -1, e.to_string()),
+        }
+    }
+
+    /// Estimate the resources the netlist uses, writing a report of every module to `report`
+    pub fn resource_report(&self, report: &mut String) -> CError {
+        match self.netlist.resources("netlist") {
+            Ok(level) => {
+                *report = ResourceReport {
+                    levels: vec![level],
+                }
+                .to_string();
+                CError::ok()
+            }
+            Err(e) => CError::new(ErrorKind::Analysis, -1, e.to_string()),
+        }
+    }
+
+    /// Work out which modules have inputs arriving on different clock cycles, writing what to delay to `report`
+    pub fn latency_report(&self, report: &mut String) -> CError {
+        match self.netlist.latency_report() {
+            Ok(r) => {
+                *report = r.to_string();
+                CError::ok()
+            }
+            Err(e) => CError::new(ErrorKind::Analysis, -1, e.to_string()),
+        }
+    }
 
-pub fn add_module_from_json_path(path: String) -> anyhow::Result<i32> {
-    let mut netlist = NETLIST.lock().expect("Lock won't panic");
-    let mut file = File::open(path)?;
-    let mut buf = String::new();
-    file.read_to_string(&mut buf)?;
-    let mi = netlist.add_module_from_json(&buf)?;
-    Ok(netlist.get_module(mi).expect("We just added it").id())
+    /// Work out the range of integer values on every pin, writing the outputs that can overflow or waste bits to
+    /// `report`
+    pub fn range_report(&self, report: &mut String) -> CError {
+        match self.netlist.range_report() {
+            Ok(r) => {
+                *report = r.to_string();
+                CError::ok()
+            }
+            Err(e) => CError::new(ErrorKind::Analysis, -1, e.to_string()),
+        }
+    }
+
+    /// Insert delays wherever inputs arrive early, setting `added` to how many it took.
+    /// The Verilog of the delays is kept with the document.
+    pub fn balance_latency(&mut self, added: &mut i32) -> CError {
+        match self.netlist.balance_latency() {
+            Ok(delays) => {
+                *added = delays.len() as i32;
+                for (_, generated) in delays {
+                    self.sources
+                        .insert(generated.module.name, generated.verilog);
+                }
+                CError::ok()
+            }
+            Err(e) => CError::new(ErrorKind::Analysis, -1, e.to_string()),
+        }
+    }
 }
 
-pub fn get_json_module(mod_id: i32) -> String {
-    let netlist = NETLIST.lock().expect("Lock won't panic");
-    let mod_map = MOD_MAP.lock().expect("Lock won't panic");
-    // Get mod index from id
-    let mi = mod_map
-        .get_by_right(&mod_id)
-        .expect("This module will always exist");
-    if let Some(s) = netlist.dump_module_to_json(*mi) {
-        s
-    } else {
-        String::new()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::ResourceModel;
+
+    #[test]
+    fn test_documents_are_independent() {
+        let mut a = Document::new();
+        let mut b = Document::new();
+        a.add_module("a".to_owned());
+        let graph = a.get_graph();
+        assert_eq!(graph.modules.len(), 1);
+        assert!(b.get_graph().modules.is_empty());
+        // Ids handed out by one document mean nothing to another
+        let id = graph.modules[0].id;
+        assert_eq!(b.remove_module(id).kind(), ErrorKind::UnknownModule);
+        assert!(a.remove_module(id).is_ok());
+    }
+
+    #[test]
+    fn test_wire_through_ids() {
+        let mut doc = Document::new();
+        let mut mod_id = -1;
+        assert!(doc
+            .add_module_from_json_path("resources/logical.json".to_owned(), &mut mod_id)
+            .is_ok());
+        assert_eq!(mod_id, 0);
+        assert!(doc
+            .add_module_from_json_path("resources/logical.json".to_owned(), &mut mod_id)
+            .is_ok());
+        let graph = doc.get_graph();
+        let out = graph.modules[0].outputs[0].id;
+        let input = graph.modules[1].inputs[0].id;
+        assert!(doc.add_wire(out, input).is_ok());
+        let graph = doc.get_graph();
+        assert_eq!(graph.wires.len(), 1);
+        assert!(doc.remove_wire(graph.wires[0].id).is_ok());
+        let err = doc.remove_wire(graph.wires[0].id);
+        assert_eq!(err.kind(), ErrorKind::UnknownWire);
+        assert_eq!(err.id(), graph.wires[0].id);
+    }
+
+    #[test]
+    fn test_structured_errors() {
+        let mut doc = Document::new();
+        let mut mod_id = -1;
+        let err = doc.add_module_from_json_path("does/not/exist.json".to_owned(), &mut mod_id);
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert_eq!(mod_id, -1);
+        doc.add_module_from_json_path("resources/logical.json".to_owned(), &mut mod_id);
+        doc.add_module_from_json_path("resources/logical.json".to_owned(), &mut mod_id);
+        let graph = doc.get_graph();
+        let (a, b) = (&graph.modules[0], &graph.modules[1]);
+
+        let mut json = String::new();
+        assert!(doc.get_json_module(a.id, &mut json).is_ok());
+        assert!(json.contains("10 GbE"));
+        assert_eq!(
+            doc.get_json_module(42, &mut json).kind(),
+            ErrorKind::UnknownModule
+        );
+
+        let err = doc.add_wire(a.inputs[0].id, b.inputs[0].id);
+        assert_eq!(err.kind(), ErrorKind::Direction);
+        assert_eq!(err.message(), "A wire must connect an input to an output");
+        let err = doc.add_wire(a.inputs[0].id, a.inputs[0].id);
+        assert_eq!(err.kind(), ErrorKind::IdenticalPins);
+        let err = doc.add_wire(a.inputs[0].id, 1000);
+        assert_eq!((err.kind(), err.id()), (ErrorKind::UnknownPin, 1000));
+        assert!(doc.add_wire(a.outputs[0].id, b.inputs[0].id).is_ok());
+        let err = doc.add_wire(b.outputs[0].id, b.inputs[0].id);
+        assert_eq!(
+            (err.kind(), err.id()),
+            (ErrorKind::InputDriven, b.inputs[0].id)
+        );
+    }
+
+    #[test]
+    fn test_add_scripted_module() {
+        let mut doc = Document::new();
+        let mut id = -1;
+        let err = doc.add_module_from_json_path("resources/adder_tree.json".to_owned(), &mut id);
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        assert!(doc.get_graph().modules.is_empty());
+    }
+
+    #[test]
+    fn test_add_generated_module() {
+        let mut doc = Document::new();
+        let mut id = -1;
+        let path = std::env::temp_dir().join("cfrs_generated_block.json");
+        let path = path.to_str().unwrap().to_owned();
+        std::fs::write(
+            &path,
+            r#"{"name": "pfb", "generator": "pfb_fir", "parameters": {"taps": "8"}}"#,
+        )
+        .unwrap();
+        assert!(doc.add_module_from_json_path(path.clone(), &mut id).is_ok());
+        let graph = doc.get_graph();
+        assert_eq!(graph.modules[0].name, "pfb_fir_10_8_hamming_1_18");
+        assert!(!graph.modules[0].inputs.is_empty());
+        assert!(doc.sources()["pfb_fir_10_8_hamming_1_18"]
+            .contains("module pfb_fir_10_8_hamming_1_18 ("));
+
+        // The Verilog is saved along with the netlist, where the exports find it
+        assert!(doc.save(path.clone()).is_ok());
+        let project = project::Project::load(&path).unwrap();
+        assert!(export::verilog::verilog(&project)
+            .unwrap()
+            .contains("module pfb_fir_10_8_hamming_1_18 ("));
+        let mut reopened = Document::new();
+        assert!(open_document(path.clone(), &mut reopened).is_ok());
+        assert_eq!(reopened.sources(), doc.sources());
+        assert_eq!(reopened.netlist().to_saved(), doc.netlist().to_saved());
+
+        std::fs::write(&path, r#"{"name": "x", "generator": "xeng"}"#).unwrap();
+        let err = doc.add_module_from_json_path(path.clone(), &mut id);
+        assert_eq!(err.kind(), ErrorKind::Parse);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_add_remove_between_polls() {
+        let mut doc = Document::new();
+        doc.add_module("a".to_owned());
+        let id = doc.netlist().modules().next().unwrap().1.id();
+        assert!(doc.remove_module(id).is_ok());
+        assert!(doc.poll_events().is_empty());
+        assert_eq!(doc.remove_module(id).kind(), ErrorKind::UnknownModule);
+    }
+
+    #[test]
+    fn test_add_pin_to_removed_module() {
+        let mut doc = Document::open("resources/netlist.json").unwrap();
+        doc.get_graph();
+        assert!(doc.remove_module(0).is_ok());
+        let err = doc.add_pin(
+            0,
+            "C".to_owned(),
+            PinKind::Wire,
+            InterconnectDirection::Input,
+        );
+        assert_eq!(err.kind(), ErrorKind::UnknownModule);
+    }
+
+    #[test]
+    fn test_open_document() {
+        let mut doc = Document::new();
+        assert!(open_document("resources/netlist.json".to_owned(), &mut doc).is_ok());
+        assert!(!doc.get_graph().modules.is_empty());
+        let err = open_document("resources/missing.json".to_owned(), &mut doc);
+        assert_eq!(err.kind(), ErrorKind::Io);
+        let err = open_document("Cargo.toml".to_owned(), &mut doc);
+        assert_eq!(err.kind(), ErrorKind::Parse);
+        // A failed open leaves the document alone
+        assert!(!doc.get_graph().modules.is_empty());
+    }
+
+    #[test]
+    fn test_attribute_events() {
+        let mut doc = Document::open("resources/netlist.json").unwrap();
+        let graph = doc.get_graph();
+        assert_eq!(graph.modules[0].parameters[0].name, "width");
+        let id = graph.modules[0].id;
+        let mi = doc.netlist().find_module(0).unwrap();
+        doc.netlist
+            .set_parameter(mi, "width".to_owned(), "16".to_owned());
+        assert!(doc.set_module_position(id, 10.0, 20.5).is_ok());
+        let events = doc.poll_events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            (
+                events[0].kind,
+                events[0].name.as_str(),
+                events[0].value.as_str()
+            ),
+            (EventKind::ParameterChanged, "width", "16")
+        );
+        assert_eq!(
+            (events[1].kind, events[1].id, events[1].name.as_str()),
+            (EventKind::AttributeChanged, id, "position")
+        );
+        let (mut x, mut y) = (0.0, 0.0);
+        assert!(doc.get_module_position(id, &mut x, &mut y).is_ok());
+        assert_eq!((x, y), (10.0, 20.5));
+        assert_eq!(
+            doc.get_module_position(-5, &mut x, &mut y).kind(),
+            ErrorKind::UnknownModule
+        );
+    }
+
+    #[test]
+    fn test_poll_events() {
+        let mut doc = Document::new();
+        let mut mod_id = -1;
+        doc.add_module_from_json_path("resources/logical.json".to_owned(), &mut mod_id);
+        let events = doc.poll_events();
+        assert_eq!(events[0].kind, EventKind::ModuleAdded);
+        assert_eq!(events[0].id, mod_id);
+        assert_eq!(
+            (events[1].kind, events[1].name.as_str()),
+            (EventKind::InstanceRenamed, "10 GbE_0")
+        );
+        let pins: Vec<_> = events[2..]
+            .iter()
+            .inspect(|e| assert_eq!((e.kind, e.module), (EventKind::PinAdded, mod_id)))
+            .map(|e| e.id)
+            .collect();
+        assert!(doc.poll_events().is_empty());
+
+        // Ids survive a full refresh
+        let graph = doc.get_graph();
+        assert_eq!(graph.modules[0].inputs[0].id, pins[0]);
+
+        // A module that comes and goes between polls is never reported
+        doc.add_module("temp".to_owned());
+        let temp = doc
+            .get_graph()
+            .modules
+            .iter()
+            .find(|m| m.name == "temp")
+            .unwrap()
+            .id;
+        assert!(doc
+            .add_pin(
+                temp,
+                "O".to_owned(),
+                PinKind::Wire,
+                InterconnectDirection::Output,
+            )
+            .is_ok());
+        let err = doc.add_pin(
+            temp,
+            "O".to_owned(),
+            PinKind::Wire,
+            InterconnectDirection::Input,
+        );
+        assert_eq!((err.kind(), err.id()), (ErrorKind::DuplicateName, temp));
+        assert!(doc.remove_module(temp).is_ok());
+        let events = doc.poll_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            (events[0].kind, events[0].id),
+            (EventKind::ModuleRemoved, temp)
+        );
+
+        // Removing a module removes its wires and pins first
+        doc.add_module_from_json_path("resources/logical.json".to_owned(), &mut mod_id);
+        let graph = doc.get_graph();
+        let other = graph.modules.iter().find(|m| m.id == mod_id).unwrap();
+        assert!(doc
+            .add_wire(graph.modules[0].outputs[0].id, other.inputs[0].id)
+            .is_ok());
+        let wire = doc.poll_events();
+        assert_eq!(wire[0].kind, EventKind::WireAdded);
+        assert_eq!(
+            (wire[0].x, wire[0].y),
+            (other.inputs[0].id, graph.modules[0].outputs[0].id)
+        );
+        doc.remove_module(mod_id);
+        let kinds: Vec<_> = doc.poll_events().iter().map(|e| e.kind).collect();
+        assert_eq!(kinds[0], EventKind::WireRemoved);
+        assert_eq!(kinds.last(), Some(&EventKind::ModuleRemoved));
+    }
+
+    #[test]
+    fn test_copy_paste_ids() {
+        let mut doc = Document::open("resources/netlist.json").unwrap();
+        doc.get_graph();
+        let mut json = String::new();
+        assert!(doc.copy_modules(vec![0, 1], &mut json).is_ok());
+        assert_eq!(
+            doc.copy_modules(vec![0, 7], &mut json).kind(),
+            ErrorKind::UnknownModule
+        );
+        let mut ids = vec![];
+        assert!(doc.paste_modules(json, &mut ids).is_ok());
+        assert_eq!(ids, vec![2, 3]);
+        // The new modules can be used straight away
+        assert!(doc.remove_module(ids[0]).is_ok());
+        let err = doc.paste_modules("nonsense".to_owned(), &mut ids);
+        assert_eq!(err.kind(), ErrorKind::Parse);
+    }
+
+    #[test]
+    fn test_run_script() {
+        let mut doc = Document::open("resources/netlist.json").unwrap();
+        doc.get_graph();
+        let mut output = String::new();
+        let err = doc.run_script(
+            r#"print(netlist:name(netlist:add_module("Scripted")))"#.to_owned(),
+            &mut output,
+        );
+        assert!(err.is_ok());
+        assert_eq!(output, "Scripted\n");
+        let events = doc.poll_events();
+        assert_eq!(events[0].kind, EventKind::ModuleAdded);
+        assert!(doc.remove_module(events[0].id).is_ok());
+        let err = doc.run_script("netlist:nonsense()".to_owned(), &mut output);
+        assert_eq!(err.kind(), ErrorKind::Script);
+    }
+
+    #[test]
+    fn test_resource_report() {
+        let mut doc = Document::open("resources/netlist.json").unwrap();
+        let mut report = String::new();
+        assert!(doc.resource_report(&mut report).is_ok());
+        assert!(report.starts_with("netlist: 0 LUTs"));
+        assert!(report.contains("Sink_0 (Sink): no cost model"));
+        let source = doc.netlist().find_module(0).unwrap();
+        doc.netlist.set_resource_model(
+            source,
+            Some(ResourceModel {
+                luts: Some("width +".to_owned()),
+                ..Default::default()
+            }),
+        );
+        assert_eq!(doc.resource_report(&mut report).kind(), ErrorKind::Analysis);
+    }
+
+    #[test]
+    fn test_balance_latency() {
+        let mut doc = Document::open("resources/netlist.json").unwrap();
+        doc.get_graph();
+        // Slow down one of the sink's inputs
+        let source = doc.netlist().find_module(0).unwrap();
+        doc.netlist.set_latency(source, Some("2".to_owned()));
+        let mut report = String::new();
+        assert!(doc.latency_report(&mut report).is_ok());
+        assert_eq!(report, "Every module's inputs arrive together\n");
+        let sink = doc.netlist().find_module(1).unwrap();
+        let b = doc
+            .netlist()
+            .find_pin(sink, "B", InterconnectDirection::Input)
+            .unwrap();
+        let (wi, _) = doc.netlist().wires().find(|(_, w)| w.0 == b).unwrap();
+        doc.netlist.remove_wire(WireIndex(wi));
+        let extra = doc.netlist.add_module("Extra".to_owned());
+        let out = doc
+            .netlist
+            .add_pin(
+                extra,
+                "O".to_owned(),
+                PinKind::Wire,
+                InterconnectDirection::Output,
+            )
+            .unwrap();
+        doc.netlist.add_wire(out, b).unwrap();
+        doc.poll_events();
+
+        let mut added = 0;
+        assert!(doc.balance_latency(&mut added).is_ok());
+        assert_eq!(added, 1);
+        assert!(doc
+            .poll_events()
+            .iter()
+            .any(|e| e.kind == EventKind::ModuleAdded && e.name == "delay_1_2"));
+        // The delay's Verilog goes out with the design
+        let saved = project::Project::from_json(&doc.to_json()).unwrap();
+        assert!(export::verilog::verilog(&saved)
+            .unwrap()
+            .contains("module delay_1_2 ("));
+    }
+
+    #[test]
+    fn test_range_report() {
+        let mut doc = Document::open("resources/netlist.json").unwrap();
+        let mut report = String::new();
+        assert!(doc.range_report(&mut report).is_ok());
+        assert_eq!(report, "Every output fits its values exactly\n");
+        let source = doc.netlist().find_module(0).unwrap();
+        doc.netlist
+            .set_ranges(source, [("O".to_owned(), "range(0, 2)".to_owned())].into());
+        assert!(doc.range_report(&mut report).is_ok());
+        assert!(report.contains("output `O` ranges over 0..2, which can overflow"));
+    }
+
+    #[test]
+    fn test_tidy_layout() {
+        let mut doc = Document::open("resources/netlist.json").unwrap();
+        doc.get_graph();
+        let mut placements = vec![];
+        assert!(doc.tidy_layout(&mut placements).is_ok());
+        placements.sort_by_key(|p| p.id);
+        let xs: Vec<_> = placements.iter().map(|p| (p.id, p.x)).collect();
+        assert_eq!(xs, vec![(0, 0.0), (1, 240.0)]);
+        let source = doc.netlist().find_module(0).unwrap();
+        assert_eq!(
+            doc.netlist().get_module(source).unwrap().position(),
+            Some((placements[0].x, placements[0].y))
+        );
+    }
+
+    #[test]
+    fn test_remove_many_ids() {
+        let mut doc = Document::open("resources/netlist.json").unwrap();
+        let graph = doc.get_graph();
+        let wires: Vec<_> = graph.wires.iter().map(|w| w.id).collect();
+        let err = doc.remove_many(vec![1, 5], wires.clone());
+        assert_eq!((err.kind(), err.id()), (ErrorKind::UnknownModule, 5));
+        let err = doc.remove_many(vec![1], vec![wires[0], 9]);
+        assert_eq!((err.kind(), err.id()), (ErrorKind::UnknownWire, 9));
+        assert_eq!(doc.netlist().wires().len(), 2);
+        assert!(doc.remove_many(vec![1], wires).is_ok());
+        assert_eq!(doc.netlist().modules().len(), 1);
+        assert_eq!(doc.netlist().wires().len(), 0);
+    }
+
+    #[test]
+    fn test_rename_through_ids() {
+        let mut doc = Document::open("resources/netlist.json").unwrap();
+        let graph = doc.get_graph();
+        let sink = &graph.modules.iter().find(|m| m.id == 1).unwrap();
+        assert!(doc.set_instance_name(1, "sink".to_owned()).is_ok());
+        let err = doc.set_instance_name(0, "sink".to_owned());
+        assert_eq!((err.kind(), err.id()), (ErrorKind::DuplicateName, 0));
+        let err = doc.rename_pin(sink.inputs[0].id, "B".to_owned());
+        assert_eq!(err.kind(), ErrorKind::DuplicateName);
+        assert!(doc.rename_pin(sink.inputs[0].id, "C".to_owned()).is_ok());
+        assert_eq!(
+            doc.rename_module(1, String::new()).kind(),
+            ErrorKind::InvalidName
+        );
+        assert_eq!(
+            doc.rename_module(7, "x".to_owned()).kind(),
+            ErrorKind::UnknownModule
+        );
+
+        let events = doc.poll_events();
+        assert_eq!(
+            (events[0].kind, events[0].id, events[0].name.as_str()),
+            (EventKind::InstanceRenamed, 1, "sink")
+        );
+        assert_eq!(
+            (events[1].kind, events[1].id, events[1].name.as_str()),
+            (EventKind::PinRenamed, sink.inputs[0].id, "C")
+        );
+        assert_eq!(doc.get_graph().modules[1].instance, "sink");
     }
 }
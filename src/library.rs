@@ -3,20 +3,116 @@
 
 use crate::ffi::{InterconnectDirection, PinKind};
 use crate::netlist::{ModuleIndex, Netlist};
+use anyhow::bail;
 use serde::{Deserialize, Serialize};
-use serde_json::Result;
+use std::collections::{BTreeMap, HashSet};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LibraryModule {
-    name: String,
-    inputs: Vec<LibraryPin>,
-    outputs: Vec<LibraryPin>,
+    pub name: String,
+    #[serde(default)]
+    pub inputs: Vec<LibraryPin>,
+    #[serde(default)]
+    pub outputs: Vec<LibraryPin>,
+    /// Default parameter values for new instances
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub parameters: BTreeMap<String, String>,
+    /// Groups of pins that travel together, like the data, valid and ready of a stream
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bundles: Vec<Bundle>,
+    /// How much of the FPGA each instance takes, for utilisation estimates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceModel>,
+    /// Clock cycles from inputs to outputs, as a Lua expression like the resource costs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency: Option<String>,
+    /// The range of values on each output, as a Lua expression in the ranges on the inputs, like `"inputs.a + inputs.b"`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ranges: BTreeMap<String, String>,
+    /// A Lua script building the block from its parameters, relative to this file.
+    /// The pins of scripted blocks come from the script rather than `inputs` and `outputs`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+    /// One of the built-in CASPER blocks, generated from its parameters, see [`crate::generators`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generator: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LibraryPin {
-    name: String,
-    kind: PinKind,
+    pub name: String,
+    #[serde(with = "kind_format")]
+    pub kind: PinKind,
+}
+
+/// A named group of a block's pins, which HDL backends can carry as a single port
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bundle {
+    pub name: String,
+    /// The type of the bundle. Bundles of the same type have the same roles, and can be connected together.
+    pub interface: String,
+    /// The pin playing each role, in order
+    pub signals: Vec<BundleSignal>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleSignal {
+    pub role: String,
+    pub pin: String,
+}
+
+/// The resources a block uses, each a Lua expression in the block's parameters and the `inputs` and `outputs`
+/// tables of pin widths in bits, like `"ceil(width / 18) * taps"`. Missing entries cost nothing.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceModel {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub luts: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ffs: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dsp48s: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brams: Option<String>,
+}
+
+/// Pin kinds are written by name, but we still accept the `{"repr": n}` form older files were saved with
+mod kind_format {
+    use crate::ffi::PinKind;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Kind {
+        Name(String),
+        Legacy { repr: u8 },
+    }
+
+    pub fn serialize<S: Serializer>(kind: &PinKind, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(kind)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PinKind, D::Error> {
+        match Kind::deserialize(deserializer)? {
+            Kind::Name(name) => name.parse().map_err(D::Error::custom),
+            Kind::Legacy { repr } => [PinKind::Wire, PinKind::Integer, PinKind::Real]
+                .into_iter()
+                .find(|x| x.repr == repr)
+                .ok_or_else(|| D::Error::custom(format!("Unknown pin kind repr {}", repr))),
+        }
+    }
+}
+
+impl LibraryModule {
+    /// Check that no two pins share a name, in either direction, as saved wires find their pins by name
+    pub fn check_pins(&self) -> anyhow::Result<()> {
+        let mut names = HashSet::new();
+        for pin in self.inputs.iter().chain(&self.outputs) {
+            if !names.insert(pin.name.as_str()) {
+                bail!("`{}` has more than one pin named `{}`", self.name, pin.name);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Netlist {
@@ -44,25 +140,61 @@ impl Netlist {
             name: m.name().to_string(),
             inputs,
             outputs,
+            parameters: m.parameters().clone(),
+            bundles: m.bundles().to_vec(),
+            resources: m.resource_model().cloned(),
+            latency: m.latency().map(str::to_owned),
+            ranges: m.ranges().clone(),
+            script: None,
+            generator: None,
         })
     }
 
-    pub fn add_module_from_library(&mut self, module: LibraryModule) -> ModuleIndex {
+    /// Add an instance of the library block `module`.
+    /// Fails without adding anything if two of its pins share a name, or it's a scripted or generated block, whose
+    /// pins only exist once it's been elaborated or generated.
+    pub fn add_module_from_library(
+        &mut self,
+        module: LibraryModule,
+    ) -> anyhow::Result<ModuleIndex> {
+        if module.script.is_some() {
+            bail!(
+                "`{}` is a scripted block, which has to be elaborated before it can be added",
+                module.name
+            );
+        }
+        if let Some(generator) = &module.generator {
+            bail!(
+                "`{}` names the built-in block `{}`, which has to be generated before it can be added",
+                module.name,
+                generator
+            );
+        }
+        module.check_pins()?;
         // Add the module
         let mi = self.add_module(module.name);
+        for (name, value) in module.parameters {
+            self.set_parameter(mi, name, value);
+        }
+        self.set_bundles(mi, module.bundles);
+        self.set_resource_model(mi, module.resources);
+        self.set_latency(mi, module.latency);
+        self.set_ranges(mi, module.ranges);
         // Add all the ports
         for port in module.inputs {
-            self.add_pin(mi, port.name, port.kind, InterconnectDirection::Input);
+            self.add_pin(mi, port.name, port.kind, InterconnectDirection::Input)
+                .expect("Checked above");
         }
         for port in module.outputs {
-            self.add_pin(mi, port.name, port.kind, InterconnectDirection::Output);
+            self.add_pin(mi, port.name, port.kind, InterconnectDirection::Output)
+                .expect("Checked above");
         }
-        mi
+        Ok(mi)
     }
 
-    pub fn add_module_from_json(&mut self, mod_json: &str) -> Result<ModuleIndex> {
+    pub fn add_module_from_json(&mut self, mod_json: &str) -> anyhow::Result<ModuleIndex> {
         let module = serde_json::from_str(mod_json)?;
-        Ok(self.add_module_from_library(module))
+        self.add_module_from_library(module)
     }
 
     pub fn dump_module_to_json(&self, idx: ModuleIndex) -> Option<String> {
@@ -95,8 +227,38 @@ mod tests {
                 name: "Out".to_owned(),
                 kind: PinKind::Wire,
             }],
+            parameters: BTreeMap::new(),
+            bundles: vec![],
+            resources: None,
+            latency: None,
+            ranges: BTreeMap::new(),
+            script: None,
+            generator: None,
         };
         // Add it
-        netlist.add_module_from_library(logical);
+        netlist.add_module_from_library(logical).unwrap();
+    }
+
+    #[test]
+    fn test_duplicate_pins() {
+        let mut netlist = Netlist::new();
+        let json = r#"{"name": "Bad", "inputs": [{"name": "A", "kind": "wire"}], "outputs": [{"name": "A", "kind": "wire"}]}"#;
+        let e = netlist.add_module_from_json(json).unwrap_err();
+        assert_eq!(e.to_string(), "`Bad` has more than one pin named `A`");
+        assert_eq!(netlist.modules().len(), 0);
+    }
+
+    #[test]
+    fn test_pin_kind_format() {
+        let pin: LibraryPin =
+            serde_json::from_str(r#"{"name": "A", "kind": {"repr": 2}}"#).unwrap();
+        assert_eq!(pin.kind, PinKind::Real);
+        let pin: LibraryPin = serde_json::from_str(r#"{"name": "A", "kind": "integer"}"#).unwrap();
+        assert_eq!(pin.kind, PinKind::Integer);
+        assert_eq!(
+            serde_json::to_string(&pin).unwrap(),
+            r#"{"name":"A","kind":"integer"}"#
+        );
+        assert!(serde_json::from_str::<LibraryPin>(r#"{"name": "A", "kind": "float"}"#).is_err());
     }
 }
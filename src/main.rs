@@ -0,0 +1,196 @@
+//! Headless command line interface to CasperFlow projects, for scripting and CI
+
+use cfrs::{
+    drc::Violation, export, generators, library::LibraryModule, project::Project, simulink,
+};
+use std::{fs, process::ExitCode};
+
+const USAGE: &str = "Usage: cfrs <command> <file> [-o <output>] [<flag> <value>]...
+
+Commands:
+    drc          Run the design rule checks on a project
+    verilog      Check a project and export it as Verilog
+    vhdl         Check a project and export it as VHDL-2008
+    sv           Check a project and export it as SystemVerilog, with pin bundles as interfaces
+    rusthdl      Check a project and export it as RustHDL structs
+    constraints  Check a project and export the XDC constraints of its top level ports
+    fusesoc      Check a project and write a FuseSoC core for it, with its Verilog and constraints, into the output
+                 directory. -p sets the part, and -D NAME=VALUE adds a Verilog define
+    resources    Estimate the FPGA resources a project uses, and how much of a SNAP that is
+    latency      Report where the inputs of a project's top level modules arrive on different cycles
+    ranges       Report where a project's top level integer outputs can overflow or waste bits
+    dot          Draw the top level of a project as a Graphviz digraph
+    svg          Draw the top level of a project as an SVG schematic
+    generate     Write the Verilog of a library file naming one of the built-in CASPER blocks
+    import       Import a Simulink .slx or .mdl model as a project, mapping blocks by the built-in table or -t's
+    print        Print every subgraph of a project
+    convert      Rewrite a library file in the current format";
+
+/// Errors that make the command fail, as opposed to not being able to run at all
+#[derive(Debug)]
+enum Failure {
+    Usage,
+    Drc,
+    Other(anyhow::Error),
+}
+
+impl<E: Into<anyhow::Error>> From<E> for Failure {
+    fn from(e: E) -> Self {
+        Failure::Other(e.into())
+    }
+}
+
+/// Print the design rule violations, failing on any errors
+fn check(project: &Project) -> Result<(), Failure> {
+    let violations = project.drc();
+    for v in &violations {
+        eprintln!("{}", v);
+    }
+    if violations.iter().any(Violation::is_error) {
+        Err(Failure::Drc)
+    } else {
+        Ok(())
+    }
+}
+
+/// Write to the output file, or stdout if there isn't one
+fn emit(output: Option<&str>, contents: &str) -> Result<(), Failure> {
+    match output {
+        Some(path) => fs::write(path, contents)?,
+        None => print!("{}", contents),
+    }
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<(), Failure> {
+    let [command, input, flags @ ..] = args else {
+        return Err(Failure::Usage);
+    };
+    let (mut output, mut table) = (None, None);
+    let mut core = export::fusesoc::CoreOptions::default();
+    for pair in flags.chunks(2) {
+        match pair {
+            [flag, value] if flag == "-o" => output = Some(value.as_str()),
+            [flag, value] if flag == "-t" && command == "import" => table = Some(value.as_str()),
+            [flag, value] if flag == "-p" && command == "fusesoc" => core.part = value.clone(),
+            [flag, value] if flag == "-D" && command == "fusesoc" => {
+                let (name, value) = value.split_once('=').unwrap_or((value, "1"));
+                core.defines.insert(name.to_owned(), value.to_owned());
+            }
+            _ => return Err(Failure::Usage),
+        }
+    }
+    match command.as_str() {
+        "drc" => check(&Project::load(input)?),
+        "verilog" => {
+            let project = Project::load(input)?;
+            check(&project)?;
+            emit(output, &export::verilog::verilog(&project)?)
+        }
+        "vhdl" => {
+            let project = Project::load(input)?;
+            check(&project)?;
+            emit(output, &export::vhdl::vhdl(&project)?)
+        }
+        "sv" => {
+            let project = Project::load(input)?;
+            check(&project)?;
+            emit(output, &export::systemverilog::systemverilog(&project)?)
+        }
+        "rusthdl" => {
+            let project = Project::load(input)?;
+            check(&project)?;
+            emit(output, &export::rusthdl::rusthdl(&project)?)
+        }
+        "constraints" => {
+            let project = Project::load(input)?;
+            check(&project)?;
+            emit(output, &export::xdc::xdc(&project)?)
+        }
+        "fusesoc" => {
+            let project = Project::load(input)?;
+            check(&project)?;
+            let dir = std::path::Path::new(output.unwrap_or("."));
+            fs::create_dir_all(dir)?;
+            for (name, contents) in export::fusesoc::fusesoc(&project, &core)? {
+                fs::write(dir.join(name), contents)?;
+            }
+            Ok(())
+        }
+        "resources" => {
+            let project = Project::load(input)?;
+            emit(output, &project.resources()?.to_string())
+        }
+        "latency" => {
+            let project = Project::load(input)?;
+            emit(
+                output,
+                &project.top().netlist().latency_report()?.to_string(),
+            )
+        }
+        "ranges" => {
+            let project = Project::load(input)?;
+            emit(output, &project.top().netlist().range_report()?.to_string())
+        }
+        // Schematics are drawn unchecked, they're most useful for finding what's wrong
+        "dot" => {
+            let project = Project::load(input)?;
+            emit(
+                output,
+                &export::dot::dot(project.top().netlist(), project.name()),
+            )
+        }
+        "svg" => {
+            let project = Project::load(input)?;
+            emit(output, &export::svg::svg(project.top().netlist()))
+        }
+        "generate" => {
+            let module: LibraryModule = serde_json::from_str(&fs::read_to_string(input)?)?;
+            emit(output, &generators::generate(&module)?.verilog)
+        }
+        "import" => {
+            let table = match table {
+                Some(path) => simulink::MappingTable::load(path)?,
+                None => simulink::MappingTable::casper(),
+            };
+            let import = simulink::import(input, &table)?;
+            eprint!("{}", import);
+            let name = std::path::Path::new(input)
+                .file_stem()
+                .map_or("top".into(), |x| x.to_string_lossy());
+            emit(
+                output,
+                &(import.into_project(name.into_owned())?.to_json() + "\n"),
+            )
+        }
+        "print" => {
+            let project = Project::load(input)?;
+            let mut out = String::new();
+            for sg in project.subgraphs() {
+                out.push_str(&format!("subgraph `{}`\n{}", sg.name(), sg.netlist()));
+            }
+            emit(output, &out)
+        }
+        "convert" => {
+            let module: LibraryModule = serde_json::from_str(&fs::read_to_string(input)?)?;
+            emit(output, &(serde_json::to_string_pretty(&module)? + "\n"))
+        }
+        _ => Err(Failure::Usage),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Drc) => ExitCode::FAILURE,
+        Err(Failure::Usage) => {
+            eprintln!("{}", USAGE);
+            ExitCode::from(2)
+        }
+        Err(Failure::Other(e)) => {
+            eprintln!("cfrs: {:#}", e);
+            ExitCode::from(2)
+        }
+    }
+}
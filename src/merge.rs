@@ -0,0 +1,927 @@
+//! This module implements a three-way merge of saved netlists
+//! Modules are matched by id, pins by name and wires by the input they drive, and each of those is merged
+//! independently so that edits to different parts of the design never conflict.
+//! When both sides do disagree, "ours" is kept and an explicit conflict is recorded.
+
+use crate::ffi::InterconnectDirection;
+use crate::library::LibraryPin;
+use crate::netlist::{fresh_name, Netlist};
+use crate::save::{SavedModule, SavedNetlist, SavedPinRef, SavedWire};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "conflict", rename_all = "snake_case")]
+pub enum Conflict {
+    /// Both sides changed the same attribute of a module in different ways
+    Module {
+        module: i32,
+        attribute: String,
+        base: Option<String>,
+        ours: Option<String>,
+        theirs: Option<String>,
+    },
+    /// Both sides changed what drives the input `to` in different ways
+    Wire {
+        to: SavedPinRef,
+        base: Option<SavedPinRef>,
+        ours: Option<SavedPinRef>,
+        theirs: Option<SavedPinRef>,
+    },
+    /// A wire survived the merge but one of its pins didn't, so it was dropped
+    DanglingWire { from: SavedPinRef, to: SavedPinRef },
+    /// The merged netlist doesn't load
+    Invalid { message: String },
+}
+
+fn or_none<T: Display>(x: &Option<T>) -> String {
+    x.as_ref()
+        .map(|x| format!("`{}`", x))
+        .unwrap_or_else(|| "(none)".to_owned())
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Conflict::Module {
+                module,
+                attribute,
+                base,
+                ours,
+                theirs,
+            } => write!(
+                f,
+                "module {} {}: base {}, ours {}, theirs {}",
+                module,
+                attribute,
+                or_none(base),
+                or_none(ours),
+                or_none(theirs)
+            ),
+            Conflict::Wire {
+                to,
+                base,
+                ours,
+                theirs,
+            } => write!(
+                f,
+                "driver of {}: base {}, ours {}, theirs {}",
+                to,
+                or_none(base),
+                or_none(ours),
+                or_none(theirs)
+            ),
+            Conflict::DanglingWire { from, to } => {
+                write!(f, "wire {} -> {} lost one of its pins", from, to)
+            }
+            Conflict::Invalid { message } => {
+                write!(f, "the merged netlist doesn't load: {}", message)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MergeResult {
+    pub merged: SavedNetlist,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl MergeResult {
+    /// Check if the merge went through without any conflicts
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Merge a single value. Returns `Err` with ours if both sides changed it differently.
+fn merge3<T: PartialEq + Clone>(
+    base: Option<T>,
+    ours: Option<T>,
+    theirs: Option<T>,
+) -> Result<Option<T>, Option<T>> {
+    if ours == theirs || theirs == base {
+        Ok(ours)
+    } else if ours == base {
+        Ok(theirs)
+    } else {
+        Err(ours)
+    }
+}
+
+/// Describe an attribute in a conflict: strings as themselves and anything else as json
+fn describe<T: Serialize>(x: &T) -> Option<String> {
+    match serde_json::to_value(x).expect("Attributes serialize") {
+        serde_json::Value::Null => None,
+        serde_json::Value::String(s) => Some(s),
+        v => Some(v.to_string()),
+    }
+}
+
+/// Merge an attribute of `module` as a single value, recording a conflict if both sides changed it differently
+fn merge_attribute<T: PartialEq + Clone + Serialize>(
+    module: i32,
+    attribute: &str,
+    base: Option<&T>,
+    ours: &T,
+    theirs: &T,
+    conflicts: &mut Vec<Conflict>,
+) -> T {
+    merge3(base, Some(ours), Some(theirs))
+        .unwrap_or_else(|o| {
+            conflicts.push(Conflict::Module {
+                module,
+                attribute: attribute.to_owned(),
+                base: base.and_then(describe),
+                ours: describe(ours),
+                theirs: describe(theirs),
+            });
+            o
+        })
+        .expect("Both sides have one")
+        .clone()
+}
+
+/// Give every module that "theirs" added under an id "ours" also added a fresh id, as both sides hand out
+/// ids from the same counter. Instance and pin names they both added are made unique the same way, and references
+/// from wires are rewritten to match.
+fn renumber(base: &SavedNetlist, ours: &SavedNetlist, theirs: &SavedNetlist) -> SavedNetlist {
+    let mut next = base
+        .modules
+        .iter()
+        .chain(&ours.modules)
+        .chain(&theirs.modules)
+        .map(|m| m.id + 1)
+        .max()
+        .unwrap_or_default();
+    let mut remap = BTreeMap::new();
+    for m in &theirs.modules {
+        if base.module(m.id).is_none() && ours.module(m.id).is_some_and(|o| o != m) {
+            remap.insert(m.id, next);
+            next += 1;
+        }
+    }
+    let id = |id: i32| *remap.get(&id).unwrap_or(&id);
+    let pin = |p: &SavedPinRef| SavedPinRef {
+        module: id(p.module),
+        pin: p.pin.clone(),
+    };
+    let mut renumbered = SavedNetlist {
+        modules: theirs
+            .modules
+            .iter()
+            .map(|m| SavedModule {
+                id: id(m.id),
+                ..m.clone()
+            })
+            .collect(),
+        wires: theirs
+            .wires
+            .iter()
+            .map(|w| SavedWire {
+                from: pin(&w.from),
+                to: pin(&w.to),
+            })
+            .collect(),
+    };
+
+    // New blocks are named from the same counters too, so rename their instances that ours already uses
+    let added = |m: &SavedModule| base.module(m.id).is_none() && ours.module(m.id) != Some(m);
+    let mut used: HashSet<String> = ours
+        .modules
+        .iter()
+        .chain(renumbered.modules.iter().filter(|m| !added(m)))
+        .filter_map(|m| m.instance.clone())
+        .collect();
+    for m in &mut renumbered.modules {
+        if !added(m) {
+            continue;
+        }
+        if let Some(instance) = &mut m.instance {
+            if used.contains(instance) {
+                *instance = fresh_name(&m.name, |x| used.contains(x));
+            }
+            used.insert(instance.clone());
+        }
+    }
+
+    // Likewise for pins both sides added to a module under the same name, unless they added the same pin
+    let mut renames = vec![];
+    for m in &mut renumbered.modules {
+        let (Some(b), Some(o)) = (base.module(m.id), ours.module(m.id)) else {
+            continue;
+        };
+        let names = |m: &SavedModule| -> HashSet<String> {
+            m.inputs
+                .iter()
+                .chain(&m.outputs)
+                .map(|p| p.name.clone())
+                .collect()
+        };
+        let (in_base, in_ours) = (names(b), names(o));
+        let mut used: HashSet<_> = in_ours.union(&names(m)).cloned().collect();
+        for (pins, our_pins) in [(&mut m.inputs, &o.inputs), (&mut m.outputs, &o.outputs)] {
+            for p in pins {
+                if in_base.contains(&p.name) || !in_ours.contains(&p.name) || our_pins.contains(p) {
+                    continue;
+                }
+                let name = fresh_name(&p.name, |x| used.contains(x));
+                used.insert(name.clone());
+                renames.push((m.id, std::mem::replace(&mut p.name, name.clone()), name));
+            }
+        }
+    }
+    for w in &mut renumbered.wires {
+        for p in [&mut w.from, &mut w.to] {
+            if let Some((_, _, name)) = renames
+                .iter()
+                .find(|(m, old, _)| *m == p.module && *old == p.pin)
+            {
+                p.pin = name.clone();
+            }
+        }
+    }
+    renumbered
+}
+
+/// Merge one direction's pins of a module, keeping our ordering and appending their additions
+fn merge_pins(
+    module: i32,
+    direction: InterconnectDirection,
+    base: &[LibraryPin],
+    ours: &[LibraryPin],
+    theirs: &[LibraryPin],
+    conflicts: &mut Vec<Conflict>,
+) -> Vec<LibraryPin> {
+    let kind =
+        |pins: &[LibraryPin], name: &str| pins.iter().find(|p| p.name == name).map(|p| p.kind);
+    let mut names: Vec<&str> = ours.iter().map(|p| p.name.as_str()).collect();
+    for p in theirs {
+        if !names.contains(&p.name.as_str()) {
+            names.push(&p.name);
+        }
+    }
+    let mut pins = vec![];
+    for name in names {
+        let (b, o, t) = (kind(base, name), kind(ours, name), kind(theirs, name));
+        let merged = merge3(b, o, t).unwrap_or_else(|o| {
+            conflicts.push(Conflict::Module {
+                module,
+                attribute: format!(
+                    "{} `{}`",
+                    match direction {
+                        InterconnectDirection::Input => "input",
+                        _ => "output",
+                    },
+                    name
+                ),
+                base: b.map(|x| x.to_string()),
+                ours: o.map(|x| x.to_string()),
+                theirs: t.map(|x| x.to_string()),
+            });
+            o
+        });
+        if let Some(kind) = merged {
+            pins.push(LibraryPin {
+                name: name.to_owned(),
+                kind,
+            });
+        }
+    }
+    pins
+}
+
+/// Merge a module that survives on both sides
+fn merge_module(
+    base: Option<&SavedModule>,
+    ours: &SavedModule,
+    theirs: &SavedModule,
+    conflicts: &mut Vec<Conflict>,
+) -> SavedModule {
+    if ours == theirs {
+        return ours.clone();
+    }
+    let module = ours.id;
+    let mut conflict = |attribute: String, b: Option<&String>, o, t: Option<&String>| {
+        conflicts.push(Conflict::Module {
+            module,
+            attribute,
+            base: b.cloned(),
+            ours: o,
+            theirs: t.cloned(),
+        });
+    };
+    let name = merge3(base.map(|b| &b.name), Some(&ours.name), Some(&theirs.name))
+        .unwrap_or_else(|o| {
+            conflict(
+                "name".to_owned(),
+                base.map(|b| &b.name),
+                o.cloned(),
+                Some(&theirs.name),
+            );
+            o
+        })
+        .expect("Both sides have a name")
+        .clone();
+    let instance = merge3(
+        base.map(|b| &b.instance),
+        Some(&ours.instance),
+        Some(&theirs.instance),
+    )
+    .unwrap_or_else(|o| {
+        conflict(
+            "instance".to_owned(),
+            base.and_then(|b| b.instance.as_ref()),
+            ours.instance.clone(),
+            theirs.instance.as_ref(),
+        );
+        o
+    })
+    .cloned()
+    .flatten();
+    // Parameters and ranges are merged key by key, so edits to different ones don't conflict
+    let mut merge_map = |what: &str, map: fn(&SavedModule) -> &BTreeMap<String, String>| {
+        let keys: BTreeSet<_> = base
+            .iter()
+            .flat_map(|b| map(b).keys())
+            .chain(map(ours).keys())
+            .chain(map(theirs).keys())
+            .collect();
+        let mut merged = BTreeMap::new();
+        for key in keys {
+            let b = base.and_then(|b| map(b).get(key));
+            let (o, t) = (map(ours).get(key), map(theirs).get(key));
+            let value = merge3(b, o, t).unwrap_or_else(|o| {
+                conflict(format!("{} `{}`", what, key), b, o.cloned(), t);
+                o
+            });
+            if let Some(value) = value {
+                merged.insert(key.clone(), value.clone());
+            }
+        }
+        merged
+    };
+    let parameters = merge_map("parameter", |m| &m.parameters);
+    let ranges = merge_map("range", |m| &m.ranges);
+    let bundles = merge_attribute(
+        module,
+        "bundles",
+        base.map(|b| &b.bundles),
+        &ours.bundles,
+        &theirs.bundles,
+        conflicts,
+    );
+    let resources = merge_attribute(
+        module,
+        "resources",
+        base.map(|b| &b.resources),
+        &ours.resources,
+        &theirs.resources,
+        conflicts,
+    );
+    let latency = merge_attribute(
+        module,
+        "latency",
+        base.map(|b| &b.latency),
+        &ours.latency,
+        &theirs.latency,
+        conflicts,
+    );
+    let position = merge_attribute(
+        module,
+        "position",
+        base.map(|b| &b.position),
+        &ours.position,
+        &theirs.position,
+        conflicts,
+    );
+    let empty = vec![];
+    let inputs = merge_pins(
+        module,
+        InterconnectDirection::Input,
+        base.map_or(&empty, |b| &b.inputs),
+        &ours.inputs,
+        &theirs.inputs,
+        conflicts,
+    );
+    let outputs = merge_pins(
+        module,
+        InterconnectDirection::Output,
+        base.map_or(&empty, |b| &b.outputs),
+        &ours.outputs,
+        &theirs.outputs,
+        conflicts,
+    );
+    SavedModule {
+        id: module,
+        name,
+        instance,
+        parameters,
+        bundles,
+        position,
+        resources,
+        latency,
+        ranges,
+        inputs,
+        outputs,
+    }
+}
+
+/// Three-way merge the `ours` and `theirs` edits of the common ancestor `base`
+pub fn merge(base: &SavedNetlist, ours: &SavedNetlist, theirs: &SavedNetlist) -> MergeResult {
+    let theirs = renumber(base, ours, theirs);
+    let mut conflicts = vec![];
+
+    // Merge the modules
+    let ids: BTreeSet<_> = base
+        .modules
+        .iter()
+        .chain(&ours.modules)
+        .chain(&theirs.modules)
+        .map(|m| m.id)
+        .collect();
+    let mut modules = vec![];
+    for id in ids {
+        let (b, o, t) = (base.module(id), ours.module(id), theirs.module(id));
+        match (o, t) {
+            (Some(o), Some(t)) => modules.push(merge_module(b, o, t, &mut conflicts)),
+            // One side deleted the module, which only goes through if the other didn't touch it
+            (o, t) => match merge3(b, o, t) {
+                Ok(m) => modules.extend(m.cloned()),
+                Err(o) => {
+                    conflicts.push(Conflict::Module {
+                        module: id,
+                        attribute: "existence".to_owned(),
+                        base: b.map(|x| x.name.clone()),
+                        ours: o.map(|x| x.name.clone()),
+                        theirs: t.map(|x| x.name.clone()),
+                    });
+                    modules.extend(o.cloned());
+                }
+            },
+        }
+    }
+    let merged_modules = SavedNetlist {
+        modules,
+        wires: vec![],
+    };
+
+    // Merge the wires by the input they drive
+    let drivers = |n: &SavedNetlist| -> BTreeMap<SavedPinRef, SavedPinRef> {
+        n.wires
+            .iter()
+            .map(|w| (w.to.clone(), w.from.clone()))
+            .collect()
+    };
+    let (base_wires, our_wires, their_wires) = (drivers(base), drivers(ours), drivers(&theirs));
+    let inputs: BTreeSet<_> = base_wires
+        .keys()
+        .chain(our_wires.keys())
+        .chain(their_wires.keys())
+        .collect();
+    let mut wires = vec![];
+    for to in inputs {
+        let (b, o, t) = (base_wires.get(to), our_wires.get(to), their_wires.get(to));
+        let from = merge3(b, o, t).unwrap_or_else(|o| {
+            conflicts.push(Conflict::Wire {
+                to: to.clone(),
+                base: b.cloned(),
+                ours: o.cloned(),
+                theirs: t.cloned(),
+            });
+            o
+        });
+        let Some(from) = from else { continue };
+        // Make sure both ends still exist and agree on a type
+        let pin = |p: &SavedPinRef, direction| {
+            let m = merged_modules.module(p.module)?;
+            let pins = match direction {
+                InterconnectDirection::Input => &m.inputs,
+                _ => &m.outputs,
+            };
+            pins.iter().find(|x| x.name == p.pin).map(|x| x.kind)
+        };
+        match (
+            pin(from, InterconnectDirection::Output),
+            pin(to, InterconnectDirection::Input),
+        ) {
+            (Some(a), Some(b)) if a.compatible(b) => wires.push(SavedWire {
+                from: from.clone(),
+                to: to.clone(),
+            }),
+            _ => conflicts.push(Conflict::DanglingWire {
+                from: from.clone(),
+                to: to.clone(),
+            }),
+        }
+    }
+
+    let merged = SavedNetlist {
+        modules: merged_modules.modules,
+        wires,
+    };
+    // Anything the merge didn't foresee still has to load
+    if let Err(e) = Netlist::from_saved(&merged) {
+        conflicts.push(Conflict::Invalid {
+            message: e.to_string(),
+        });
+    }
+    MergeResult { merged, conflicts }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::PinKind;
+    use crate::library::{Bundle, BundleSignal, ResourceModel};
+    use crate::netlist::ModuleIndex;
+
+    fn load_base() -> Netlist {
+        Netlist::from_json(include_str!("../resources/netlist.json")).unwrap()
+    }
+
+    /// Load the base and edit its module 0
+    fn edit(f: impl FnOnce(&mut Netlist, ModuleIndex)) -> SavedNetlist {
+        let mut netlist = load_base();
+        let m = netlist.find_module(0).unwrap();
+        f(&mut netlist, m);
+        netlist.to_saved()
+    }
+
+    fn add_source(netlist: &mut Netlist, name: &str) {
+        let m = netlist.add_module(name.to_owned());
+        netlist.add_pin(
+            m,
+            "O".to_owned(),
+            PinKind::Wire,
+            InterconnectDirection::Output,
+        );
+    }
+
+    #[test]
+    fn test_clean_merge() {
+        let base = load_base();
+        // We change a parameter and add a module
+        let mut ours = load_base();
+        let src = ours.find_module(0).unwrap();
+        ours.set_parameter(src, "width".to_owned(), "16".to_owned());
+        add_source(&mut ours, "Ours");
+        // They add a different module under the same id and rewire to it
+        let mut theirs = load_base();
+        add_source(&mut theirs, "Theirs");
+        let sink = theirs.find_module(1).unwrap();
+        let b = theirs
+            .find_pin(sink, "B", InterconnectDirection::Input)
+            .unwrap();
+        theirs.remove_pin(b).unwrap();
+        theirs.add_pin(
+            sink,
+            "B".to_owned(),
+            PinKind::Wire,
+            InterconnectDirection::Input,
+        );
+        let out = theirs
+            .find_pin(
+                theirs.find_module(2).unwrap(),
+                "O",
+                InterconnectDirection::Output,
+            )
+            .unwrap();
+        let b = theirs
+            .find_pin(sink, "B", InterconnectDirection::Input)
+            .unwrap();
+        theirs.add_wire(out, b).unwrap();
+
+        let result = merge(&base.to_saved(), &ours.to_saved(), &theirs.to_saved());
+        assert!(result.is_clean(), "{:?}", result.conflicts);
+        let merged = Netlist::from_saved(&result.merged).unwrap();
+        let m = merged.find_module(0).unwrap();
+        assert_eq!(merged.get_module(m).unwrap().parameter("width"), Some("16"));
+        assert_eq!(
+            merged
+                .get_module(merged.find_module(2).unwrap())
+                .unwrap()
+                .name(),
+            "Ours"
+        );
+        assert_eq!(
+            merged
+                .get_module(merged.find_module(3).unwrap())
+                .unwrap()
+                .name(),
+            "Theirs"
+        );
+        let sink = merged.find_module(1).unwrap();
+        let b = merged
+            .find_pin(sink, "B", InterconnectDirection::Input)
+            .unwrap();
+        assert_eq!(
+            merged.pin_ref(merged.driver(b).unwrap()).unwrap(),
+            SavedPinRef {
+                module: 3,
+                pin: "O".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn test_same_block_added() {
+        // Both sides add a constant, which gets the same id and instance name on each
+        let base = load_base();
+        let add_constant = |value: &str| {
+            let mut netlist = load_base();
+            let m = netlist.add_module("Constant".to_owned());
+            netlist.set_parameter(m, "value".to_owned(), value.to_owned());
+            netlist.add_pin(
+                m,
+                "O".to_owned(),
+                PinKind::Integer,
+                InterconnectDirection::Output,
+            );
+            // And a differently typed input of the same name to the sink
+            let sink = netlist.find_module(1).unwrap();
+            let kind = if value == "1" {
+                PinKind::Integer
+            } else {
+                PinKind::Wire
+            };
+            let c = netlist
+                .add_pin(sink, "C".to_owned(), kind, InterconnectDirection::Input)
+                .unwrap();
+            if value == "1" {
+                let o = netlist
+                    .find_pin(m, "O", InterconnectDirection::Output)
+                    .unwrap();
+                netlist.add_wire(o, c).unwrap();
+            }
+            netlist
+        };
+        let (ours, mut theirs) = (add_constant("1"), add_constant("2"));
+        let src = theirs.find_module(0).unwrap();
+        let out = theirs
+            .find_pin(src, "O", InterconnectDirection::Output)
+            .unwrap();
+        let sink = theirs.find_module(1).unwrap();
+        let c = theirs
+            .find_pin(sink, "C", InterconnectDirection::Input)
+            .unwrap();
+        theirs.add_wire(out, c).unwrap();
+
+        let result = merge(&base.to_saved(), &ours.to_saved(), &theirs.to_saved());
+        assert!(result.is_clean(), "{:?}", result.conflicts);
+        let merged = Netlist::from_saved(&result.merged).unwrap();
+        let instances: Vec<_> = merged
+            .modules()
+            .filter(|(_, m)| m.name() == "Constant")
+            .map(|(_, m)| (m.instance(), m.parameter("value").unwrap()))
+            .collect();
+        assert_eq!(instances, vec![("Constant_0", "1"), ("Constant_1", "2")]);
+        // Their pin is renamed, and still wired from the source
+        let sink = merged.find_module(1).unwrap();
+        let c = merged
+            .find_pin(sink, "C_0", InterconnectDirection::Input)
+            .unwrap();
+        assert_eq!(merged.get_pin(c).unwrap().kind(), PinKind::Wire);
+        assert_eq!(
+            merged.pin_ref(merged.driver(c).unwrap()).unwrap(),
+            SavedPinRef {
+                module: 0,
+                pin: "O".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        // Both sides name a different block the same
+        let base = load_base();
+        let rename = |name: &str| {
+            let mut netlist = load_base();
+            let m = netlist.find_module(0).unwrap();
+            netlist.set_instance_name(m, name.to_owned()).unwrap();
+            netlist.to_saved()
+        };
+        let mut theirs = load_base();
+        let m = theirs.find_module(1).unwrap();
+        theirs.set_instance_name(m, "a".to_owned()).unwrap();
+        let result = merge(&base.to_saved(), &rename("a"), &theirs.to_saved());
+        assert!(matches!(
+            result.conflicts.as_slice(),
+            [Conflict::Invalid { .. }]
+        ));
+        assert!(result.conflicts[0]
+            .to_string()
+            .contains("`a` is already taken"));
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let base = load_base();
+        let mut ours = load_base();
+        let src = ours.find_module(0).unwrap();
+        ours.set_parameter(src, "width".to_owned(), "16".to_owned());
+        let mut theirs = load_base();
+        let src = theirs.find_module(0).unwrap();
+        theirs.set_parameter(src, "width".to_owned(), "32".to_owned());
+        // They also delete the sink we're about to wire to
+        let sink = theirs.find_module(1).unwrap();
+        theirs.remove_module(sink).unwrap();
+        let sink = ours.find_module(1).unwrap();
+        ours.add_pin(
+            sink,
+            "C".to_owned(),
+            PinKind::Wire,
+            InterconnectDirection::Input,
+        );
+
+        let result = merge(&base.to_saved(), &ours.to_saved(), &theirs.to_saved());
+        assert_eq!(
+            result.conflicts,
+            vec![
+                Conflict::Module {
+                    module: 0,
+                    attribute: "parameter `width`".to_owned(),
+                    base: Some("8".to_owned()),
+                    ours: Some("16".to_owned()),
+                    theirs: Some("32".to_owned()),
+                },
+                Conflict::Module {
+                    module: 1,
+                    attribute: "existence".to_owned(),
+                    base: Some("Sink".to_owned()),
+                    ours: Some("Sink".to_owned()),
+                    theirs: None,
+                },
+            ]
+        );
+        // Ours is kept on conflicts
+        let merged = Netlist::from_saved(&result.merged).unwrap();
+        let src = merged.find_module(0).unwrap();
+        assert_eq!(
+            merged.get_module(src).unwrap().parameter("width"),
+            Some("16")
+        );
+        let sink = merged.find_module(1).unwrap();
+        assert_eq!(merged.get_module(sink).unwrap().inputs().len(), 3);
+    }
+
+    #[test]
+    fn test_bundles() {
+        let base = load_base().to_saved();
+        let bundle = |interface: &str| Bundle {
+            name: "out".to_owned(),
+            interface: interface.to_owned(),
+            signals: vec![BundleSignal {
+                role: "data".to_owned(),
+                pin: "O".to_owned(),
+            }],
+        };
+        // Only they add a bundle, so theirs is taken
+        let theirs = edit(|n, m| {
+            n.set_bundles(m, vec![bundle("stream")]);
+        });
+        let result = merge(&base, &base, &theirs);
+        assert!(result.is_clean());
+        assert_eq!(
+            result.merged.module(0).unwrap().bundles,
+            vec![bundle("stream")]
+        );
+        // Both add a different one
+        let ours = edit(|n, m| {
+            n.set_bundles(m, vec![bundle("axis")]);
+        });
+        let result = merge(&base, &ours, &theirs);
+        assert_eq!(
+            result.merged.module(0).unwrap().bundles,
+            vec![bundle("axis")]
+        );
+        assert_eq!(
+            result.conflicts,
+            vec![Conflict::Module {
+                module: 0,
+                attribute: "bundles".to_owned(),
+                base: Some("[]".to_owned()),
+                ours: Some(
+                    r#"[{"interface":"axis","name":"out","signals":[{"pin":"O","role":"data"}]}]"#
+                        .to_owned()
+                ),
+                theirs: Some(
+                    r#"[{"interface":"stream","name":"out","signals":[{"pin":"O","role":"data"}]}]"#
+                        .to_owned()
+                ),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_positions() {
+        let base = load_base().to_saved();
+        let ours = edit(|n, m| {
+            n.set_position(m, Some((1.0, 2.0)));
+        });
+        let theirs = edit(|n, m| {
+            n.set_position(m, Some((3.0, 4.0)));
+        });
+        assert!(merge(&base, &base, &theirs).is_clean());
+        let result = merge(&base, &ours, &theirs);
+        assert_eq!(result.merged.module(0).unwrap().position, Some((1.0, 2.0)));
+        assert_eq!(
+            result.conflicts,
+            vec![Conflict::Module {
+                module: 0,
+                attribute: "position".to_owned(),
+                base: None,
+                ours: Some("[1.0,2.0]".to_owned()),
+                theirs: Some("[3.0,4.0]".to_owned()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_resources() {
+        let base = load_base().to_saved();
+        let luts = |expr: &str| ResourceModel {
+            luts: Some(expr.to_owned()),
+            ..Default::default()
+        };
+        let ours = edit(|n, m| {
+            n.set_resource_model(m, Some(luts("width")));
+        });
+        let theirs = edit(|n, m| {
+            n.set_resource_model(m, Some(luts("2 * width")));
+        });
+        let result = merge(&base, &base, &theirs);
+        assert!(result.is_clean());
+        assert_eq!(
+            result.merged.module(0).unwrap().resources,
+            Some(luts("2 * width"))
+        );
+        let result = merge(&base, &ours, &theirs);
+        assert_eq!(
+            result.conflicts,
+            vec![Conflict::Module {
+                module: 0,
+                attribute: "resources".to_owned(),
+                base: None,
+                ours: Some(r#"{"luts":"width"}"#.to_owned()),
+                theirs: Some(r#"{"luts":"2 * width"}"#.to_owned()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_latency() {
+        let base = load_base().to_saved();
+        let ours = edit(|n, m| {
+            n.set_latency(m, Some("2".to_owned()));
+        });
+        let theirs = edit(|n, m| {
+            n.set_latency(m, Some("taps".to_owned()));
+        });
+        let result = merge(&base, &ours, &base);
+        assert!(result.is_clean());
+        assert_eq!(
+            result.merged.module(0).unwrap().latency.as_deref(),
+            Some("2")
+        );
+        let result = merge(&base, &ours, &theirs);
+        assert_eq!(
+            result.conflicts,
+            vec![Conflict::Module {
+                module: 0,
+                attribute: "latency".to_owned(),
+                base: None,
+                ours: Some("2".to_owned()),
+                theirs: Some("taps".to_owned()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_ranges() {
+        let base = load_base().to_saved();
+        let range = |n: &mut Netlist, m, pin: &str, range: &str| {
+            n.set_ranges(m, BTreeMap::from([(pin.to_owned(), range.to_owned())]));
+        };
+        // Ranges of different outputs merge cleanly
+        let ours = edit(|n, m| range(n, m, "O", "range(0, 255)"));
+        let theirs = edit(|n, m| range(n, m, "P", "range(-1, 1)"));
+        let result = merge(&base, &ours, &theirs);
+        assert!(result.is_clean());
+        assert_eq!(result.merged.module(0).unwrap().ranges.len(), 2);
+        // The same one doesn't
+        let theirs = edit(|n, m| range(n, m, "O", "range(0, 15)"));
+        let result = merge(&base, &ours, &theirs);
+        assert_eq!(
+            result.conflicts,
+            vec![Conflict::Module {
+                module: 0,
+                attribute: "range `O`".to_owned(),
+                base: None,
+                ours: Some("range(0, 255)".to_owned()),
+                theirs: Some("range(0, 15)".to_owned()),
+            }]
+        );
+    }
+}
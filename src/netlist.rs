@@ -1,9 +1,14 @@
 //! This module defines the internal graph structure of the netlist
 
 use crate::ffi::{InterconnectDirection, PinKind};
+use crate::library::{Bundle, ResourceModel};
 use anyhow::bail;
 use generational_arena::{Arena, Index};
-use std::fmt::Display;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fmt::Display,
+    str::FromStr,
+};
 use thiserror::Error;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -85,6 +90,16 @@ impl Display for PinKind {
 }
 
 impl PinKind {
+    /// The width in bits of a signal of this kind, as the HDL backends declare it
+    pub fn width(&self) -> u32 {
+        match *self {
+            PinKind::Wire => 1,
+            PinKind::Integer => 32,
+            PinKind::Real => 64,
+            _ => unreachable!(),
+        }
+    }
+
     /// Check to see if this pin kind is compatible with another
     pub fn compatible(&self, other: PinKind) -> bool {
         // Right now, just check equality. Eventually we want to use some casting rules
@@ -92,6 +107,19 @@ impl PinKind {
     }
 }
 
+impl FromStr for PinKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "wire" => PinKind::Wire,
+            "integer" => PinKind::Integer,
+            "real" => PinKind::Real,
+            _ => bail!("Unknown pin kind `{}`", s),
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct Pin {
     name: String,
@@ -140,6 +168,11 @@ impl Pin {
     pub fn kind(&self) -> PinKind {
         self.kind
     }
+
+    /// Get the index of the module this pin belongs to
+    pub fn parent(&self) -> ModuleIndex {
+        self.parent
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -149,15 +182,36 @@ pub struct Module {
     outputs: Vec<PinIndex>,
     // Globally unique module id
     id: i32,
+    // Free-form block parameters, keyed by parameter name
+    parameters: BTreeMap<String, String>,
+    // The name of this particular instance of the block, unique in the netlist
+    instance: String,
+    // Groups of pins from the library definition, referenced by pin name
+    bundles: Vec<Bundle>,
+    // Where the module is drawn, if it has been placed
+    position: Option<(f32, f32)>,
+    // The cost model from the library definition
+    resources: Option<ResourceModel>,
+    // The latency expression from the library definition
+    latency: Option<String>,
+    // The value range expressions from the library definition, keyed by output name
+    ranges: BTreeMap<String, String>,
 }
 
 impl Module {
-    pub fn new(name: String, id: i32) -> Self {
+    pub fn new(name: String, id: i32, instance: String) -> Self {
         Self {
             name,
             inputs: vec![],
             outputs: vec![],
             id,
+            parameters: BTreeMap::new(),
+            instance,
+            bundles: vec![],
+            position: None,
+            resources: None,
+            latency: None,
+            ranges: BTreeMap::new(),
         }
     }
 
@@ -167,12 +221,12 @@ impl Module {
     }
 
     /// Get an iterator over the inputs of the module
-    pub fn inputs(&self) -> std::slice::Iter<PinIndex> {
+    pub fn inputs(&self) -> std::slice::Iter<'_, PinIndex> {
         self.inputs.iter()
     }
 
     /// Get an iterator over the outputs of the module
-    pub fn outputs(&self) -> std::slice::Iter<PinIndex> {
+    pub fn outputs(&self) -> std::slice::Iter<'_, PinIndex> {
         self.outputs.iter()
     }
 
@@ -180,6 +234,98 @@ impl Module {
     pub fn id(&self) -> i32 {
         self.id
     }
+
+    /// Get the parameters of the module, ordered by name
+    pub fn parameters(&self) -> &BTreeMap<String, String> {
+        &self.parameters
+    }
+
+    /// Get the pin bundles of the module
+    pub fn bundles(&self) -> &[Bundle] {
+        &self.bundles
+    }
+
+    /// Get the resource cost model of the module, if its library block declared one
+    pub fn resource_model(&self) -> Option<&ResourceModel> {
+        self.resources.as_ref()
+    }
+
+    /// Get the expression for the module's latency in clock cycles, if its library block declared one
+    pub fn latency(&self) -> Option<&str> {
+        self.latency.as_deref()
+    }
+
+    /// Get the expressions for the range of values on each output, if its library block declared them
+    pub fn ranges(&self) -> &BTreeMap<String, String> {
+        &self.ranges
+    }
+
+    /// Get the position of the module's top left corner, if it has been placed
+    pub fn position(&self) -> Option<(f32, f32)> {
+        self.position
+    }
+
+    /// Get the value of a single parameter
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters.get(name).map(String::as_str)
+    }
+
+    /// Get the instance name of this module, as opposed to the name of the block it's an instance of
+    pub fn instance(&self) -> &str {
+        &self.instance
+    }
+}
+
+/// A change made to a netlist, recorded so frontends can update incrementally
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetlistEvent {
+    ModuleAdded(ModuleIndex),
+    ModuleRemoved(ModuleIndex),
+    PinAdded(PinIndex),
+    PinRemoved(PinIndex),
+    WireAdded(WireIndex),
+    WireRemoved(WireIndex),
+    /// The parameter of the given name was set on the module
+    ParameterChanged(ModuleIndex, String),
+    ModuleRenamed(ModuleIndex),
+    InstanceRenamed(ModuleIndex),
+    PinRenamed(PinIndex),
+    /// One of the module's other attributes was given a different value
+    AttributeChanged(ModuleIndex, Attribute),
+}
+
+/// The attributes of a module besides its names, parameters and pins
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Attribute {
+    Bundles,
+    Resources,
+    Latency,
+    Ranges,
+    Position,
+}
+
+impl Display for Attribute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Attribute::Bundles => "bundles",
+                Attribute::Resources => "resources",
+                Attribute::Latency => "latency",
+                Attribute::Ranges => "ranges",
+                Attribute::Position => "position",
+            }
+        )
+    }
+}
+
+/// Come up with a name like `name_2` that isn't `used` yet
+pub(crate) fn fresh_name(name: &str, used: impl Fn(&str) -> bool) -> String {
+    (0..)
+        .map(|n| format!("{}_{}", name, n))
+        .find(|x| !used(x))
+        .expect("There's always a free name")
 }
 
 #[derive(Debug)]
@@ -188,6 +334,8 @@ pub struct Netlist {
     pins: Arena<Pin>,
     wires: Arena<(PinIndex, PinIndex)>,
     next_mod_idx: i32,
+    // Changes since the events were last taken
+    events: Vec<NetlistEvent>,
 }
 
 impl Default for Netlist {
@@ -203,21 +351,28 @@ impl Netlist {
             pins: Arena::new(),
             wires: Arena::new(),
             next_mod_idx: 0,
+            events: vec![],
         }
     }
 
+    /// Take every change made since the last call, oldest first.
+    /// Removals always come after the additions of the same object.
+    pub fn take_events(&mut self) -> Vec<NetlistEvent> {
+        std::mem::take(&mut self.events)
+    }
+
     /// Get an iterator over the modules
-    pub fn modules(&self) -> generational_arena::Iter<Module> {
+    pub fn modules(&self) -> generational_arena::Iter<'_, Module> {
         self.modules.iter()
     }
 
     /// Get an iterator over the pins
-    pub fn pins(&self) -> generational_arena::Iter<Pin> {
+    pub fn pins(&self) -> generational_arena::Iter<'_, Pin> {
         self.pins.iter()
     }
 
     /// Get an iterator over the wires
-    pub fn wires(&self) -> generational_arena::Iter<(PinIndex, PinIndex)> {
+    pub fn wires(&self) -> generational_arena::Iter<'_, (PinIndex, PinIndex)> {
         self.wires.iter()
     }
 
@@ -236,8 +391,42 @@ impl Netlist {
         self.wires.get(idx.0)
     }
 
+    /// Find a module by its globally unique id
+    pub fn find_module(&self, id: i32) -> Option<ModuleIndex> {
+        self.modules()
+            .find(|(_, m)| m.id == id)
+            .map(|(i, _)| ModuleIndex(i))
+    }
+
+    /// Find a pin of the module `idx` by its name and direction
+    pub fn find_pin(
+        &self,
+        idx: ModuleIndex,
+        name: &str,
+        direction: InterconnectDirection,
+    ) -> Option<PinIndex> {
+        let m = self.get_module(idx)?;
+        let mut pins = match direction {
+            InterconnectDirection::Input => m.inputs(),
+            InterconnectDirection::Output => m.outputs(),
+            _ => unreachable!(),
+        };
+        pins.find(|x| self.get_pin(**x).is_some_and(|p| p.name == name))
+            .copied()
+    }
+
+    /// Check whether the module `idx` has a pin called `name`, in either direction
+    pub fn has_pin_named(&self, idx: ModuleIndex, name: &str) -> bool {
+        self.get_module(idx).is_some_and(|m| {
+            m.inputs()
+                .chain(m.outputs())
+                .any(|x| self.get_pin(*x).is_some_and(|p| p.name == name))
+        })
+    }
+
     /// Add a pin to the netlist, associating it with a module by module index `idx`.
-    /// Returns `None` if no module exists in the given index.
+    /// Returns `None` if no module exists in the given index, or it already has a pin called `name`,
+    /// as saved wires find their pins by name.
     pub fn add_pin(
         &mut self,
         idx: ModuleIndex,
@@ -245,6 +434,9 @@ impl Netlist {
         kind: PinKind,
         direction: InterconnectDirection,
     ) -> Option<PinIndex> {
+        if self.has_pin_named(idx, &name) {
+            return None;
+        }
         // Grab the module and bail if it doesn't exist
         let m = self.modules.get_mut(idx.0)?;
         // If it does, insert the pin
@@ -255,6 +447,7 @@ impl Netlist {
             InterconnectDirection::Output => m.outputs.push(pi),
             _ => unreachable!(),
         };
+        self.events.push(NetlistEvent::PinAdded(pi));
         // And return the index
         Some(pi)
     }
@@ -280,7 +473,7 @@ impl Netlist {
                     if let Some(p) = self.pins.get_mut(pi.0) {
                         match &mut p.interconnect {
                             Interconnect::Output { connections } => {
-                                connections.retain(|x| *x != pi)
+                                connections.retain(|x| *x != idx)
                             }
                             _ => unreachable!(),
                         }
@@ -301,7 +494,16 @@ impl Netlist {
             }
         }
         // Finally, remove all the wires that invlove this pin
-        self.wires.retain(|_, (x, y)| (*x != idx) && (*y != idx));
+        let wires: Vec<_> = self
+            .wires()
+            .filter(|(_, (x, y))| (*x == idx) || (*y == idx))
+            .map(|(i, _)| i)
+            .collect();
+        for wi in wires {
+            self.wires.remove(wi);
+            self.events.push(NetlistEvent::WireRemoved(WireIndex(wi)));
+        }
+        self.events.push(NetlistEvent::PinRemoved(idx));
         // We're done!
         Some(())
     }
@@ -313,7 +515,152 @@ impl Netlist {
         // Increment the counter
         self.next_mod_idx += 1;
         // Add the module to the arena and return the index
-        ModuleIndex(self.modules.insert(Module::new(name, id)))
+        let instance = self.fresh_instance_name(&name);
+        let mi = ModuleIndex(self.modules.insert(Module::new(name, id, instance)));
+        self.events.push(NetlistEvent::ModuleAdded(mi));
+        mi
+    }
+
+    /// Add a module with a specific id, as when loading a saved netlist.
+    /// Returns `None` if a module with that id already exists, or the id leaves no room for fresh ones after it.
+    pub fn add_module_with_id(&mut self, name: String, id: i32) -> Option<ModuleIndex> {
+        if self.modules().any(|(_, m)| m.id == id) {
+            return None;
+        }
+        // Make sure freshly added modules never collide with this one
+        self.next_mod_idx = self.next_mod_idx.max(id.checked_add(1)?);
+        let instance = self.fresh_instance_name(&name);
+        let mi = ModuleIndex(self.modules.insert(Module::new(name, id, instance)));
+        self.events.push(NetlistEvent::ModuleAdded(mi));
+        Some(mi)
+    }
+
+    /// Come up with an instance name for a new block `name` that isn't used yet, like `Inverter_2`
+    fn fresh_instance_name(&self, name: &str) -> String {
+        let used: HashSet<_> = self.modules().map(|(_, m)| m.instance.as_str()).collect();
+        fresh_name(name, |x| used.contains(x))
+    }
+
+    /// Set the parameter `name` of the module `idx` to `value`, returning the previous value.
+    /// An event is only recorded if that changes it. Returns `None` if no module exists in the given index.
+    pub fn set_parameter(
+        &mut self,
+        idx: ModuleIndex,
+        name: String,
+        value: String,
+    ) -> Option<Option<String>> {
+        let m = self.modules.get_mut(idx.0)?;
+        let old = m.parameters.insert(name.clone(), value);
+        if old.as_ref() != m.parameters.get(&name) {
+            self.events.push(NetlistEvent::ParameterChanged(idx, name));
+        }
+        Some(old)
+    }
+
+    /// Set an attribute of the module `idx` to `value`, recording an event if that changes it
+    fn set_attribute<T: PartialEq>(
+        &mut self,
+        idx: ModuleIndex,
+        attribute: Attribute,
+        field: fn(&mut Module) -> &mut T,
+        value: T,
+    ) -> Option<()> {
+        let old = field(self.modules.get_mut(idx.0)?);
+        if *old != value {
+            *old = value;
+            self.events
+                .push(NetlistEvent::AttributeChanged(idx, attribute));
+        }
+        Some(())
+    }
+
+    /// Replace the pin bundles of the module `idx`.
+    /// Returns `None` if no module exists in the given index.
+    pub fn set_bundles(&mut self, idx: ModuleIndex, bundles: Vec<Bundle>) -> Option<()> {
+        self.set_attribute(idx, Attribute::Bundles, |m| &mut m.bundles, bundles)
+    }
+
+    /// Replace the resource cost model of the module `idx`.
+    /// Returns `None` if no module exists in the given index.
+    pub fn set_resource_model(
+        &mut self,
+        idx: ModuleIndex,
+        resources: Option<ResourceModel>,
+    ) -> Option<()> {
+        self.set_attribute(idx, Attribute::Resources, |m| &mut m.resources, resources)
+    }
+
+    /// Replace the latency expression of the module `idx`.
+    /// Returns `None` if no module exists in the given index.
+    pub fn set_latency(&mut self, idx: ModuleIndex, latency: Option<String>) -> Option<()> {
+        self.set_attribute(idx, Attribute::Latency, |m| &mut m.latency, latency)
+    }
+
+    /// Replace the output range expressions of the module `idx`.
+    /// Returns `None` if no module exists in the given index.
+    pub fn set_ranges(&mut self, idx: ModuleIndex, ranges: BTreeMap<String, String>) -> Option<()> {
+        self.set_attribute(idx, Attribute::Ranges, |m| &mut m.ranges, ranges)
+    }
+
+    /// Place the module `idx`, or unplace it with `None`.
+    /// Returns `None` if no module exists in the given index.
+    pub fn set_position(&mut self, idx: ModuleIndex, position: Option<(f32, f32)>) -> Option<()> {
+        self.set_attribute(idx, Attribute::Position, |m| &mut m.position, position)
+    }
+
+    /// Change the block name of the module `idx`, returning the old one
+    pub fn rename_module(&mut self, idx: ModuleIndex, name: String) -> anyhow::Result<String> {
+        if name.is_empty() {
+            bail!(NameError::Empty);
+        }
+        let m = self
+            .modules
+            .get_mut(idx.0)
+            .ok_or(NameError::BadModule(idx))?;
+        let old = std::mem::replace(&mut m.name, name);
+        self.events.push(NetlistEvent::ModuleRenamed(idx));
+        Ok(old)
+    }
+
+    /// Set the instance name of the module `idx`, returning the old one.
+    /// Will error if another module in the netlist already has that instance name.
+    pub fn set_instance_name(
+        &mut self,
+        idx: ModuleIndex,
+        instance: String,
+    ) -> anyhow::Result<String> {
+        if instance.is_empty() {
+            bail!(NameError::Empty);
+        }
+        if self
+            .modules()
+            .any(|(i, m)| i != idx.0 && m.instance == instance)
+        {
+            bail!(NameError::Taken(instance));
+        }
+        let m = self
+            .modules
+            .get_mut(idx.0)
+            .ok_or(NameError::BadModule(idx))?;
+        let old = std::mem::replace(&mut m.instance, instance);
+        self.events.push(NetlistEvent::InstanceRenamed(idx));
+        Ok(old)
+    }
+
+    /// Rename the pin `idx`, returning the old name.
+    /// Will error if another pin on the same module, in either direction, already has that name.
+    pub fn rename_pin(&mut self, idx: PinIndex, name: String) -> anyhow::Result<String> {
+        if name.is_empty() {
+            bail!(NameError::Empty);
+        }
+        let p = self.get_pin(idx).ok_or(NameError::BadPin(idx))?;
+        if p.name != name && self.has_pin_named(p.parent, &name) {
+            bail!(NameError::Taken(name));
+        }
+        let p = self.pins.get_mut(idx.0).expect("Checked above");
+        let old = std::mem::replace(&mut p.name, name);
+        self.events.push(NetlistEvent::PinRenamed(idx));
+        Ok(old)
     }
 
     /// Remove a module by it's module index `idx`, returning None if no such module exists
@@ -327,6 +674,7 @@ impl Netlist {
         m.outputs.iter().for_each(|x| {
             self.remove_pin(*x);
         });
+        self.events.push(NetlistEvent::ModuleRemoved(idx));
         // We're done!
         Some(())
     }
@@ -372,7 +720,9 @@ impl Netlist {
             _ => unreachable!(),
         }
         // Finally, add the wire to the list of wires
-        Ok(WireIndex(self.wires.insert((input_idx, output_idx))))
+        let wi = WireIndex(self.wires.insert((input_idx, output_idx)));
+        self.events.push(NetlistEvent::WireAdded(wi));
+        Ok(wi)
     }
 
     /// Remove a wire given its wire index, returning None if the index points to nothing
@@ -402,9 +752,306 @@ impl Netlist {
             }
             _ => unreachable!(),
         }
+        self.events.push(NetlistEvent::WireRemoved(idx));
         // All done
         Some(())
     }
+
+    /// Remove the wires `wires` and the modules `modules` (along with everything connected to them) in one go.
+    /// If any of them doesn't exist, returns `None` and leaves the netlist untouched.
+    pub fn remove_many(&mut self, modules: &[ModuleIndex], wires: &[WireIndex]) -> Option<()> {
+        // Check everything up front so we never stop halfway through
+        if !modules.iter().all(|x| self.modules.contains(x.0))
+            || !wires.iter().all(|x| self.wires.contains(x.0))
+        {
+            return None;
+        }
+        // Wires can go away with an earlier module or show up twice, so don't mind if they're already gone
+        for wi in wires {
+            self.remove_wire(*wi);
+        }
+        for mi in modules {
+            self.remove_module(*mi);
+        }
+        Some(())
+    }
+
+    /// Check that the modules, pins, interconnects and wires all agree with each other, panicking if they don't.
+    /// This is meant for tests, as it walks the entire netlist.
+    pub fn assert_invariants(&self) {
+        let mut ids = HashSet::new();
+        for (mi, m) in self.modules() {
+            assert!(ids.insert(m.id), "Module id {} is used twice", m.id);
+            assert!(
+                m.id < self.next_mod_idx,
+                "Module id {} would be handed out again",
+                m.id
+            );
+            for (pins, direction) in [
+                (&m.inputs, InterconnectDirection::Input),
+                (&m.outputs, InterconnectDirection::Output),
+            ] {
+                for pi in pins {
+                    let p = self
+                        .get_pin(*pi)
+                        .unwrap_or_else(|| panic!("Module {} lists missing pin {}", m.id, pi));
+                    assert_eq!(p.parent.0, mi, "Pin {} has the wrong parent", pi);
+                    assert_eq!(p.direction(), direction, "Pin {} is on the wrong side", pi);
+                    assert_eq!(
+                        pins.iter().filter(|x| *x == pi).count(),
+                        1,
+                        "Module {} lists pin {} twice",
+                        m.id,
+                        pi
+                    );
+                }
+            }
+        }
+        let mut driven = 0;
+        for (pi, p) in self.pins.iter() {
+            let pi = PinIndex(pi);
+            let m = self
+                .get_module(p.parent)
+                .unwrap_or_else(|| panic!("Pin {} belongs to a missing module", pi));
+            let listed = match p.direction() {
+                InterconnectDirection::Input => &m.inputs,
+                _ => &m.outputs,
+            };
+            assert!(
+                listed.contains(&pi),
+                "Pin {} isn't listed by its module",
+                pi
+            );
+            match &p.interconnect {
+                Interconnect::Input {
+                    connection: Some(o),
+                } => {
+                    assert!(
+                        self.fanout(*o).is_some_and(|x| x.contains(&pi)),
+                        "Pin {} is driven by {}, which doesn't drive it",
+                        pi,
+                        o
+                    );
+                    driven += 1;
+                }
+                Interconnect::Input { connection: None } => (),
+                Interconnect::Output { connections } => {
+                    for i in connections {
+                        assert_eq!(
+                            self.driver(*i),
+                            Some(pi),
+                            "Pin {} drives {}, which isn't driven by it",
+                            pi,
+                            i
+                        );
+                    }
+                    assert_eq!(
+                        connections.iter().collect::<HashSet<_>>().len(),
+                        connections.len(),
+                        "Pin {} drives the same pin twice",
+                        pi
+                    );
+                }
+            }
+        }
+        assert_eq!(
+            driven,
+            self.wires.len(),
+            "Every driven input should have exactly one wire"
+        );
+        for (wi, (input, output)) in self.wires() {
+            assert_eq!(
+                self.driver(*input),
+                Some(*output),
+                "Wire {} doesn't match its pins",
+                WireIndex(wi)
+            );
+            // Saved wires find their pins by name, which must lead back to the same pins
+            for (pi, direction) in [
+                (*output, InterconnectDirection::Output),
+                (*input, InterconnectDirection::Input),
+            ] {
+                let pin = self.pin_ref(pi).expect("Wired pins exist");
+                assert_eq!(
+                    self.resolve_pin(&pin, direction),
+                    Some(pi),
+                    "Wire {} would be saved on {}, which is ambiguous",
+                    WireIndex(wi),
+                    pin
+                );
+            }
+        }
+        let saved = self.to_saved();
+        let loaded = Netlist::from_saved(&saved)
+            .unwrap_or_else(|e| panic!("The saved netlist doesn't load: {}", e));
+        assert_eq!(loaded.to_saved(), saved, "Saving changes the netlist");
+    }
+
+    /// Get the output pin driving the input pin `idx`.
+    /// Returns `None` if the pin doesn't exist, isn't an input, or is undriven.
+    pub fn driver(&self, idx: PinIndex) -> Option<PinIndex> {
+        match self.get_pin(idx)?.interconnect {
+            Interconnect::Input { connection } => connection,
+            Interconnect::Output { .. } => None,
+        }
+    }
+
+    /// Get the input pins driven by the output pin `idx`.
+    /// Returns `None` if the pin doesn't exist or isn't an output.
+    pub fn fanout(&self, idx: PinIndex) -> Option<&[PinIndex]> {
+        match &self.get_pin(idx)?.interconnect {
+            Interconnect::Input { .. } => None,
+            Interconnect::Output { connections } => Some(connections),
+        }
+    }
+
+    /// Get the modules that directly drive any input of the module `idx`, without duplicates.
+    /// Returns `None` if no module exists in the given index.
+    pub fn predecessors(&self, idx: ModuleIndex) -> Option<Vec<ModuleIndex>> {
+        let mut seen = HashSet::new();
+        Some(
+            self.get_module(idx)?
+                .inputs()
+                .filter_map(|x| self.driver(*x))
+                .filter_map(|x| self.get_pin(x).map(Pin::parent))
+                .filter(|x| seen.insert(*x))
+                .collect(),
+        )
+    }
+
+    /// Get the modules that are directly driven by any output of the module `idx`, without duplicates.
+    /// Returns `None` if no module exists in the given index.
+    pub fn successors(&self, idx: ModuleIndex) -> Option<Vec<ModuleIndex>> {
+        let mut seen = HashSet::new();
+        Some(
+            self.get_module(idx)?
+                .outputs()
+                .filter_map(|x| self.fanout(*x))
+                .flatten()
+                .filter_map(|x| self.get_pin(*x).map(Pin::parent))
+                .filter(|x| seen.insert(*x))
+                .collect(),
+        )
+    }
+
+    /// Get every module directly connected to the module `idx`, predecessors first.
+    /// Returns `None` if no module exists in the given index.
+    pub fn neighbours(&self, idx: ModuleIndex) -> Option<Vec<ModuleIndex>> {
+        let mut neighbours = self.predecessors(idx)?;
+        for m in self.successors(idx)? {
+            if !neighbours.contains(&m) {
+                neighbours.push(m);
+            }
+        }
+        Some(neighbours)
+    }
+
+    /// Get every module that transitively drives the module `idx`, nearest first.
+    /// The module itself is only included if it sits on a feedback loop.
+    /// Returns `None` if no module exists in the given index.
+    pub fn upstream(&self, idx: ModuleIndex) -> Option<Vec<ModuleIndex>> {
+        self.cone(idx, Self::predecessors)
+    }
+
+    /// Get every module that is transitively driven by the module `idx`, nearest first.
+    /// The module itself is only included if it sits on a feedback loop.
+    /// Returns `None` if no module exists in the given index.
+    pub fn downstream(&self, idx: ModuleIndex) -> Option<Vec<ModuleIndex>> {
+        self.cone(idx, Self::successors)
+    }
+
+    /// Breadth-first walk from `idx` using `step` to find the next modules
+    fn cone(
+        &self,
+        idx: ModuleIndex,
+        step: fn(&Self, ModuleIndex) -> Option<Vec<ModuleIndex>>,
+    ) -> Option<Vec<ModuleIndex>> {
+        let mut cone = vec![];
+        let mut seen = HashSet::new();
+        let mut queue: VecDeque<_> = step(self, idx)?.into();
+        while let Some(m) = queue.pop_front() {
+            if seen.insert(m) {
+                cone.push(m);
+                queue.extend(step(self, m).expect("Connected modules always exist"));
+            }
+        }
+        Some(cone)
+    }
+
+    /// Get all the modules ordered such that every module comes after the modules driving it.
+    /// Returns `None` if the netlist contains a loop.
+    pub fn topological_order(&self) -> Option<Vec<ModuleIndex>> {
+        // Kahn's algorithm, seeded in arena order so the result is deterministic
+        let mut in_degree: HashMap<_, _> = self
+            .modules()
+            .map(|(i, _)| {
+                let mi = ModuleIndex(i);
+                (mi, self.predecessors(mi).expect("Module exists").len())
+            })
+            .collect();
+        let mut queue: VecDeque<_> = self
+            .modules()
+            .map(|(i, _)| ModuleIndex(i))
+            .filter(|x| in_degree[x] == 0)
+            .collect();
+        let mut order = vec![];
+        while let Some(m) = queue.pop_front() {
+            order.push(m);
+            for s in self.successors(m).expect("Module exists") {
+                let d = in_degree.get_mut(&s).expect("Module exists");
+                *d -= 1;
+                if *d == 0 {
+                    queue.push_back(s);
+                }
+            }
+        }
+        (order.len() == self.modules.len()).then_some(order)
+    }
+}
+
+impl Display for Netlist {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // List every module by id, along with what each of its pins connects to
+        let pin_ref = |idx: PinIndex| {
+            let p = self.get_pin(idx).expect("Connected pins always exist");
+            let m = self
+                .get_module(p.parent)
+                .expect("Pins always have a parent");
+            format!("{}.{}", m.id, p.name)
+        };
+        let mut modules: Vec<_> = self.modules().map(|(_, m)| m).collect();
+        modules.sort_by_key(|m| m.id);
+        for m in modules {
+            writeln!(f, "module {} `{}` as {}", m.id, m.name, m.instance)?;
+            for (name, value) in &m.parameters {
+                writeln!(f, "  parameter {} = {}", name, value)?;
+            }
+            for pi in m.inputs() {
+                let p = self.get_pin(*pi).expect("Module pins always exist");
+                write!(f, "  input {} ({})", p.name, p.kind)?;
+                match self.driver(*pi) {
+                    Some(d) => writeln!(f, " <- {}", pin_ref(d))?,
+                    None => writeln!(f)?,
+                }
+            }
+            for pi in m.outputs() {
+                let p = self.get_pin(*pi).expect("Module pins always exist");
+                write!(f, "  output {} ({})", p.name, p.kind)?;
+                let fanout: Vec<_> = self
+                    .fanout(*pi)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|x| pin_ref(*x))
+                    .collect();
+                if fanout.is_empty() {
+                    writeln!(f)?;
+                } else {
+                    writeln!(f, " -> {}", fanout.join(", "))?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Error, Debug, PartialEq, Eq)]
@@ -421,6 +1068,18 @@ pub enum ConnectionError {
     InputDriven,
 }
 
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum NameError {
+    #[error("The supplied module index `{0}` points to a module that doesn't exist")]
+    BadModule(ModuleIndex),
+    #[error("The supplied pin index `{0}` points to a pin that doesn't exist")]
+    BadPin(PinIndex),
+    #[error("Names can't be empty")]
+    Empty,
+    #[error("The name `{0}` is already taken")]
+    Taken(String),
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -460,6 +1119,16 @@ mod tests {
                 InterconnectDirection::Output,
             )
             .unwrap();
+        // Pin names are unique across both directions
+        assert!(netlist
+            .add_pin(
+                mi,
+                "A".to_owned(),
+                PinKind::Wire,
+                InterconnectDirection::Output,
+            )
+            .is_none());
+        assert_eq!(netlist.pins().len(), 3);
     }
 
     #[test]
@@ -698,7 +1367,7 @@ mod tests {
         let b = netlist
             .add_pin(
                 m_3,
-                "".to_owned(),
+                "B".to_owned(),
                 PinKind::Wire,
                 InterconnectDirection::Input,
             )
@@ -728,4 +1397,283 @@ mod tests {
             _ => unreachable!(),
         }
     }
+
+    /// Build a module with a single input and a single output wire pin
+    fn add_buffer(netlist: &mut Netlist, name: &str) -> (ModuleIndex, PinIndex, PinIndex) {
+        let m = netlist.add_module(name.to_owned());
+        let i = netlist
+            .add_pin(
+                m,
+                "I".to_owned(),
+                PinKind::Wire,
+                InterconnectDirection::Input,
+            )
+            .unwrap();
+        let o = netlist
+            .add_pin(
+                m,
+                "O".to_owned(),
+                PinKind::Wire,
+                InterconnectDirection::Output,
+            )
+            .unwrap();
+        (m, i, o)
+    }
+
+    #[test]
+    fn test_traversal() {
+        let mut netlist = Netlist::new();
+        let (m_a, _, out_a) = add_buffer(&mut netlist, "a");
+        let (m_b, in_b, out_b) = add_buffer(&mut netlist, "b");
+        let (m_c, in_c, _) = add_buffer(&mut netlist, "c");
+        let (m_d, _, _) = add_buffer(&mut netlist, "d");
+        netlist.add_wire(out_a, in_b).unwrap();
+        netlist.add_wire(out_b, in_c).unwrap();
+
+        assert_eq!(netlist.driver(in_b), Some(out_a));
+        assert_eq!(netlist.driver(out_a), None);
+        assert_eq!(netlist.fanout(out_a).unwrap(), &[in_b]);
+        assert!(netlist.fanout(in_b).is_none());
+
+        assert_eq!(netlist.predecessors(m_b).unwrap(), vec![m_a]);
+        assert_eq!(netlist.successors(m_b).unwrap(), vec![m_c]);
+        assert_eq!(netlist.neighbours(m_b).unwrap(), vec![m_a, m_c]);
+        assert!(netlist.neighbours(m_d).unwrap().is_empty());
+
+        assert_eq!(netlist.upstream(m_c).unwrap(), vec![m_b, m_a]);
+        assert_eq!(netlist.downstream(m_a).unwrap(), vec![m_b, m_c]);
+        assert!(netlist.downstream(m_c).unwrap().is_empty());
+
+        assert_eq!(
+            netlist.topological_order().unwrap(),
+            vec![m_a, m_d, m_b, m_c]
+        );
+    }
+
+    #[test]
+    fn test_traversal_loop() {
+        let mut netlist = Netlist::new();
+        let (m_a, in_a, out_a) = add_buffer(&mut netlist, "a");
+        let (m_b, in_b, out_b) = add_buffer(&mut netlist, "b");
+        netlist.add_wire(out_a, in_b).unwrap();
+        netlist.add_wire(out_b, in_a).unwrap();
+
+        assert!(netlist.topological_order().is_none());
+        assert_eq!(netlist.upstream(m_a).unwrap(), vec![m_b, m_a]);
+    }
+
+    #[test]
+    fn test_events() {
+        let mut netlist = Netlist::new();
+        let (m_a, _, out_a) = add_buffer(&mut netlist, "a");
+        let (m_b, in_b, out_b) = add_buffer(&mut netlist, "b");
+        let wi = netlist.add_wire(out_a, in_b).unwrap();
+        netlist.set_parameter(m_b, "width".to_owned(), "8".to_owned());
+        let events = netlist.take_events();
+        assert_eq!(events.len(), 8);
+        assert_eq!(events[0], NetlistEvent::ModuleAdded(m_a));
+        assert_eq!(events[6], NetlistEvent::WireAdded(wi));
+        assert_eq!(
+            events[7],
+            NetlistEvent::ParameterChanged(m_b, "width".to_owned())
+        );
+        assert!(netlist.take_events().is_empty());
+
+        // Removing a module takes its wires and pins with it
+        netlist.remove_module(m_b).unwrap();
+        assert_eq!(
+            netlist.take_events(),
+            vec![
+                NetlistEvent::WireRemoved(wi),
+                NetlistEvent::PinRemoved(in_b),
+                NetlistEvent::PinRemoved(out_b),
+                NetlistEvent::ModuleRemoved(m_b),
+            ]
+        );
+
+        // Parameters and attributes only report actual changes
+        netlist.set_parameter(m_a, "width".to_owned(), "8".to_owned());
+        netlist.set_parameter(m_a, "width".to_owned(), "8".to_owned());
+        netlist.set_latency(m_a, Some("1".to_owned()));
+        netlist.set_latency(m_a, Some("1".to_owned()));
+        netlist.set_bundles(m_a, vec![]);
+        netlist.set_position(m_a, Some((1.0, 2.0)));
+        assert_eq!(
+            netlist.take_events(),
+            vec![
+                NetlistEvent::ParameterChanged(m_a, "width".to_owned()),
+                NetlistEvent::AttributeChanged(m_a, Attribute::Latency),
+                NetlistEvent::AttributeChanged(m_a, Attribute::Position),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_many() {
+        let mut netlist = Netlist::new();
+        let (m_a, _, out_a) = add_buffer(&mut netlist, "a");
+        let (m_b, in_b, out_b) = add_buffer(&mut netlist, "b");
+        let (m_c, in_c, _) = add_buffer(&mut netlist, "c");
+        let w_ab = netlist.add_wire(out_a, in_b).unwrap();
+        let w_bc = netlist.add_wire(out_b, in_c).unwrap();
+
+        // A stale index anywhere stops the whole thing
+        let (gone, _, _) = add_buffer(&mut netlist, "gone");
+        netlist.remove_module(gone);
+        assert!(netlist.remove_many(&[m_a, gone], &[w_bc]).is_none());
+        assert_eq!(netlist.modules().len(), 3);
+        assert_eq!(netlist.wires().len(), 2);
+
+        // Wires that go away with a module, or are listed twice, are fine
+        netlist.remove_many(&[m_b], &[w_ab, w_bc, w_bc]).unwrap();
+        assert_eq!(netlist.modules().len(), 2);
+        assert_eq!(netlist.wires().len(), 0);
+        assert!(netlist.get_module(m_a).is_some() && netlist.get_module(m_c).is_some());
+    }
+
+    /// A random edit, picking its targets out of everything ever created so stale indices get tried too
+    #[derive(Debug, Clone)]
+    enum Op {
+        AddModule,
+        AddPin(usize, usize, bool, bool),
+        RemovePin(usize),
+        RemoveModule(usize),
+        AddWire(usize, usize),
+        RemoveWire(usize),
+        RemoveMany(Vec<usize>, Vec<usize>),
+    }
+
+    fn op() -> impl proptest::strategy::Strategy<Value = Op> {
+        use proptest::prelude::*;
+        prop_oneof![
+            1 => Just(Op::AddModule),
+            // Pin names come from a small pool, so modules get several pins and duplicates still get tried
+            3 => (any::<usize>(), 0..3usize, any::<bool>(), any::<bool>())
+                .prop_map(|(m, n, input, wire)| Op::AddPin(m, n, input, wire)),
+            1 => any::<usize>().prop_map(Op::RemovePin),
+            1 => any::<usize>().prop_map(Op::RemoveModule),
+            3 => (any::<usize>(), any::<usize>()).prop_map(|(a, b)| Op::AddWire(a, b)),
+            1 => any::<usize>().prop_map(Op::RemoveWire),
+            1 => (
+                prop::collection::vec(any::<usize>(), 0..3),
+                prop::collection::vec(any::<usize>(), 0..3)
+            )
+                .prop_map(|(m, w)| Op::RemoveMany(m, w)),
+        ]
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_random_edits_keep_invariants(ops in proptest::collection::vec(op(), 1..60)) {
+            fn pick<T: Copy>(xs: &[T], i: usize) -> Option<T> {
+                (!xs.is_empty()).then(|| xs[i % xs.len()])
+            }
+            let mut netlist = Netlist::new();
+            let (mut modules, mut pins, mut wires) = (vec![], vec![], vec![]);
+            for op in ops {
+                match op {
+                    Op::AddModule => modules.push(netlist.add_module("m".to_owned())),
+                    Op::AddPin(m, n, input, wire) => {
+                        if let Some(m) = pick(&modules, m) {
+                            let direction = if input {
+                                InterconnectDirection::Input
+                            } else {
+                                InterconnectDirection::Output
+                            };
+                            let kind = if wire { PinKind::Wire } else { PinKind::Real };
+                            pins.extend(netlist.add_pin(m, format!("p{}", n), kind, direction));
+                        }
+                    }
+                    Op::RemovePin(p) => {
+                        pick(&pins, p).map(|p| netlist.remove_pin(p));
+                    }
+                    Op::RemoveModule(m) => {
+                        pick(&modules, m).map(|m| netlist.remove_module(m));
+                    }
+                    Op::AddWire(a, b) => {
+                        if let (Some(a), Some(b)) = (pick(&pins, a), pick(&pins, b)) {
+                            wires.extend(netlist.add_wire(a, b).ok());
+                        }
+                    }
+                    Op::RemoveWire(w) => {
+                        pick(&wires, w).map(|w| netlist.remove_wire(w));
+                    }
+                    Op::RemoveMany(m, w) => {
+                        let m: Vec<_> = m.iter().filter_map(|x| pick(&modules, *x)).collect();
+                        let w: Vec<_> = w.iter().filter_map(|x| pick(&wires, *x)).collect();
+                        netlist.remove_many(&m, &w);
+                    }
+                }
+                netlist.assert_invariants();
+            }
+            // Whatever happened, the netlist should still survive a round trip through the save format
+            let loaded = Netlist::from_saved(&netlist.to_saved()).unwrap();
+            loaded.assert_invariants();
+            proptest::prop_assert_eq!(loaded.to_saved(), netlist.to_saved());
+        }
+    }
+
+    #[test]
+    fn test_remove_driven_input() {
+        // Removing a driven input used to leave it in the fanout of its driver
+        let mut netlist = Netlist::new();
+        let (_, _, out_a) = add_buffer(&mut netlist, "a");
+        let (_, in_b, _) = add_buffer(&mut netlist, "b");
+        netlist.add_wire(out_a, in_b).unwrap();
+        netlist.remove_pin(in_b).unwrap();
+        netlist.assert_invariants();
+        assert!(netlist.fanout(out_a).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut netlist = Netlist::new();
+        let (m_a, in_a, out_a) = add_buffer(&mut netlist, "Bufer");
+        let (m_b, in_b, _) = add_buffer(&mut netlist, "Buffer");
+        netlist.add_wire(out_a, in_b).unwrap();
+        netlist.take_events();
+
+        assert_eq!(
+            netlist.rename_module(m_a, "Buffer".to_owned()).unwrap(),
+            "Bufer"
+        );
+        assert_eq!(netlist.get_module(m_a).unwrap().name(), "Buffer");
+
+        netlist.set_instance_name(m_a, "first".to_owned()).unwrap();
+        let err = netlist
+            .set_instance_name(m_b, "first".to_owned())
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<NameError>().unwrap(),
+            NameError::Taken("first".to_owned())
+        );
+        // Setting a module's own instance name again is fine
+        netlist.set_instance_name(m_a, "first".to_owned()).unwrap();
+        assert_eq!(netlist.get_module(m_a).unwrap().instance(), "first");
+
+        // Pin names are unique across both directions of a module, and renaming keeps the wiring
+        assert!(netlist.rename_pin(in_a, "O".to_owned()).is_err());
+        assert!(netlist.rename_pin(in_a, String::new()).is_err());
+        assert_eq!(netlist.rename_pin(out_a, "Y".to_owned()).unwrap(), "O");
+        assert_eq!(netlist.driver(in_b), Some(out_a));
+        assert_eq!(
+            netlist.find_pin(m_a, "Y", InterconnectDirection::Output),
+            Some(out_a)
+        );
+        assert_eq!(
+            netlist.take_events(),
+            vec![
+                NetlistEvent::ModuleRenamed(m_a),
+                NetlistEvent::InstanceRenamed(m_a),
+                NetlistEvent::InstanceRenamed(m_a),
+                NetlistEvent::PinRenamed(out_a),
+            ]
+        );
+
+        // Freshly added modules get a name that's free
+        let (m_c, _, _) = add_buffer(&mut netlist, "Buffer");
+        assert_eq!(netlist.get_module(m_b).unwrap().instance(), "Buffer_0");
+        assert_eq!(netlist.get_module(m_c).unwrap().instance(), "Buffer_1");
+        netlist.assert_invariants();
+    }
 }
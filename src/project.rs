@@ -1,30 +1,415 @@
 //! A Project contains several netlists, each netlist acting as a "submodule" or "subgraph"
+//! A module whose name matches another subgraph is an instance of that subgraph, with one pin per subgraph port.
 
-use crate::{ netlist::{Netlist, Module, ModuleIndex}};
+use crate::{
+    ffi::PinKind,
+    netlist::{Module, ModuleIndex, Netlist, PinIndex},
+    save::SavedNetlist,
+};
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::Path,
+};
 
 #[derive(Debug)]
-pub struct Subgraph<'a>{
+pub struct Subgraph {
     inputs: Vec<ModuleIndex>,
     outputs: Vec<ModuleIndex>,
-    netlist: &'a Netlist,
+    netlist: Netlist,
     name: String,
 }
 
-impl<'a> Subgraph<'a> {
-    pub fn new(inputs: Vec<ModuleIndex>, outputs: Vec<ModuleIndex>, netlist: &'a Netlist) -> Self {
+impl Subgraph {
+    pub fn new(
+        name: String,
+        inputs: Vec<ModuleIndex>,
+        outputs: Vec<ModuleIndex>,
+        netlist: Netlist,
+    ) -> anyhow::Result<Self> {
         // From the pints in `netlist`, we need to specify a few as inputs and ouptuts.
-        // These are special nodes in that the won't have any parameters, just a single pin with
-        // a type. We'll check that here.
-        Self { inputs: todo!(), outputs: todo!(), netlist: todo!(), name: todo!() }
+        // These are special nodes in that they just have a single pin with a type.
+        // We'll check that here.
+        for idx in &inputs {
+            match netlist.get_module(*idx) {
+                Some(m) if is_input_module(m) => (),
+                Some(m) => bail!("Module `{}` can't be used as a subgraph input", m.name()),
+                None => bail!("Subgraph input `{}` doesn't exist", idx),
+            }
+        }
+        for idx in &outputs {
+            match netlist.get_module(*idx) {
+                Some(m) if is_output_module(m) => (),
+                Some(m) => bail!("Module `{}` can't be used as a subgraph output", m.name()),
+                None => bail!("Subgraph output `{}` doesn't exist", idx),
+            }
+        }
+        Ok(Self {
+            inputs,
+            outputs,
+            netlist,
+            name,
+        })
+    }
+
+    /// Get the name of the subgraph
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the netlist this subgraph wraps
+    pub fn netlist(&self) -> &Netlist {
+        &self.netlist
+    }
+
+    /// Get an iterator over the modules acting as subgraph inputs
+    pub fn inputs(&self) -> std::slice::Iter<'_, ModuleIndex> {
+        self.inputs.iter()
+    }
+
+    /// Get an iterator over the modules acting as subgraph outputs
+    pub fn outputs(&self) -> std::slice::Iter<'_, ModuleIndex> {
+        self.outputs.iter()
+    }
+
+    /// Check if the module `idx` is one of the subgraph's ports
+    pub fn is_port(&self, idx: ModuleIndex) -> bool {
+        self.inputs.contains(&idx) || self.outputs.contains(&idx)
+    }
+
+    /// Get the name and kind of every input port, named after their port module
+    pub fn input_ports(&self) -> Vec<(&str, PinKind)> {
+        self.ports(&self.inputs, Module::outputs)
+    }
+
+    /// Get the name and kind of every output port, named after their port module
+    pub fn output_ports(&self) -> Vec<(&str, PinKind)> {
+        self.ports(&self.outputs, Module::inputs)
+    }
+
+    fn ports<'a>(
+        &'a self,
+        modules: &[ModuleIndex],
+        pins: fn(&Module) -> std::slice::Iter<'_, PinIndex>,
+    ) -> Vec<(&'a str, PinKind)> {
+        modules
+            .iter()
+            .map(|x| {
+                let m = self.netlist.get_module(*x).expect("Ports always exist");
+                let p = pins(m).next().expect("Ports always have a pin");
+                let kind = self.netlist.get_pin(*p).expect("Pins always exist").kind();
+                (m.name(), kind)
+            })
+            .collect()
     }
 }
 
 // A subgraph input module has a single output pin
-fn is_input_module(m: Module) -> bool {
+fn is_input_module(m: &Module) -> bool {
     m.inputs().len() == 0 && m.outputs().len() == 1
 }
 
 // A subgraph output module has a single input pin
-fn is_output_module(m: Module) -> bool {
+fn is_output_module(m: &Module) -> bool {
     m.inputs().len() == 1 && m.outputs().len() == 0
-}
\ No newline at end of file
+}
+
+#[derive(Debug)]
+pub struct Project {
+    name: String,
+    // Name of the subgraph at the top of the hierarchy
+    top: String,
+    subgraphs: Vec<Subgraph>,
+    // Lua scripts kept with the project, keyed by name
+    scripts: BTreeMap<String, String>,
+    // Verilog of the generated library blocks, keyed by module name
+    sources: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedProject {
+    pub name: String,
+    pub top: String,
+    pub subgraphs: Vec<SavedSubgraph>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub scripts: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sources: BTreeMap<String, String>,
+}
+
+/// A subgraph on disk, with its ports referenced by module id
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedSubgraph {
+    pub name: String,
+    pub inputs: Vec<i32>,
+    pub outputs: Vec<i32>,
+    pub netlist: SavedNetlist,
+}
+
+impl Project {
+    pub fn new(name: String, top: Subgraph) -> Self {
+        Self {
+            name,
+            top: top.name.clone(),
+            subgraphs: vec![top],
+            scripts: BTreeMap::new(),
+            sources: BTreeMap::new(),
+        }
+    }
+
+    /// Get the name of the project
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the subgraph at the top of the hierarchy
+    pub fn top(&self) -> &Subgraph {
+        self.subgraph(&self.top)
+            .expect("The top subgraph always exists")
+    }
+
+    /// Get a subgraph by name
+    pub fn subgraph(&self, name: &str) -> Option<&Subgraph> {
+        self.subgraphs.iter().find(|x| x.name == name)
+    }
+
+    /// Get an iterator over all the subgraphs
+    pub fn subgraphs(&self) -> std::slice::Iter<'_, Subgraph> {
+        self.subgraphs.iter()
+    }
+
+    /// Add another subgraph to the project, failing if one with the same name exists
+    pub fn add_subgraph(&mut self, subgraph: Subgraph) -> anyhow::Result<()> {
+        if self.subgraph(&subgraph.name).is_some() {
+            bail!("A subgraph named `{}` already exists", subgraph.name);
+        }
+        self.subgraphs.push(subgraph);
+        Ok(())
+    }
+
+    /// Edit the netlist of the subgraph `name` through `f`. The edits are made on a copy, which only replaces
+    /// the subgraph if `f` succeeds and the subgraph's ports are still usable as ports.
+    pub fn edit_subgraph<R>(
+        &mut self,
+        name: &str,
+        f: impl FnOnce(&mut Netlist) -> anyhow::Result<R>,
+    ) -> anyhow::Result<R> {
+        let pos = self
+            .subgraphs
+            .iter()
+            .position(|x| x.name == name)
+            .ok_or_else(|| anyhow!("There's no subgraph named `{}`", name))?;
+        let sg = &self.subgraphs[pos];
+        let ids = |modules: &[ModuleIndex]| -> Vec<_> {
+            modules
+                .iter()
+                .map(|x| sg.netlist.get_module(*x).expect("Ports always exist").id())
+                .collect()
+        };
+        let (inputs, outputs) = (ids(&sg.inputs), ids(&sg.outputs));
+        let mut netlist = Netlist::from_saved(&sg.netlist.to_saved())?;
+        netlist.take_events();
+        let result = f(&mut netlist)?;
+        let find = |ids: Vec<i32>| -> anyhow::Result<Vec<_>> {
+            ids.into_iter()
+                .map(|id| {
+                    netlist
+                        .find_module(id)
+                        .ok_or_else(|| anyhow!("Port module {} was removed", id))
+                })
+                .collect()
+        };
+        let (inputs, outputs) = (find(inputs)?, find(outputs)?);
+        self.subgraphs[pos] = Subgraph::new(name.to_owned(), inputs, outputs, netlist)?;
+        Ok(result)
+    }
+
+    /// Get the script saved with the project as `name`
+    pub fn script(&self, name: &str) -> Option<&str> {
+        self.scripts.get(name).map(String::as_str)
+    }
+
+    /// Get the scripts saved with the project, keyed by name
+    pub fn scripts(&self) -> &BTreeMap<String, String> {
+        &self.scripts
+    }
+
+    /// Save a script with the project, returning the one it replaces
+    pub fn set_script(&mut self, name: String, source: String) -> Option<String> {
+        self.scripts.insert(name, source)
+    }
+
+    /// Get the Verilog of the generated library blocks, keyed by module name
+    pub fn sources(&self) -> &BTreeMap<String, String> {
+        &self.sources
+    }
+
+    /// Keep the Verilog of a generated library block with the project, returning the source it replaces
+    pub fn set_source(&mut self, module: String, verilog: String) -> Option<String> {
+        self.sources.insert(module, verilog)
+    }
+
+    /// Get the subgraphs instantiated by modules of the subgraph `sg`
+    pub fn children(&self, sg: &Subgraph) -> Vec<&Subgraph> {
+        let mut children: Vec<&Subgraph> = vec![];
+        for (_, m) in sg.netlist.modules() {
+            if let Some(child) = self.subgraph(m.name()) {
+                if !children.iter().any(|x| x.name == child.name) {
+                    children.push(child);
+                }
+            }
+        }
+        children
+    }
+
+    /// Get the subgraphs reachable from the top, ordered such that every subgraph comes before the ones
+    /// instantiating it. Errors if a subgraph ends up instantiating itself.
+    pub fn hierarchy_order(&self) -> anyhow::Result<Vec<&Subgraph>> {
+        fn visit<'a>(
+            project: &'a Project,
+            sg: &'a Subgraph,
+            path: &mut Vec<&'a str>,
+            done: &mut HashSet<&'a str>,
+            order: &mut Vec<&'a Subgraph>,
+        ) -> anyhow::Result<()> {
+            if done.contains(sg.name()) {
+                return Ok(());
+            }
+            if path.contains(&sg.name()) {
+                bail!("Subgraph `{}` instantiates itself", sg.name());
+            }
+            path.push(sg.name());
+            for child in project.children(sg) {
+                visit(project, child, path, done, order)?;
+            }
+            path.pop();
+            done.insert(sg.name());
+            order.push(sg);
+            Ok(())
+        }
+        let mut order = vec![];
+        visit(
+            self,
+            self.top(),
+            &mut vec![],
+            &mut HashSet::new(),
+            &mut order,
+        )?;
+        Ok(order)
+    }
+
+    /// Rebuild a project from its on-disk form
+    pub fn from_saved(saved: &SavedProject) -> anyhow::Result<Self> {
+        let mut subgraphs = vec![];
+        for sg in &saved.subgraphs {
+            let netlist = Netlist::from_saved(&sg.netlist)?;
+            let find = |ids: &[i32]| -> anyhow::Result<Vec<_>> {
+                ids.iter()
+                    .map(|id| {
+                        netlist
+                            .find_module(*id)
+                            .ok_or_else(|| anyhow!("Port module {} doesn't exist", id))
+                    })
+                    .collect()
+            };
+            let (inputs, outputs) = (find(&sg.inputs)?, find(&sg.outputs)?);
+            subgraphs.push(Subgraph::new(sg.name.clone(), inputs, outputs, netlist)?);
+        }
+        let project = Self {
+            name: saved.name.clone(),
+            top: saved.top.clone(),
+            subgraphs,
+            scripts: saved.scripts.clone(),
+            sources: saved.sources.clone(),
+        };
+        if project.subgraph(&project.top).is_none() {
+            bail!("The top subgraph `{}` doesn't exist", project.top);
+        }
+        Ok(project)
+    }
+
+    /// Convert the project into its on-disk form
+    pub fn to_saved(&self) -> SavedProject {
+        let ids = |sg: &Subgraph, modules: &[ModuleIndex]| {
+            modules
+                .iter()
+                .map(|x| sg.netlist.get_module(*x).expect("Ports always exist").id())
+                .collect()
+        };
+        SavedProject {
+            name: self.name.clone(),
+            top: self.top.clone(),
+            subgraphs: self
+                .subgraphs
+                .iter()
+                .map(|sg| SavedSubgraph {
+                    name: sg.name.clone(),
+                    inputs: ids(sg, &sg.inputs),
+                    outputs: ids(sg, &sg.outputs),
+                    netlist: sg.netlist.to_saved(),
+                })
+                .collect(),
+            scripts: self.scripts.clone(),
+            sources: self.sources.clone(),
+        }
+    }
+
+    /// Load a project from json. A bare netlist is also accepted, becoming a project with a single subgraph.
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        if value.get("subgraphs").is_some() {
+            Self::from_saved(&serde_json::from_value(value)?)
+        } else {
+            let netlist = Netlist::from_saved(&serde_json::from_value(value)?)?;
+            let top = Subgraph::new("top".to_owned(), vec![], vec![], netlist)?;
+            Ok(Self::new("top".to_owned(), top))
+        }
+    }
+
+    /// Serialize the whole project to pretty-printed json
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.to_saved()).unwrap()
+    }
+
+    /// Load a project from a json file
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_project() {
+        let project = Project::from_json(include_str!("../resources/project.json")).unwrap();
+        assert_eq!(project.top().name(), "top");
+        assert_eq!(project.top().input_ports(), vec![("x", PinKind::Wire)]);
+        let order: Vec<_> = project
+            .hierarchy_order()
+            .unwrap()
+            .iter()
+            .map(|x| x.name())
+            .collect();
+        assert_eq!(order, vec!["invert", "top"]);
+        let round_trip = Project::from_json(&project.to_json()).unwrap();
+        assert_eq!(round_trip.to_json(), project.to_json());
+    }
+
+    #[test]
+    fn test_bare_netlist() {
+        let project = Project::from_json(include_str!("../resources/netlist.json")).unwrap();
+        assert_eq!(project.top().netlist().modules().len(), 2);
+        assert!(project.top().input_ports().is_empty());
+    }
+
+    #[test]
+    fn test_bad_ports() {
+        let netlist = Netlist::from_json(include_str!("../resources/netlist.json")).unwrap();
+        let sink = netlist.find_module(1).unwrap();
+        // The sink has two inputs, so it can't be a port
+        assert!(Subgraph::new("bad".to_owned(), vec![], vec![sink], netlist).is_err());
+    }
+}
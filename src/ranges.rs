@@ -0,0 +1,430 @@
+//! This module works out the range of integer values on every pin, to find where arithmetic can overflow or wastes bits
+//! Library blocks declare the range of each output as a Lua expression in the ranges on their inputs, where ranges
+//! support `+`, `-`, `*`, division by constants and a few helpers:
+//!
+//! - `range(min, max)` is every value from `min` to `max`
+//! - `hull(a, b, ...)` is the smallest range holding all of its arguments
+//! - `shift(r, n)` scales `r` by `2^n`
+//!
+//! Outputs without an expression can carry anything their kind can, as can undriven inputs. Only integer and wire pins
+//! have a range: reals are floating point, so they're left out rather than checked as if they were fixed point.
+
+use crate::ffi::PinKind;
+use crate::netlist::{Module, Netlist, PinIndex};
+use crate::resources::environment;
+use crate::script::runtime_error;
+use anyhow::{anyhow, bail};
+use mlua::{FromLua, Lua, MetaMethod, UserData, UserDataFields, UserDataMethods, Value, Variadic};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// Every value from `min` to `max`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Range {
+    pub fn new(a: f64, b: f64) -> Self {
+        Self {
+            min: a.min(b),
+            max: a.max(b),
+        }
+    }
+
+    /// Every value a pin of `kind` can carry, or `None` for reals, which have no integer range
+    pub fn of_kind(kind: PinKind) -> Option<Self> {
+        match kind {
+            PinKind::Wire => Some(Self::new(0.0, 1.0)),
+            PinKind::Integer => Some(Self::new(
+                -(2f64.powi(kind.width() as i32 - 1)),
+                2f64.powi(kind.width() as i32 - 1) - 1.0,
+            )),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, other: &Range) -> bool {
+        self.min <= other.min && other.max <= self.max
+    }
+
+    /// The number of bits a signed integer needs to hold every whole number in the range
+    pub fn bits(&self) -> u32 {
+        let (min, max) = (self.min.floor(), self.max.ceil());
+        (1..1024)
+            .find(|n| {
+                let half = 2f64.powi(*n as i32 - 1);
+                -half <= min && max < half
+            })
+            .unwrap_or(1024)
+    }
+
+    fn hull(&self, other: &Range) -> Range {
+        Self::new(self.min.min(other.min), self.max.max(other.max))
+    }
+}
+
+impl Add for Range {
+    type Output = Range;
+
+    fn add(self, other: Range) -> Range {
+        Range::new(self.min + other.min, self.max + other.max)
+    }
+}
+
+impl Sub for Range {
+    type Output = Range;
+
+    fn sub(self, other: Range) -> Range {
+        Range::new(self.min - other.max, self.max - other.min)
+    }
+}
+
+impl Neg for Range {
+    type Output = Range;
+
+    fn neg(self) -> Range {
+        Range::new(-self.max, -self.min)
+    }
+}
+
+impl Mul for Range {
+    type Output = Range;
+
+    fn mul(self, other: Range) -> Range {
+        let products = [
+            self.min * other.min,
+            self.min * other.max,
+            self.max * other.min,
+            self.max * other.max,
+        ];
+        Range::new(
+            products.into_iter().fold(f64::INFINITY, f64::min),
+            products.into_iter().fold(f64::NEG_INFINITY, f64::max),
+        )
+    }
+}
+
+impl Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.min, self.max)
+    }
+}
+
+impl UserData for Range {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("min", |_, this| Ok(this.min));
+        fields.add_field_method_get("max", |_, this| Ok(this.max));
+    }
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        // Functions rather than methods, as numbers can be on either side
+        methods.add_meta_function(MetaMethod::Add, |_, (a, b): (Range, Range)| Ok(a + b));
+        methods.add_meta_function(MetaMethod::Sub, |_, (a, b): (Range, Range)| Ok(a - b));
+        methods.add_meta_function(MetaMethod::Mul, |_, (a, b): (Range, Range)| Ok(a * b));
+        methods.add_meta_function(MetaMethod::Div, |_, (a, b): (Range, f64)| {
+            if b == 0.0 {
+                return Err(runtime_error("Ranges can't be divided by zero"));
+            }
+            Ok(Range::new(a.min / b, a.max / b))
+        });
+        methods.add_meta_method(MetaMethod::Unm, |_, this, ()| Ok(-*this));
+        methods.add_meta_method(MetaMethod::ToString, |_, this, ()| Ok(this.to_string()));
+    }
+}
+
+impl<'lua> FromLua<'lua> for Range {
+    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> mlua::Result<Self> {
+        match value {
+            Value::Integer(x) => Ok(Range::new(x as f64, x as f64)),
+            Value::Number(x) => Ok(Range::new(x, x)),
+            Value::UserData(ud) => Ok(*ud.borrow::<Self>()?),
+            _ => Err(mlua::Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "Range",
+                message: None,
+            }),
+        }
+    }
+}
+
+/// Evaluate the range expression `expr` of the module `m`, given the ranges on its inputs
+fn evaluate(
+    lua: &Lua,
+    netlist: &Netlist,
+    m: &Module,
+    output: &str,
+    expr: &str,
+    ranges: &HashMap<PinIndex, Range>,
+) -> anyhow::Result<Range> {
+    let error = |e: mlua::Error| {
+        anyhow!(
+            "Can't work out the range of `{}.{}` from `{}`: {}",
+            m.instance(),
+            output,
+            expr,
+            e
+        )
+    };
+    let env = environment(lua, m).map_err(error)?;
+    let inputs = lua.create_table().map_err(error)?;
+    for pi in m.inputs() {
+        let p = netlist.get_pin(*pi).expect("Module pins always exist");
+        if let Some(r) = ranges.get(pi) {
+            inputs.set(p.name(), *r).map_err(error)?;
+        }
+    }
+    env.set("inputs", inputs).map_err(error)?;
+    let range = lua
+        .create_function(|_, (a, b): (f64, f64)| Ok(Range::new(a, b)))
+        .map_err(error)?;
+    env.set("range", range).map_err(error)?;
+    let hull = lua
+        .create_function(|_, ranges: Variadic<Range>| {
+            let mut ranges = ranges.into_iter();
+            let first = ranges
+                .next()
+                .ok_or_else(|| runtime_error("hull needs at least one range"))?;
+            Ok(ranges.fold(first, |a, b| a.hull(&b)))
+        })
+        .map_err(error)?;
+    env.set("hull", hull).map_err(error)?;
+    let shift = lua
+        .create_function(|_, (r, n): (Range, i32)| Ok(r * Range::new(2f64.powi(n), 2f64.powi(n))))
+        .map_err(error)?;
+    env.set("shift", shift).map_err(error)?;
+    lua.load(format!("return {}", expr))
+        .set_name(output)
+        .set_environment(env)
+        .eval()
+        .map_err(error)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Issue {
+    /// The output can carry values its kind can't hold
+    Overflow,
+    /// The output never uses all of its bits
+    Wasted,
+}
+
+/// An output whose range doesn't match its kind
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub pin: PinIndex,
+    pub module: i32,
+    pub instance: String,
+    pub name: String,
+    pub range: Range,
+    pub issue: Issue,
+    /// The bits it takes to hold the range
+    pub needed: u32,
+    /// The bits the pin has
+    pub available: u32,
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "module {} `{}`: output `{}` ranges over {}, ",
+            self.module, self.instance, self.name, self.range
+        )?;
+        match self.issue {
+            Issue::Overflow => write!(
+                f,
+                "which can overflow as it needs {} bits of {}",
+                self.needed, self.available
+            ),
+            Issue::Wasted => write!(
+                f,
+                "which only needs {} of its {} bits",
+                self.needed, self.available
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeReport {
+    /// The range of values on every integer and wire pin
+    pub ranges: HashMap<PinIndex, Range>,
+    /// The outputs that can overflow or waste bits, ordered by module id
+    pub findings: Vec<Finding>,
+}
+
+impl Display for RangeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.findings.is_empty() {
+            return writeln!(f, "Every output fits its values exactly");
+        }
+        for x in &self.findings {
+            writeln!(f, "{}", x)?;
+        }
+        Ok(())
+    }
+}
+
+impl Netlist {
+    /// Propagate integer ranges from the sources of the netlist through to every integer and wire pin.
+    /// Errors if a range can't be evaluated, or the netlist has a feedback loop.
+    pub fn range_report(&self) -> anyhow::Result<RangeReport> {
+        let Some(order) = self.topological_order() else {
+            bail!("The netlist has a feedback loop, so its ranges can't be worked out");
+        };
+        let lua = Lua::new();
+        let mut ranges = HashMap::new();
+        let mut findings = vec![];
+        for mi in order {
+            let m = self.get_module(mi).expect("Modules in order exist");
+            for pi in m.inputs() {
+                let kind = self.get_pin(*pi).expect("Module pins always exist").kind();
+                // Drivers come earlier in the order, so they already have their range
+                let range = match self.driver(*pi) {
+                    Some(d) => ranges.get(&d).copied(),
+                    None => Range::of_kind(kind),
+                };
+                ranges.extend(range.map(|x| (*pi, x)));
+            }
+            for name in m.ranges().keys() {
+                if !m
+                    .outputs()
+                    .any(|x| self.get_pin(*x).is_some_and(|p| p.name() == name))
+                {
+                    bail!(
+                        "`{}` has no output `{}` to give a range",
+                        m.instance(),
+                        name
+                    );
+                }
+            }
+            for pi in m.outputs() {
+                let p = self.get_pin(*pi).expect("Module pins always exist");
+                let Some(available) = Range::of_kind(p.kind()) else {
+                    continue;
+                };
+                let Some(expr) = m.ranges().get(p.name()) else {
+                    ranges.insert(*pi, available);
+                    continue;
+                };
+                let range = evaluate(&lua, self, m, p.name(), expr, &ranges)?;
+                ranges.insert(*pi, range);
+                let issue = if !available.contains(&range) {
+                    Issue::Overflow
+                } else if p.kind() == PinKind::Integer && range.bits() < p.kind().width() {
+                    Issue::Wasted
+                } else {
+                    continue;
+                };
+                findings.push(Finding {
+                    pin: *pi,
+                    module: m.id(),
+                    instance: m.instance().to_owned(),
+                    name: p.name().to_owned(),
+                    range,
+                    issue,
+                    needed: range.bits(),
+                    available: p.kind().width(),
+                });
+            }
+        }
+        findings.sort_by_key(|x| x.module);
+        Ok(RangeReport { ranges, findings })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::LibraryModule;
+    use crate::netlist::ModuleIndex;
+
+    fn block(netlist: &mut Netlist, json: &str) -> ModuleIndex {
+        let module: LibraryModule = serde_json::from_str(json).unwrap();
+        netlist.add_module_from_library(module).unwrap()
+    }
+
+    fn pin(netlist: &Netlist, m: ModuleIndex, name: &str) -> PinIndex {
+        let m = netlist.get_module(m).unwrap();
+        *m.inputs()
+            .chain(m.outputs())
+            .find(|x| netlist.get_pin(**x).unwrap().name() == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_bits() {
+        assert_eq!(Range::new(0.0, 0.0).bits(), 1);
+        assert_eq!(Range::new(-1.0, 0.0).bits(), 1);
+        assert_eq!(Range::new(-128.0, 127.0).bits(), 8);
+        assert_eq!(Range::new(0.0, 128.0).bits(), 9);
+        assert_eq!(Range::of_kind(PinKind::Integer).unwrap().bits(), 32);
+    }
+
+    #[test]
+    fn test_range_report() {
+        // An 8 bit ADC feeding a multiplier by a 16 bit coefficient, whose products are summed over 2^16 samples
+        let mut netlist = Netlist::new();
+        let adc = block(
+            &mut netlist,
+            r#"{"name": "adc", "outputs": [{"name": "dout", "kind": "integer"}],
+                "ranges": {"dout": "range(-128, 127)"}}"#,
+        );
+        let mult = block(
+            &mut netlist,
+            r#"{"name": "mult",
+                "inputs": [{"name": "a", "kind": "integer"}, {"name": "b", "kind": "integer"}],
+                "outputs": [{"name": "p", "kind": "integer"}],
+                "ranges": {"p": "inputs.a * range(-32768, 32767)"}}"#,
+        );
+        let acc = block(
+            &mut netlist,
+            r#"{"name": "acc",
+                "inputs": [{"name": "din", "kind": "integer"}],
+                "outputs": [{"name": "dout", "kind": "integer"}],
+                "parameters": {"length": "16"},
+                "ranges": {"dout": "shift(inputs.din, length)"}}"#,
+        );
+        netlist
+            .add_wire(pin(&netlist, adc, "dout"), pin(&netlist, mult, "a"))
+            .unwrap();
+        netlist
+            .add_wire(pin(&netlist, mult, "p"), pin(&netlist, acc, "din"))
+            .unwrap();
+
+        let report = netlist.range_report().unwrap();
+        // b is undriven, so it could be anything
+        assert_eq!(
+            report.ranges[&pin(&netlist, mult, "b")],
+            Range::of_kind(PinKind::Integer).unwrap()
+        );
+        assert_eq!(
+            report.ranges[&pin(&netlist, acc, "din")],
+            Range::new(-4194176.0, 4194304.0)
+        );
+        let findings: Vec<_> = report
+            .findings
+            .iter()
+            .map(|x| (x.name.as_str(), x.issue, x.needed))
+            .collect();
+        assert_eq!(
+            findings,
+            vec![
+                ("dout", Issue::Wasted, 8),
+                ("p", Issue::Wasted, 24),
+                ("dout", Issue::Overflow, 40)
+            ]
+        );
+        assert!(report.findings[2]
+            .to_string()
+            .contains("can overflow as it needs 40 bits of 32"));
+
+        // Mistakes in the expressions are errors
+        netlist.set_ranges(mult, [("p".to_owned(), "inputs.c * 2".to_owned())].into());
+        assert!(netlist.range_report().is_err());
+        netlist.set_ranges(mult, [("q".to_owned(), "0".to_owned())].into());
+        assert!(netlist.range_report().is_err());
+    }
+}
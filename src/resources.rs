@@ -0,0 +1,383 @@
+//! This module estimates how much of an FPGA a design takes, to see early on whether it will fit
+//! Library blocks declare a [`ResourceModel`], which is evaluated for every instance with its parameters and
+//! pin widths. Instances of subgraphs cost whatever the subgraph adds up to, and ports cost nothing.
+
+use crate::export::sorted_modules;
+use crate::library::ResourceModel;
+use crate::netlist::{Module, ModuleIndex, Netlist};
+use crate::project::Project;
+use anyhow::anyhow;
+use mlua::{Lua, Table, Value};
+use serde::Serialize;
+use std::fmt::Display;
+use std::ops::{Add, AddAssign};
+
+/// An amount of each kind of FPGA resource
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Resources {
+    pub luts: u64,
+    pub ffs: u64,
+    pub dsp48s: u64,
+    pub brams: u64,
+}
+
+/// What the XC7K160T on a SNAP board has to offer, counting BRAMs as 36 Kb blocks
+pub const SNAP: Resources = Resources {
+    luts: 101_400,
+    ffs: 202_800,
+    dsp48s: 600,
+    brams: 325,
+};
+
+impl Add for Resources {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            luts: self.luts + other.luts,
+            ffs: self.ffs + other.ffs,
+            dsp48s: self.dsp48s + other.dsp48s,
+            brams: self.brams + other.brams,
+        }
+    }
+}
+
+impl AddAssign for Resources {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Display for Resources {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} LUTs, {} FFs, {} DSP48s, {} BRAMs",
+            self.luts, self.ffs, self.dsp48s, self.brams
+        )
+    }
+}
+
+impl Resources {
+    /// Check that this fits in `device`
+    pub fn fits(&self, device: &Resources) -> bool {
+        self.luts <= device.luts
+            && self.ffs <= device.ffs
+            && self.dsp48s <= device.dsp48s
+            && self.brams <= device.brams
+    }
+
+    /// The percentage of each resource of `device` this uses, in the order they're declared
+    pub fn utilisation(&self, device: &Resources) -> [f64; 4] {
+        let percent = |used: u64, available: u64| 100.0 * used as f64 / available as f64;
+        [
+            percent(self.luts, device.luts),
+            percent(self.ffs, device.ffs),
+            percent(self.dsp48s, device.dsp48s),
+            percent(self.brams, device.brams),
+        ]
+    }
+}
+
+/// The estimated cost of one module
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModuleCost {
+    pub id: i32,
+    pub instance: String,
+    pub name: String,
+    /// `None` for blocks that didn't declare a cost model
+    pub resources: Option<Resources>,
+}
+
+/// The estimated cost of every module of one level of the hierarchy
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LevelCost {
+    pub name: String,
+    pub modules: Vec<ModuleCost>,
+    /// Everything in this level, including the insides of the subgraphs it instantiates
+    pub total: Resources,
+}
+
+impl LevelCost {
+    /// Get the modules that aren't counted as they have no cost model
+    pub fn unmodelled(&self) -> impl Iterator<Item = &ModuleCost> {
+        self.modules.iter().filter(|m| m.resources.is_none())
+    }
+}
+
+impl Display for LevelCost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}: {}", self.name, self.total)?;
+        for m in &self.modules {
+            match &m.resources {
+                Some(r) => writeln!(f, "  {} ({}): {}", m.instance, m.name, r)?,
+                None => writeln!(f, "  {} ({}): no cost model", m.instance, m.name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The estimated cost of every level of a project, each level after the ones it instantiates
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResourceReport {
+    pub levels: Vec<LevelCost>,
+}
+
+impl ResourceReport {
+    /// The cost of the whole design
+    pub fn total(&self) -> Resources {
+        self.levels.last().map(|x| x.total).unwrap_or_default()
+    }
+}
+
+impl Display for ResourceReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for level in &self.levels {
+            write!(f, "{}", level)?;
+        }
+        let [luts, ffs, dsp48s, brams] = self.total().utilisation(&SNAP);
+        writeln!(
+            f,
+            "SNAP utilisation: {:.1}% LUTs, {:.1}% FFs, {:.1}% DSP48s, {:.1}% BRAMs",
+            luts, ffs, dsp48s, brams
+        )
+    }
+}
+
+/// Make the environment for one of the expressions a library block declares, with the module's parameters as
+/// globals. Those that look like numbers are numbers.
+pub(crate) fn environment<'lua>(lua: &'lua Lua, m: &Module) -> mlua::Result<Table<'lua>> {
+    let env = lua.create_table()?;
+    // The math library is in scope, so models can say `ceil(x)` rather than `math.ceil(x)`
+    let meta = lua.create_table()?;
+    let math: Table = lua.globals().get("math")?;
+    meta.set("__index", math)?;
+    env.set_metatable(Some(meta));
+    for (name, value) in m.parameters() {
+        let value = match value.parse::<f64>() {
+            Ok(x) => Value::Number(x),
+            Err(_) => Value::String(lua.create_string(value)?),
+        };
+        env.set(name.as_str(), value)?;
+    }
+    Ok(env)
+}
+
+/// Evaluate one of the expressions a library block declares for the module `m`, rounding up to a whole number.
+/// Errors say that it's `what` being worked out.
+pub(crate) fn evaluate(
+    lua: &Lua,
+    netlist: &Netlist,
+    m: &Module,
+    what: &str,
+    expr: &str,
+) -> anyhow::Result<u64> {
+    let error = |e: mlua::Error| {
+        anyhow!(
+            "Can't work out the {} of `{}` from `{}`: {}",
+            what,
+            m.instance(),
+            expr,
+            e
+        )
+    };
+    let env = environment(lua, m).map_err(error)?;
+    for (table, pins) in [("inputs", m.inputs()), ("outputs", m.outputs())] {
+        let widths = lua.create_table().map_err(error)?;
+        for pi in pins {
+            let p = netlist.get_pin(*pi).expect("Module pins always exist");
+            widths.set(p.name(), p.kind().width()).map_err(error)?;
+        }
+        env.set(table, widths).map_err(error)?;
+    }
+    let value: f64 = lua
+        .load(format!("return {}", expr))
+        .set_name(what)
+        .set_environment(env)
+        .eval()
+        .map_err(error)?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(anyhow!(
+            "The {} of `{}` came out as {}",
+            what,
+            m.instance(),
+            value
+        ));
+    }
+    Ok(value.ceil() as u64)
+}
+
+/// Evaluate the cost model `model` for the module `m`
+fn cost(
+    lua: &Lua,
+    netlist: &Netlist,
+    m: &Module,
+    model: &ResourceModel,
+) -> anyhow::Result<Resources> {
+    let one = |what, expr: &Option<String>| match expr {
+        Some(expr) => evaluate(lua, netlist, m, what, expr),
+        None => Ok(0),
+    };
+    Ok(Resources {
+        luts: one("LUTs", &model.luts)?,
+        ffs: one("FFs", &model.ffs)?,
+        dsp48s: one("DSP48s", &model.dsp48s)?,
+        brams: one("BRAMs", &model.brams)?,
+    })
+}
+
+/// Cost every module of `netlist` except those `skip`ped, asking `child` for the cost of modules that
+/// aren't library blocks
+fn level(
+    lua: &Lua,
+    name: &str,
+    netlist: &Netlist,
+    skip: impl Fn(ModuleIndex) -> bool,
+    child: impl Fn(&Module) -> Option<Resources>,
+) -> anyhow::Result<LevelCost> {
+    let mut modules = vec![];
+    let mut total = Resources::default();
+    for (_, m) in sorted_modules(netlist)
+        .into_iter()
+        .filter(|(i, _)| !skip(*i))
+    {
+        let resources = match m.resource_model() {
+            Some(model) => Some(cost(lua, netlist, m, model)?),
+            None => child(m),
+        };
+        total += resources.unwrap_or_default();
+        modules.push(ModuleCost {
+            id: m.id(),
+            instance: m.instance().to_owned(),
+            name: m.name().to_owned(),
+            resources,
+        });
+    }
+    Ok(LevelCost {
+        name: name.to_owned(),
+        modules,
+        total,
+    })
+}
+
+impl Netlist {
+    /// Estimate the resources used by every module of the netlist, on its own
+    pub fn resources(&self, name: &str) -> anyhow::Result<LevelCost> {
+        level(&Lua::new(), name, self, |_| false, |_| None)
+    }
+}
+
+impl Project {
+    /// Estimate the resources used by every module of every subgraph in the hierarchy
+    pub fn resources(&self) -> anyhow::Result<ResourceReport> {
+        let lua = Lua::new();
+        let mut levels: Vec<LevelCost> = vec![];
+        for sg in self.hierarchy_order()? {
+            // Ports are just the edges of the subgraph, not something to have a cost model for
+            let cost = level(
+                &lua,
+                sg.name(),
+                sg.netlist(),
+                |x| sg.is_port(x),
+                |m| {
+                    let child = levels.iter().find(|x| x.name == m.name());
+                    child.map(|x| x.total)
+                },
+            )?;
+            levels.push(cost);
+        }
+        Ok(ResourceReport { levels })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::LibraryModule;
+
+    fn block(json: &str) -> LibraryModule {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_module_costs() {
+        let mut netlist = Netlist::new();
+        let fir = netlist
+            .add_module_from_library(block(
+                r#"{
+                "name": "fir",
+                "inputs": [{"name": "din", "kind": "integer"}],
+                "outputs": [{"name": "dout", "kind": "integer"}],
+                "parameters": {"taps": "16", "style": "systolic"},
+                "resources": {
+                    "luts": "inputs.din * 4",
+                    "ffs": "inputs.din * taps",
+                    "dsp48s": "style == 'systolic' and taps or ceil(taps / 2)",
+                    "brams": "taps / 64"
+                }
+            }"#,
+            ))
+            .unwrap();
+        netlist.add_module("mystery".to_owned());
+        let cost = netlist.resources("top").unwrap();
+        assert_eq!(
+            cost.modules[0].resources,
+            Some(Resources {
+                luts: 128,
+                ffs: 512,
+                dsp48s: 16,
+                brams: 1,
+            })
+        );
+        assert_eq!(cost.total, cost.modules[0].resources.unwrap());
+        assert_eq!(cost.unmodelled().count(), 1);
+
+        // Parameters feed straight into the model
+        netlist.set_parameter(fir, "style".to_owned(), "folded".to_owned());
+        let cost = netlist.resources("top").unwrap();
+        assert_eq!(cost.total.dsp48s, 8);
+
+        // Bad expressions say where they came from
+        netlist.set_parameter(fir, "taps".to_owned(), "many".to_owned());
+        let e = netlist.resources("top").unwrap_err().to_string();
+        assert!(e.contains("FFs of `fir_0`"), "{}", e);
+    }
+
+    #[test]
+    fn test_hierarchy() {
+        let add = r#"{
+            "name": "add",
+            "inputs": [{"name": "a", "kind": "integer"}, {"name": "b", "kind": "integer"}],
+            "outputs": [{"name": "sum", "kind": "integer"}],
+            "resources": {"luts": "outputs.sum", "ffs": "outputs.sum"}
+        }"#;
+        let json = include_str!("../resources/project.json");
+        let mut project = Project::from_json(json).unwrap();
+        let report = project.resources().unwrap();
+        assert_eq!(report.total(), Resources::default());
+
+        // Give every library block of the child a cost, and every instance of it picks that up
+        let child = project.hierarchy_order().unwrap()[0].name().to_owned();
+        project
+            .edit_subgraph(&child, |netlist| {
+                netlist.add_module_from_library(block(add))?;
+                Ok(())
+            })
+            .unwrap();
+        let report = project.resources().unwrap();
+        let child_cost = report.levels[0].total;
+        assert_eq!(child_cost.luts, 32);
+        let instances = report
+            .levels
+            .last()
+            .unwrap()
+            .modules
+            .iter()
+            .filter(|m| m.name == child)
+            .count() as u64;
+        assert!(instances > 0);
+        assert_eq!(report.total().luts, 32 * instances);
+        assert!(report.total().fits(&SNAP));
+    }
+}
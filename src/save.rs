@@ -0,0 +1,283 @@
+//! This module defines the on-disk form of a netlist
+//! Arena indices aren't stable, so everything is referenced by module id and pin name instead
+
+use crate::ffi::InterconnectDirection;
+use crate::library::{Bundle, LibraryPin, ResourceModel};
+use crate::netlist::{Netlist, PinIndex};
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SavedNetlist {
+    pub modules: Vec<SavedModule>,
+    pub wires: Vec<SavedWire>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedModule {
+    pub id: i32,
+    pub name: String,
+    /// Missing from older files, in which case a fresh one is made up on load
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub parameters: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bundles: Vec<Bundle>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<(f32, f32)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceModel>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ranges: BTreeMap<String, String>,
+    pub inputs: Vec<LibraryPin>,
+    pub outputs: Vec<LibraryPin>,
+}
+
+/// A pin, referenced by the id of its module and its name
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct SavedPinRef {
+    pub module: i32,
+    pub pin: String,
+}
+
+/// A wire from an output pin to the input pin it drives
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct SavedWire {
+    pub from: SavedPinRef,
+    pub to: SavedPinRef,
+}
+
+impl std::fmt::Display for SavedPinRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.`{}`", self.module, self.pin)
+    }
+}
+
+impl SavedNetlist {
+    /// Get a module by its id
+    pub fn module(&self, id: i32) -> Option<&SavedModule> {
+        self.modules.iter().find(|m| m.id == id)
+    }
+}
+
+impl Netlist {
+    /// Get the stable reference to the pin `idx`
+    pub fn pin_ref(&self, idx: PinIndex) -> Option<SavedPinRef> {
+        let pin = self.get_pin(idx)?;
+        Some(SavedPinRef {
+            module: self.get_module(pin.parent())?.id(),
+            pin: pin.name().to_owned(),
+        })
+    }
+
+    /// Convert the netlist into its on-disk form, with modules ordered by id and wires by the pin they drive
+    pub fn to_saved(&self) -> SavedNetlist {
+        let pins = |pins: std::slice::Iter<'_, PinIndex>| {
+            pins.filter_map(|x| self.get_pin(*x))
+                .map(|p| LibraryPin {
+                    name: p.name().to_owned(),
+                    kind: p.kind(),
+                })
+                .collect()
+        };
+        let mut modules: Vec<_> = self
+            .modules()
+            .map(|(_, m)| SavedModule {
+                id: m.id(),
+                name: m.name().to_owned(),
+                instance: Some(m.instance().to_owned()),
+                parameters: m.parameters().clone(),
+                bundles: m.bundles().to_vec(),
+                position: m.position(),
+                resources: m.resource_model().cloned(),
+                latency: m.latency().map(str::to_owned),
+                ranges: m.ranges().clone(),
+                inputs: pins(m.inputs()),
+                outputs: pins(m.outputs()),
+            })
+            .collect();
+        modules.sort_by_key(|m| m.id);
+        let mut wires: Vec<_> = self
+            .wires()
+            .filter_map(|(_, (input, output))| {
+                Some(SavedWire {
+                    from: self.pin_ref(*output)?,
+                    to: self.pin_ref(*input)?,
+                })
+            })
+            .collect();
+        wires.sort_by(|a, b| a.to.cmp(&b.to));
+        SavedNetlist { modules, wires }
+    }
+
+    /// Rebuild a netlist from its on-disk form, keeping all the module ids
+    pub fn from_saved(saved: &SavedNetlist) -> anyhow::Result<Self> {
+        let mut netlist = Netlist::new();
+        // Add the modules with instance names first, so the made up names can't take theirs
+        let (named, unnamed): (Vec<_>, Vec<_>) =
+            saved.modules.iter().partition(|m| m.instance.is_some());
+        for m in named.into_iter().chain(unnamed) {
+            let mi = netlist
+                .add_module_with_id(m.name.clone(), m.id)
+                .ok_or_else(|| anyhow!("Module id {} is taken or out of range", m.id))?;
+            if let Some(instance) = &m.instance {
+                netlist.set_instance_name(mi, instance.clone())?;
+            }
+            for (name, value) in &m.parameters {
+                netlist.set_parameter(mi, name.clone(), value.clone());
+            }
+            netlist.set_bundles(mi, m.bundles.clone());
+            netlist.set_position(mi, m.position);
+            netlist.set_resource_model(mi, m.resources.clone());
+            netlist.set_latency(mi, m.latency.clone());
+            netlist.set_ranges(mi, m.ranges.clone());
+            for (pins, direction) in [
+                (&m.inputs, InterconnectDirection::Input),
+                (&m.outputs, InterconnectDirection::Output),
+            ] {
+                for p in pins {
+                    // Wires find their pins by name, so two with the same name would be ambiguous
+                    netlist
+                        .add_pin(mi, p.name.clone(), p.kind, direction)
+                        .ok_or_else(|| {
+                            anyhow!("Module {} has more than one pin named `{}`", m.id, p.name)
+                        })?;
+                }
+            }
+        }
+        for w in &saved.wires {
+            let from = netlist
+                .resolve_pin(&w.from, InterconnectDirection::Output)
+                .ok_or_else(|| anyhow!("Wire source {} doesn't exist", w.from))?;
+            let to = netlist
+                .resolve_pin(&w.to, InterconnectDirection::Input)
+                .ok_or_else(|| anyhow!("Wire destination {} doesn't exist", w.to))?;
+            netlist.add_wire(from, to)?;
+        }
+        Ok(netlist)
+    }
+
+    /// Find the pin a stable reference points to
+    pub fn resolve_pin(
+        &self,
+        pin: &SavedPinRef,
+        direction: InterconnectDirection,
+    ) -> Option<PinIndex> {
+        self.find_pin(self.find_module(pin.module)?, &pin.pin, direction)
+    }
+
+    /// Serialize the whole netlist to pretty-printed json
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.to_saved()).unwrap()
+    }
+
+    /// Load a netlist previously written with [`Netlist::to_json`]
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        Self::from_saved(&serde_json::from_str(json)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::PinKind;
+
+    #[test]
+    fn test_round_trip() {
+        let mut netlist = Netlist::new();
+        let m_1 = netlist.add_module("a".to_owned());
+        let out = netlist
+            .add_pin(
+                m_1,
+                "O".to_owned(),
+                PinKind::Wire,
+                InterconnectDirection::Output,
+            )
+            .unwrap();
+        let m_2 = netlist.add_module("b".to_owned());
+        let input = netlist
+            .add_pin(
+                m_2,
+                "I".to_owned(),
+                PinKind::Wire,
+                InterconnectDirection::Input,
+            )
+            .unwrap();
+        netlist.set_parameter(m_2, "width".to_owned(), "8".to_owned());
+        netlist.add_wire(out, input).unwrap();
+        // Leave a hole in the ids
+        netlist.remove_module(m_1);
+        let m_3 = netlist.add_module("c".to_owned());
+        let out = netlist
+            .add_pin(
+                m_3,
+                "O".to_owned(),
+                PinKind::Wire,
+                InterconnectDirection::Output,
+            )
+            .unwrap();
+        netlist.add_wire(out, input).unwrap();
+
+        let json = netlist.to_json();
+        let loaded = Netlist::from_json(&json).unwrap();
+        assert_eq!(loaded.to_saved(), netlist.to_saved());
+        assert_eq!(loaded.to_json(), json);
+        // New modules don't reuse saved ids
+        let mut loaded = loaded;
+        let m_4 = loaded.add_module("d".to_owned());
+        assert_eq!(loaded.get_module(m_4).unwrap().id(), 3);
+    }
+
+    #[test]
+    fn test_bad_wire() {
+        let saved = SavedNetlist {
+            modules: vec![],
+            wires: vec![SavedWire {
+                from: SavedPinRef {
+                    module: 0,
+                    pin: "O".to_owned(),
+                },
+                to: SavedPinRef {
+                    module: 1,
+                    pin: "I".to_owned(),
+                },
+            }],
+        };
+        assert!(Netlist::from_saved(&saved).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_pins() {
+        let json = include_str!("../resources/netlist.json").replacen("\"B\"", "\"A\"", 1);
+        let e = Netlist::from_json(&json).unwrap_err();
+        assert_eq!(e.to_string(), "Module 1 has more than one pin named `A`");
+    }
+
+    #[test]
+    fn test_bad_module_ids() {
+        let module = |id| SavedModule {
+            id,
+            name: "a".to_owned(),
+            instance: None,
+            parameters: BTreeMap::new(),
+            bundles: vec![],
+            position: None,
+            resources: None,
+            latency: None,
+            ranges: BTreeMap::new(),
+            inputs: vec![],
+            outputs: vec![],
+        };
+        for modules in [vec![module(1), module(1)], vec![module(i32::MAX)]] {
+            let saved = SavedNetlist {
+                modules,
+                wires: vec![],
+            };
+            assert!(Netlist::from_saved(&saved).is_err());
+        }
+    }
+}
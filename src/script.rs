@@ -0,0 +1,331 @@
+//! This module hosts the Lua scripts behind parametric library blocks
+//! A scripted block's json names a script defining `build(params, block)`, which is given the block's parameter
+//! values as strings and builds the block's insides through `block`:
+//!
+//! - `block:input(name, kind)` adds an input port, returning the pin it drives
+//! - `block:output(name, kind)` adds an output port, returning the pin that drives it
+//! - `block:module(name, inputs, outputs[, parameters])` adds a module with pins given as `{name, kind}` pairs,
+//!   returning its pins keyed by name
+//! - `block:wire(from, to)` connects an output pin to an input pin
+//!
+//! The result is a subgraph, which instances of the block are instances of.
+
+use crate::ffi::{InterconnectDirection, PinKind};
+use crate::library::{LibraryModule, LibraryPin};
+use crate::netlist::{ModuleIndex, NameError, Netlist, PinIndex, WireIndex};
+use crate::project::{Project, Subgraph};
+use anyhow::anyhow;
+use mlua::{
+    FromLua, Function, Lua, LuaOptions, MetaMethod, StdLib, Table, UserData, UserDataMethods, Value,
+};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Declare a handle to a netlist object, as held by scripts. Handles compare equal if they refer to the same object.
+macro_rules! handle {
+    ($name:ident, $index:ty) => {
+        #[derive(Clone, Copy)]
+        pub(crate) struct $name(pub $index);
+
+        impl UserData for $name {
+            fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+                methods
+                    .add_meta_method(MetaMethod::Eq, |_, this, other: Self| Ok(this.0 == other.0));
+            }
+        }
+
+        impl<'lua> FromLua<'lua> for $name {
+            fn from_lua(value: Value<'lua>, _: &'lua Lua) -> mlua::Result<Self> {
+                match value {
+                    Value::UserData(ud) => Ok(*ud.borrow::<Self>()?),
+                    _ => Err(mlua::Error::FromLuaConversionError {
+                        from: value.type_name(),
+                        to: stringify!($name),
+                        message: None,
+                    }),
+                }
+            }
+        }
+    };
+}
+
+handle!(ModuleHandle, ModuleIndex);
+handle!(PinHandle, PinIndex);
+handle!(WireHandle, WireIndex);
+
+/// The subgraph a script is building
+struct Builder {
+    netlist: Netlist,
+    inputs: Vec<ModuleIndex>,
+    outputs: Vec<ModuleIndex>,
+}
+
+pub(crate) fn runtime_error(e: impl std::fmt::Display) -> mlua::Error {
+    mlua::Error::RuntimeError(e.to_string())
+}
+
+impl Builder {
+    /// Add a module with a single pin, as subgraph ports are
+    fn add_port(
+        &mut self,
+        name: String,
+        kind: &str,
+        direction: InterconnectDirection,
+    ) -> mlua::Result<(ModuleIndex, PinIndex)> {
+        let kind: PinKind = kind.parse().map_err(runtime_error)?;
+        let mi = self.netlist.add_module(name);
+        let pin = match direction {
+            InterconnectDirection::Input => "I",
+            _ => "O",
+        };
+        let pi = self
+            .netlist
+            .add_pin(mi, pin.to_owned(), kind, direction)
+            .expect("We just added the module");
+        Ok((mi, pi))
+    }
+
+    /// Add the pins described by a table of `{name, kind}` pairs
+    fn add_pins<'lua>(
+        &mut self,
+        mi: ModuleIndex,
+        pins: Table<'lua>,
+        direction: InterconnectDirection,
+        handles: &Table<'lua>,
+    ) -> mlua::Result<()> {
+        for pin in pins.sequence_values::<Table>() {
+            let pin = pin?;
+            let (name, kind): (String, String) = (pin.get(1)?, pin.get(2)?);
+            let kind: PinKind = kind.parse().map_err(runtime_error)?;
+            let pi = self
+                .netlist
+                .add_pin(mi, name.clone(), kind, direction)
+                .ok_or_else(|| runtime_error(NameError::Taken(name.clone())))?;
+            handles.set(name, PinHandle(pi))?;
+        }
+        Ok(())
+    }
+}
+
+impl UserData for Builder {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method_mut("input", |_, this, (name, kind): (String, String)| {
+            let (mi, pi) = this.add_port(name, &kind, InterconnectDirection::Output)?;
+            this.inputs.push(mi);
+            Ok(PinHandle(pi))
+        });
+        methods.add_method_mut("output", |_, this, (name, kind): (String, String)| {
+            let (mi, pi) = this.add_port(name, &kind, InterconnectDirection::Input)?;
+            this.outputs.push(mi);
+            Ok(PinHandle(pi))
+        });
+        methods.add_method_mut(
+            "module",
+            |lua,
+             this,
+             (name, inputs, outputs, parameters): (
+                String,
+                Table,
+                Table,
+                Option<BTreeMap<String, String>>,
+            )| {
+                let mi = this.netlist.add_module(name);
+                for (k, v) in parameters.unwrap_or_default() {
+                    this.netlist.set_parameter(mi, k, v);
+                }
+                let handles = lua.create_table()?;
+                this.add_pins(mi, inputs, InterconnectDirection::Input, &handles)?;
+                this.add_pins(mi, outputs, InterconnectDirection::Output, &handles)?;
+                Ok(handles)
+            },
+        );
+        methods.add_method_mut("wire", |_, this, (from, to): (PinHandle, PinHandle)| {
+            this.netlist.add_wire(from.0, to.0).map_err(runtime_error)?;
+            Ok(())
+        });
+    }
+}
+
+/// The name of the subgraph a block elaborates to with the given parameters, so each set of values gets its own
+pub fn subgraph_name(name: &str, parameters: &BTreeMap<String, String>) -> String {
+    if parameters.is_empty() {
+        return name.to_owned();
+    }
+    let values: Vec<_> = parameters
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect();
+    format!("{} ({})", name, values.join(", "))
+}
+
+/// Make a Lua state for library scripts, which come from anywhere, so only get the libraries that can't reach
+/// outside the script
+fn sandbox() -> mlua::Result<Lua> {
+    let lua = Lua::new_with(
+        StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+        LuaOptions::new(),
+    )?;
+    // The base library can still run other files
+    for name in ["dofile", "loadfile"] {
+        lua.globals().set(name, Value::Nil)?;
+    }
+    Ok(lua)
+}
+
+/// Run the script `source` of the block `name` with the given parameter values, returning the subgraph it builds
+pub fn elaborate(
+    name: &str,
+    source: &str,
+    parameters: &BTreeMap<String, String>,
+) -> anyhow::Result<Subgraph> {
+    let fail = |e: mlua::Error| anyhow!("Script of `{}` failed: {}", name, e);
+    let lua = sandbox().map_err(fail)?;
+    lua.load(source).set_name(name).exec().map_err(fail)?;
+    let build: Function = lua
+        .globals()
+        .get("build")
+        .map_err(|_| anyhow!("Script of `{}` doesn't define `build`", name))?;
+    let builder = lua
+        .create_userdata(Builder {
+            netlist: Netlist::new(),
+            inputs: vec![],
+            outputs: vec![],
+        })
+        .map_err(fail)?;
+    build
+        .call::<_, ()>((parameters.clone(), builder.clone()))
+        .map_err(fail)?;
+    let builder: Builder = builder.take().map_err(fail)?;
+    // Scripts don't see the edit history
+    let mut netlist = builder.netlist;
+    netlist.take_events();
+    Subgraph::new(
+        subgraph_name(name, parameters),
+        builder.inputs,
+        builder.outputs,
+        netlist,
+    )
+}
+
+impl Project {
+    /// Elaborate the scripted library block `module`, whose script is found relative to `dir`, adding the
+    /// subgraph it builds unless these parameter values already have one.
+    /// Returns the block to instantiate, whose pins are the subgraph's ports.
+    pub fn add_scripted_module(
+        &mut self,
+        module: &LibraryModule,
+        dir: &Path,
+    ) -> anyhow::Result<LibraryModule> {
+        let script = module
+            .script
+            .as_ref()
+            .ok_or_else(|| anyhow!("`{}` isn't a scripted block", module.name))?;
+        let name = subgraph_name(&module.name, &module.parameters);
+        if self.subgraph(&name).is_none() {
+            let source = std::fs::read_to_string(dir.join(script))
+                .map_err(|e| anyhow!("Can't read the script of `{}`: {}", module.name, e))?;
+            self.add_subgraph(elaborate(&module.name, &source, &module.parameters)?)?;
+        }
+        let sg = self.subgraph(&name).expect("We just added it");
+        let pins = |ports: Vec<(&str, PinKind)>| {
+            ports
+                .into_iter()
+                .map(|(name, kind)| LibraryPin {
+                    name: name.to_owned(),
+                    kind,
+                })
+                .collect()
+        };
+        Ok(LibraryModule {
+            name,
+            inputs: pins(sg.input_ports()),
+            outputs: pins(sg.output_ports()),
+            parameters: BTreeMap::new(),
+            bundles: vec![],
+            resources: None,
+            latency: None,
+            ranges: BTreeMap::new(),
+            script: None,
+            generator: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adder_tree() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources");
+        let mut module: LibraryModule =
+            serde_json::from_str(include_str!("../resources/adder_tree.json")).unwrap();
+        let top = Subgraph::new("top".to_owned(), vec![], vec![], Netlist::new()).unwrap();
+        let mut project = Project::new("p".to_owned(), top);
+
+        let block = project.add_scripted_module(&module, &dir).unwrap();
+        assert_eq!(block.name, "adder tree (inputs=5)");
+        assert_eq!(block.inputs.len(), 5);
+        assert_eq!(
+            block.outputs,
+            vec![LibraryPin {
+                name: "sum".to_owned(),
+                kind: PinKind::Integer
+            }]
+        );
+        let sg = project.subgraph(&block.name).unwrap();
+        sg.netlist().assert_invariants();
+        // One adder fewer than there are inputs, plus the ports
+        assert_eq!(sg.netlist().modules().len(), 4 + 6);
+        assert_eq!(sg.netlist().wires().len(), 2 * 4 + 1);
+
+        // The same values reuse the subgraph, new ones make another
+        project.add_scripted_module(&module, &dir).unwrap();
+        assert_eq!(project.subgraphs().len(), 2);
+        module
+            .parameters
+            .insert("kind".to_owned(), "real".to_owned());
+        let block = project.add_scripted_module(&module, &dir).unwrap();
+        assert_eq!(block.outputs[0].kind, PinKind::Real);
+        assert_eq!(project.subgraphs().len(), 3);
+    }
+
+    #[test]
+    fn test_script_errors() {
+        let params = BTreeMap::new();
+        assert!(elaborate("a", "this isn't lua", &params).is_err());
+        assert!(elaborate("a", "x = 1", &params).is_err());
+        let bad_kind = r#"function build(params, block) block:input("a", "float") end"#;
+        assert!(elaborate("a", bad_kind, &params).is_err());
+        // Wiring two outputs together
+        let bad_wire = r#"
+            function build(params, block)
+                block:wire(block:input("a", "wire"), block:input("b", "wire"))
+            end"#;
+        assert!(elaborate("a", bad_wire, &params).is_err());
+        let same_names = r#"
+            function build(params, block)
+                block:module("m", {{"a", "wire"}}, {{"a", "wire"}})
+            end"#;
+        assert!(elaborate("a", same_names, &params).is_err());
+
+        // Scripts can't reach the system
+        for escape in [
+            r#"os.execute("true")"#,
+            r#"io.open("Cargo.toml")"#,
+            r#"dofile("Cargo.toml")"#,
+            r#"require("os")"#,
+        ] {
+            let source = format!("function build(params, block) {} end", escape);
+            assert!(elaborate("a", &source, &params).is_err(), "{}", escape);
+        }
+    }
+
+    #[test]
+    fn test_not_elaborated() {
+        let module: LibraryModule =
+            serde_json::from_str(include_str!("../resources/adder_tree.json")).unwrap();
+        let e = Netlist::new().add_module_from_library(module).unwrap_err();
+        assert!(e.to_string().contains("has to be elaborated"), "{}", e);
+    }
+}
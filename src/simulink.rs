@@ -0,0 +1,780 @@
+//! This module imports the top level of Simulink models, as `.slx` archives or legacy `.mdl` text, into a netlist.
+//! Blocks are mapped to library blocks by a table keyed on their library path, like `xbsIndex_r4/AddSub`, or their
+//! type for Simulink's own blocks. Anything not in the table comes across as a placeholder with numbered pins, so the
+//! design keeps its shape and the report says what's left to do.
+
+use crate::ffi::{InterconnectDirection, PinKind};
+use crate::generators;
+use crate::library::LibraryModule;
+use crate::netlist::{ModuleIndex, Netlist, PinIndex};
+use crate::project::{Project, Subgraph};
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+/// How to bring one kind of Simulink block across
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockMapping {
+    /// The library block to instantiate, generated from its parameters if it names a generator
+    pub block: LibraryModule,
+    /// The pin for each Simulink input port in order, `null` for ports with no counterpart.
+    /// Defaults to the block's inputs in order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inputs: Option<Vec<Option<String>>>,
+    /// The pin for each Simulink output port, like `inputs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outputs: Option<Vec<Option<String>>>,
+    /// The Simulink parameter to take each of the block's parameters from
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub parameters: BTreeMap<String, String>,
+}
+
+/// Block mappings, by library path or block type
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MappingTable {
+    pub blocks: BTreeMap<String, BlockMapping>,
+}
+
+impl MappingTable {
+    /// The table for the CASPER and System Generator blocks the built-in library covers
+    pub fn casper() -> Self {
+        serde_json::from_str(include_str!("../resources/simulink.json"))
+            .expect("The built-in Simulink mapping table is valid")
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+/// A block brought across as a placeholder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unmapped {
+    pub module: ModuleIndex,
+    /// The Simulink name of the block
+    pub name: String,
+    /// The library path or block type it was looked up by
+    pub key: String,
+    pub reason: String,
+}
+
+/// A line that couldn't be wired up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DroppedLine {
+    /// The port it leaves, like "`Mult` output 1"
+    pub from: String,
+    pub to: String,
+    pub reason: String,
+}
+
+#[derive(Debug)]
+pub struct Import {
+    pub netlist: Netlist,
+    /// Verilog of the generated blocks, keyed by module name
+    pub sources: BTreeMap<String, String>,
+    pub unmapped: Vec<Unmapped>,
+    pub dropped: Vec<DroppedLine>,
+}
+
+impl Import {
+    /// Make a project of the imported design, with its generated blocks' Verilog
+    pub fn into_project(self, name: String) -> anyhow::Result<Project> {
+        let top = Subgraph::new("top".to_owned(), vec![], vec![], self.netlist)?;
+        let mut project = Project::new(name, top);
+        for (module, verilog) in self.sources {
+            project.set_source(module, verilog);
+        }
+        Ok(project)
+    }
+}
+
+impl Display for Import {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.unmapped.is_empty() && self.dropped.is_empty() {
+            return writeln!(f, "Every block and line came across");
+        }
+        for x in &self.unmapped {
+            writeln!(f, "block `{}` (`{}`): {}", x.name, x.key, x.reason)?;
+        }
+        for x in &self.dropped {
+            writeln!(f, "line from {} to {}: {}", x.from, x.to, x.reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// A section of a model, from an XML element or an `.mdl` block in braces
+#[derive(Debug, Default)]
+struct Section {
+    kind: String,
+    parameters: Vec<(String, String)>,
+    children: Vec<Section>,
+}
+
+impl Section {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn children<'a>(&'a self, kind: &'a str) -> impl Iterator<Item = &'a Section> {
+        self.children.iter().filter(move |x| x.kind == kind)
+    }
+
+    /// The first `System` at or under this section, depth first
+    fn system(&self) -> Option<&Section> {
+        if self.kind == "System" {
+            return Some(self);
+        }
+        self.children.iter().find_map(Section::system)
+    }
+
+    /// A block parameter, which masked and library blocks keep a level down
+    fn parameter(&self, name: &str) -> Option<&str> {
+        self.get(name).or_else(|| {
+            ["InstanceData", "Mask"]
+                .iter()
+                .flat_map(|kind| self.children(kind))
+                .find_map(|x| x.get(name))
+        })
+    }
+}
+
+/// Collapse the whitespace, including the newlines Simulink puts in long names
+fn normalise(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Undo the quoting of an `.mdl` string
+fn unquote(value: &str, line: usize) -> anyhow::Result<String> {
+    let Some(inner) = value.strip_prefix('"').and_then(|x| x.strip_suffix('"')) else {
+        bail!("Unterminated string on line {}", line);
+    };
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(c) => out.push(c),
+            None => bail!("Unterminated string on line {}", line),
+        }
+    }
+    Ok(out)
+}
+
+/// Parse the text of an `.mdl` file, where `Name {` opens a section, `}` closes it and other lines are
+/// `key value`. Strings too long for a line carry on in quotes on the next.
+fn parse_mdl(text: &str) -> anyhow::Result<Section> {
+    let mut stack = vec![Section::default()];
+    let mut lines = text.lines().enumerate().peekable();
+    while let Some((n, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line == "}" {
+            let done = stack.pop().unwrap();
+            let Some(parent) = stack.last_mut() else {
+                bail!("Unmatched `}}` on line {}", n + 1);
+            };
+            parent.children.push(done);
+            continue;
+        }
+        if let Some(kind) = line.strip_suffix('{') {
+            stack.push(Section {
+                kind: kind.trim().to_owned(),
+                ..Default::default()
+            });
+            continue;
+        }
+        let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let mut value = value.trim().to_owned();
+        if value.starts_with('"') {
+            value = unquote(&value, n + 1)?;
+            while let Some((n, next)) = lines.next_if(|(_, x)| x.trim().starts_with('"')) {
+                value.push_str(&unquote(next.trim(), n + 1)?);
+            }
+        }
+        stack
+            .last_mut()
+            .unwrap()
+            .parameters
+            .push((key.to_owned(), value));
+    }
+    if stack.len() > 1 {
+        bail!(
+            "The model ends inside a `{}` section",
+            stack.last().unwrap().kind
+        );
+    }
+    Ok(stack.pop().unwrap())
+}
+
+/// Turn an element of an `.slx` file into a section.
+/// Attributes and `<P Name="...">` elements are parameters, as are the parameters of a mask.
+fn from_xml(node: roxmltree::Node) -> Section {
+    let mut section = Section {
+        kind: node.tag_name().name().to_owned(),
+        ..Default::default()
+    };
+    for a in node.attributes() {
+        section
+            .parameters
+            .push((a.name().to_owned(), a.value().to_owned()));
+    }
+    for child in node.children().filter(roxmltree::Node::is_element) {
+        let name = child.attribute("Name");
+        match (child.tag_name().name(), name) {
+            ("P", Some(name)) => section
+                .parameters
+                .push((name.to_owned(), child.text().unwrap_or_default().to_owned())),
+            ("MaskParameter", Some(name)) => {
+                let value = child
+                    .children()
+                    .find(|x| x.has_tag_name("Value"))
+                    .and_then(|x| x.text())
+                    .unwrap_or_default();
+                section.parameters.push((name.to_owned(), value.to_owned()));
+            }
+            _ => section.children.push(from_xml(child)),
+        }
+    }
+    section
+}
+
+fn parse_xml(text: &str) -> anyhow::Result<Section> {
+    let document = roxmltree::Document::parse(text)?;
+    Ok(from_xml(document.root_element()))
+}
+
+/// A port of a block, as a line names it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Port {
+    In(usize),
+    Out(usize),
+    /// Enable, trigger and the like, which have no counterpart
+    Other(String),
+}
+
+/// Where a line starts or ends, by block SID in `.slx` files and block name in `.mdl` files
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Endpoint {
+    block: String,
+    by_sid: bool,
+    port: Port,
+}
+
+impl Endpoint {
+    /// Parse an `.slx` endpoint like `5#out:1`
+    fn from_slx(value: &str) -> Option<Self> {
+        let (block, port) = value.split_once('#')?;
+        let port = match port.split_once(':') {
+            Some(("in", n)) => Port::In(n.parse().ok()?),
+            Some(("out", n)) => Port::Out(n.parse().ok()?),
+            _ => Port::Other(port.to_owned()),
+        };
+        Some(Self {
+            block: block.to_owned(),
+            by_sid: true,
+            port,
+        })
+    }
+
+    /// Read an `.mdl` endpoint from the `{prefix}Block` and `{prefix}Port` parameters
+    fn from_mdl(section: &Section, prefix: &str, input: bool) -> Option<Self> {
+        let block = section.get(&format!("{}Block", prefix))?;
+        let port = section.get(&format!("{}Port", prefix))?;
+        let port = match port.parse() {
+            Ok(n) if input => Port::In(n),
+            Ok(n) => Port::Out(n),
+            Err(_) => Port::Other(port.to_owned()),
+        };
+        Some(Self {
+            block: normalise(block),
+            by_sid: false,
+            port,
+        })
+    }
+}
+
+/// Collect where a line and its branches end
+fn destinations(section: &Section, out: &mut Vec<Endpoint>) {
+    let end = match section.get("Dst") {
+        Some(dst) => Endpoint::from_slx(dst),
+        None => Endpoint::from_mdl(section, "Dst", true),
+    };
+    out.extend(end);
+    for branch in section.children("Branch") {
+        destinations(branch, out);
+    }
+}
+
+/// Read a line as its source and destinations, skipping lines left unconnected
+fn line(section: &Section) -> Option<(Endpoint, Vec<Endpoint>)> {
+    let src = match section.get("Src") {
+        Some(src) => Endpoint::from_slx(src),
+        None => Endpoint::from_mdl(section, "Src", false),
+    }?;
+    let mut dsts = vec![];
+    destinations(section, &mut dsts);
+    Some((src, dsts))
+}
+
+/// The key a block is looked up by in the mapping table
+fn key(block: &Section) -> String {
+    let path = block
+        .parameter("SourceBlock")
+        .or_else(|| block.parameter("ReferenceBlock"))
+        .filter(|x| !x.is_empty());
+    normalise(path.unwrap_or_else(|| block.get("BlockType").unwrap_or("Unknown")))
+}
+
+/// Where to draw a block, from the top left of its `[left, top, right, bottom]`
+fn position(block: &Section) -> Option<(f32, f32)> {
+    let corners = block.parameter("Position")?;
+    let mut numbers = corners
+        .trim_matches(|c| c == '[' || c == ']')
+        .split(',')
+        .map(|x| x.trim().parse::<f32>());
+    Some((numbers.next()?.ok()?, numbers.next()?.ok()?))
+}
+
+/// The block from the mapping with the Simulink block's parameters, generated if need be
+fn library_block(
+    mapping: &BlockMapping,
+    block: &Section,
+    sources: &mut BTreeMap<String, String>,
+) -> anyhow::Result<LibraryModule> {
+    let mut module = mapping.block.clone();
+    for (name, from) in &mapping.parameters {
+        if let Some(value) = block.parameter(from) {
+            // Masks quote string parameters, but the generators want the bare value
+            let value = value.trim().trim_matches('\'');
+            module.parameters.insert(name.clone(), value.to_owned());
+        }
+    }
+    if module.generator.is_some() {
+        let generated = generators::generate(&module)?;
+        sources.insert(generated.module.name.clone(), generated.verilog);
+        module = generated.module;
+    }
+    Ok(module)
+}
+
+/// The pins Simulink ports map to, checking they're all on the block
+fn port_pins(
+    mapping: Option<&Vec<Option<String>>>,
+    pins: &[crate::library::LibraryPin],
+    what: &str,
+) -> anyhow::Result<Vec<Option<String>>> {
+    let Some(mapping) = mapping else {
+        return Ok(pins.iter().map(|x| Some(x.name.clone())).collect());
+    };
+    for name in mapping.iter().flatten() {
+        if !pins.iter().any(|x| &x.name == name) {
+            bail!(
+                "The mapping names {} `{}`, which the block doesn't have",
+                what,
+                name
+            );
+        }
+    }
+    Ok(mapping.clone())
+}
+
+/// An imported block, with the pin of each Simulink port
+struct Placed {
+    name: String,
+    inputs: Vec<Option<PinIndex>>,
+    outputs: Vec<Option<PinIndex>>,
+}
+
+impl Placed {
+    fn pin(&self, port: &Port) -> Result<PinIndex, String> {
+        let (pins, n) = match port {
+            Port::In(n) => (&self.inputs, *n),
+            Port::Out(n) => (&self.outputs, *n),
+            Port::Other(name) => return Err(format!("`{}` ports have no counterpart", name)),
+        };
+        n.checked_sub(1)
+            .and_then(|i| pins.get(i).copied().flatten())
+            .ok_or_else(|| format!("`{}` has no pin for port {}", self.name, n))
+    }
+}
+
+fn describe(end: &Endpoint, placed: Option<&Placed>) -> String {
+    let name = placed.map_or(end.block.as_str(), |x| x.name.as_str());
+    match &end.port {
+        Port::In(n) => format!("`{}` input {}", name, n),
+        Port::Out(n) => format!("`{}` output {}", name, n),
+        Port::Other(port) => format!("`{}` {}", name, port),
+    }
+}
+
+/// Name and position the module `mi` for `block`, and keep its pins for wiring
+fn place(
+    netlist: &mut Netlist,
+    placed: &mut HashMap<String, Placed>,
+    mi: ModuleIndex,
+    name: &str,
+    block: &Section,
+    inputs: Vec<Option<PinIndex>>,
+    outputs: Vec<Option<PinIndex>>,
+) {
+    // Simulink names are unique within a system, so this only fails on empty names
+    let _ = netlist.set_instance_name(mi, name.to_owned());
+    netlist.set_position(mi, position(block));
+    placed.insert(
+        name.to_owned(),
+        Placed {
+            name: name.to_owned(),
+            inputs,
+            outputs,
+        },
+    );
+}
+
+/// Import the top level system of a parsed model
+fn import_system(model: &Section, table: &MappingTable) -> anyhow::Result<Import> {
+    let system = model
+        .system()
+        .ok_or_else(|| anyhow!("The model has no system"))?;
+    let blocks: Vec<_> = system
+        .children("Block")
+        .map(|x| (normalise(x.get("Name").unwrap_or_default()), x))
+        .collect();
+    let sids: HashMap<&str, &str> = blocks
+        .iter()
+        .filter_map(|(name, x)| Some((x.get("SID")?, name.as_str())))
+        .collect();
+    // The name of the block an endpoint is on
+    let resolve = |end: &Endpoint| -> String {
+        match sids.get(end.block.as_str()) {
+            Some(name) if end.by_sid => (*name).to_owned(),
+            _ => end.block.clone(),
+        }
+    };
+    let lines: Vec<_> = system.children("Line").filter_map(line).collect();
+    // Placeholders get as many pins as the lines use
+    let mut used: HashMap<String, (usize, usize)> = HashMap::new();
+    for (src, dsts) in &lines {
+        for end in std::iter::once(src).chain(dsts) {
+            let counts = used.entry(resolve(end)).or_default();
+            match end.port {
+                Port::In(n) => counts.0 = counts.0.max(n),
+                Port::Out(n) => counts.1 = counts.1.max(n),
+                Port::Other(_) => (),
+            }
+        }
+    }
+
+    let mut netlist = Netlist::new();
+    let mut sources = BTreeMap::new();
+    let mut placed: HashMap<String, Placed> = HashMap::new();
+    let mut failed = vec![];
+    for (name, block) in &blocks {
+        let key = key(block);
+        let Some(mapping) = table.blocks.get(&key) else {
+            failed.push((name, *block, key, "isn't in the mapping table".to_owned()));
+            continue;
+        };
+        let mapped = library_block(mapping, block, &mut sources).and_then(|module| {
+            module.check_pins()?;
+            let inputs = port_pins(mapping.inputs.as_ref(), &module.inputs, "input")?;
+            let outputs = port_pins(mapping.outputs.as_ref(), &module.outputs, "output")?;
+            Ok((module, inputs, outputs))
+        });
+        let (module, inputs, outputs) = match mapped {
+            Ok(x) => x,
+            Err(e) => {
+                failed.push((name, *block, key, format!("{:#}", e)));
+                continue;
+            }
+        };
+        let mi = netlist
+            .add_module_from_library(module)
+            .expect("Checked above");
+        let m = netlist.get_module(mi).unwrap();
+        let find = |names: Vec<Option<String>>, pins: Vec<PinIndex>| -> Vec<Option<PinIndex>> {
+            names
+                .into_iter()
+                .map(|name| {
+                    pins.iter()
+                        .copied()
+                        .find(|pi| Some(netlist.get_pin(*pi).unwrap().name()) == name.as_deref())
+                })
+                .collect()
+        };
+        let inputs = find(inputs, m.inputs().copied().collect());
+        let outputs = find(outputs, m.outputs().copied().collect());
+        place(&mut netlist, &mut placed, mi, name, block, inputs, outputs);
+    }
+
+    // Placeholder pins take the kind of the mapped pins they're wired to, so the lines between them come across
+    let mut kinds: HashMap<(String, Port), PinKind> = HashMap::new();
+    for (src, dsts) in &lines {
+        for dst in dsts {
+            for (from, to) in [(src, dst), (dst, src)] {
+                let Some(pin) = placed
+                    .get(&resolve(from))
+                    .and_then(|x| x.pin(&from.port).ok())
+                else {
+                    continue;
+                };
+                if !placed.contains_key(&resolve(to)) {
+                    kinds
+                        .entry((resolve(to), to.port.clone()))
+                        .or_insert_with(|| netlist.get_pin(pin).unwrap().kind());
+                }
+            }
+        }
+    }
+    let mut unmapped = vec![];
+    for (name, block, key, reason) in failed {
+        let (ins, outs) = used.get(name).copied().unwrap_or_default();
+        let mi = netlist.add_module(key.clone());
+        let mut add = |prefix: &str, n: usize, port: fn(usize) -> Port, direction| {
+            (1..=n)
+                .map(|i| {
+                    let kind = kinds
+                        .get(&(name.clone(), port(i)))
+                        .copied()
+                        .unwrap_or(PinKind::Integer);
+                    netlist.add_pin(mi, format!("{}{}", prefix, i), kind, direction)
+                })
+                .collect::<Vec<_>>()
+        };
+        let inputs = add("in", ins, Port::In, InterconnectDirection::Input);
+        let outputs = add("out", outs, Port::Out, InterconnectDirection::Output);
+        place(&mut netlist, &mut placed, mi, name, block, inputs, outputs);
+        unmapped.push(Unmapped {
+            module: mi,
+            name: name.clone(),
+            key,
+            reason,
+        });
+    }
+
+    let mut dropped = vec![];
+    for (src, dsts) in &lines {
+        let from = placed.get(&resolve(src));
+        let driver = from
+            .ok_or_else(|| format!("there's no block `{}`", src.block))
+            .and_then(|x| x.pin(&src.port));
+        for dst in dsts {
+            let to = placed.get(&resolve(dst));
+            let wired = driver.clone().and_then(|driver| {
+                let pin = to
+                    .ok_or_else(|| format!("there's no block `{}`", dst.block))?
+                    .pin(&dst.port)?;
+                netlist
+                    .add_wire(driver, pin)
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            });
+            if let Err(reason) = wired {
+                dropped.push(DroppedLine {
+                    from: describe(src, from),
+                    to: describe(dst, to),
+                    reason,
+                });
+            }
+        }
+    }
+    netlist.take_events();
+    Ok(Import {
+        netlist,
+        sources,
+        unmapped,
+        dropped,
+    })
+}
+
+/// Import the `.mdl` text of a model
+pub fn import_mdl(text: &str, table: &MappingTable) -> anyhow::Result<Import> {
+    import_system(&parse_mdl(text)?, table)
+}
+
+/// Import an `.slx` archive, from the root system if it's split out or the block diagram if not
+pub fn import_slx(bytes: &[u8], table: &MappingTable) -> anyhow::Result<Import> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let mut text = String::new();
+    for name in [
+        "simulink/systems/system_root.xml",
+        "simulink/blockdiagram.xml",
+    ] {
+        if let Ok(mut file) = archive.by_name(name) {
+            file.read_to_string(&mut text)?;
+            return import_system(&parse_xml(&text)?, table);
+        }
+    }
+    bail!("The archive has no Simulink block diagram")
+}
+
+/// Import a model file, by its extension
+pub fn import(path: impl AsRef<Path>, table: &MappingTable) -> anyhow::Result<Import> {
+    let path = path.as_ref();
+    match path.extension().and_then(|x| x.to_str()) {
+        Some("slx") => import_slx(&std::fs::read(path)?, table),
+        Some("mdl") => import_mdl(&std::fs::read_to_string(path)?, table),
+        _ => bail!("`{}` isn't an .slx or .mdl model", path.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn instances(netlist: &Netlist) -> Vec<(String, String)> {
+        let mut out: Vec<_> = netlist
+            .modules()
+            .map(|(_, m)| (m.instance().to_owned(), m.name().to_owned()))
+            .collect();
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn test_parse_mdl() {
+        let model = parse_mdl(
+            "Model {\n  Name \"test\"\n  System {\n    Block {\n      Name \"first\\nsecond\"\n      Note \"a\"\n      \"b\"\n    }\n  }\n}\n",
+        )
+        .unwrap();
+        let system = model.system().unwrap();
+        let block = system.children("Block").next().unwrap();
+        assert_eq!(block.get("Name"), Some("first\nsecond"));
+        assert_eq!(block.get("Note"), Some("ab"));
+        assert!(parse_mdl("Model {\n").is_err());
+        assert!(parse_mdl("}\n").is_err());
+    }
+
+    #[test]
+    fn test_import_mdl() {
+        let import = import_mdl(
+            include_str!("../resources/pfb.mdl"),
+            &MappingTable::casper(),
+        )
+        .unwrap();
+        assert_eq!(
+            instances(&import.netlist),
+            [
+                ("Scope".to_owned(), "Scope".to_owned()),
+                ("adc".to_owned(), "Gateway In".to_owned()),
+                ("fft".to_owned(), "fft_wideband_5_1_all_18".to_owned()),
+                ("pfb".to_owned(), "pfb_fir_5_2_hann_1_18".to_owned()),
+                ("sync".to_owned(), "Gateway In".to_owned()),
+            ]
+        );
+        assert!(import.sources.contains_key("pfb_fir_5_2_hann_1_18"));
+        assert!(import.sources.contains_key("fft_wideband_5_1_all_18"));
+        // The FFT's data comes in on the third port, after the sync and the shift schedule
+        let fft = import
+            .netlist
+            .modules()
+            .find(|(_, m)| m.instance() == "fft")
+            .unwrap()
+            .1;
+        let in0 = fft
+            .inputs()
+            .copied()
+            .find(|x| import.netlist.get_pin(*x).unwrap().name() == "in0_re")
+            .unwrap();
+        assert!(import.netlist.driver(in0).is_some());
+        // The placeholder scope got a pin for the line into it, with the kind of the FFT's sync
+        let scope = import.unmapped.iter().find(|x| x.name == "Scope").unwrap();
+        let m = import.netlist.get_module(scope.module).unwrap();
+        assert_eq!((m.inputs().len(), m.outputs().len()), (1, 0));
+        let sync = import.netlist.get_pin(*m.inputs().next().unwrap()).unwrap();
+        assert_eq!(sync.kind(), PinKind::Wire);
+        assert_eq!(m.position(), Some((450.0, 130.0)));
+        assert_eq!(import.netlist.wires().count(), 4);
+        assert_eq!(
+            import.to_string(),
+            "block `Scope` (`Scope`): isn't in the mapping table\n\
+             line from `sync` output 1 to `pfb` input 1: The pins on either side of the connection are incompatible: integer and wire\n\
+             line from `fft` output 1 to `adc` input 1: `adc` has no pin for port 1\n"
+        );
+
+        let project = import.into_project("pfb".to_owned()).unwrap();
+        assert_eq!(project.sources().len(), 2);
+    }
+
+    #[test]
+    fn test_import_slx() {
+        let mut bytes = vec![];
+        let mut zip = zip::ZipWriter::new(Cursor::new(&mut bytes));
+        zip.start_file(
+            "simulink/blockdiagram.xml",
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored),
+        )
+        .unwrap();
+        zip.write_all(include_bytes!("../resources/blockdiagram.xml"))
+            .unwrap();
+        zip.finish().unwrap();
+        drop(zip);
+
+        let import = import_slx(&bytes, &MappingTable::casper()).unwrap();
+        assert_eq!(
+            instances(&import.netlist),
+            [
+                ("a".to_owned(), "Gateway In".to_owned()),
+                ("acc".to_owned(), "vacc_6_1024".to_owned()),
+                ("delay".to_owned(), "delay".to_owned()),
+                ("enabled".to_owned(), "SubSystem".to_owned()),
+                ("out".to_owned(), "Gateway Out".to_owned()),
+            ]
+        );
+        let delay = import
+            .netlist
+            .modules()
+            .find(|(_, m)| m.instance() == "delay")
+            .unwrap()
+            .1;
+        assert_eq!(delay.parameter("latency"), Some("2"));
+        // The delay fans out to the accumulator and the output through a branch
+        assert_eq!(import.netlist.wires().count(), 4);
+        assert_eq!(
+            import.to_string(),
+            "block `enabled` (`SubSystem`): isn't in the mapping table\n\
+             line from `a` output 1 to `enabled` enable: `enable` ports have no counterpart\n"
+        );
+        assert!(import_slx(b"not a zip", &MappingTable::casper()).is_err());
+    }
+
+    #[test]
+    fn test_casper_table() {
+        // Every generated block's mapping names pins its defaults have
+        for (key, mapping) in MappingTable::casper().blocks {
+            if mapping.block.generator.is_none() {
+                continue;
+            }
+            let module = generators::generate(&mapping.block).unwrap().module;
+            port_pins(mapping.inputs.as_ref(), &module.inputs, "input").expect(&key);
+            port_pins(mapping.outputs.as_ref(), &module.outputs, "output").expect(&key);
+        }
+    }
+
+    #[test]
+    fn test_bad_mapping() {
+        let mut table = MappingTable::casper();
+        table
+            .blocks
+            .get_mut("xbsIndex_r4/Gateway In")
+            .unwrap()
+            .outputs = Some(vec![Some("dout".to_owned())]);
+        let import = import_mdl(include_str!("../resources/pfb.mdl"), &table).unwrap();
+        assert!(import.unmapped.iter().any(|x| x.name == "adc"
+            && x.reason == "The mapping names output `dout`, which the block doesn't have"));
+    }
+}
@@ -1,8 +1,108 @@
 #include "ui_components.hpp"
 #include "imnodes.h"
+#include <algorithm>
+#include <utility>
+
+namespace {
+/// Remove the first element matching `pred`, order isn't preserved as
+/// rust::Vec can't erase from the middle
+template <typename T, typename F> void remove_first(rust::Vec<T> &v, F pred) {
+  for (std::size_t i = 0; i < v.size(); i++) {
+    if (pred(v[i])) {
+      std::swap(v[i], v[v.size() - 1]);
+      v.truncate(v.size() - 1);
+      return;
+    }
+  }
+}
+} // namespace
+
+/// Bring the drawn graph up to date with any changes made to the document
+void apply_events(org::cfrs::Document &doc, org::cfrs::CGraph &graph) {
+  namespace rs = org::cfrs;
+  for (auto &e : doc.poll_events()) {
+    switch (e.kind) {
+    case rs::EventKind::ModuleAdded:
+      graph.modules.push_back(rs::CModule{e.id, e.name, {}, {}, {}, {}});
+      break;
+    case rs::EventKind::ModuleRemoved:
+      remove_first(graph.modules,
+                   [&](const rs::CModule &m) { return m.id == e.id; });
+      break;
+    case rs::EventKind::PinAdded:
+      for (auto &m : graph.modules) {
+        if (m.id == e.module) {
+          auto &ports = e.direction == rs::InterconnectDirection::Input
+                            ? m.inputs
+                            : m.outputs;
+          ports.push_back(rs::CPort{e.id, e.name});
+        }
+      }
+      break;
+    case rs::EventKind::PinRemoved:
+      for (auto &m : graph.modules) {
+        auto is_pin = [&](const rs::CPort &p) { return p.id == e.id; };
+        remove_first(m.inputs, is_pin);
+        remove_first(m.outputs, is_pin);
+      }
+      break;
+    case rs::EventKind::WireAdded:
+      graph.wires.push_back(rs::CWire{e.id, e.x, e.y});
+      break;
+    case rs::EventKind::WireRemoved:
+      remove_first(graph.wires,
+                   [&](const rs::CWire &w) { return w.id == e.id; });
+      break;
+    case rs::EventKind::ModuleRenamed:
+    case rs::EventKind::InstanceRenamed:
+      for (auto &m : graph.modules) {
+        if (m.id == e.id) {
+          auto &field =
+              e.kind == rs::EventKind::ModuleRenamed ? m.name : m.instance;
+          field = e.name;
+        }
+      }
+      break;
+    case rs::EventKind::PinRenamed:
+      for (auto &m : graph.modules) {
+        for (auto &p : m.inputs) {
+          if (p.id == e.id)
+            p.name = e.name;
+        }
+        for (auto &p : m.outputs) {
+          if (p.id == e.id)
+            p.name = e.name;
+        }
+      }
+      break;
+    case rs::EventKind::ParameterChanged:
+      for (auto &m : graph.modules) {
+        if (m.id != e.id)
+          continue;
+        auto it = std::find_if(
+            m.parameters.begin(), m.parameters.end(),
+            [&](const rs::CParameter &p) { return p.name == e.name; });
+        if (it != m.parameters.end())
+          it->value = e.value;
+        else
+          m.parameters.push_back(rs::CParameter{e.name, e.value});
+      }
+      break;
+    case rs::EventKind::AttributeChanged:
+      // Only positions are drawn, the other attributes show up in reports
+      if (e.name == "position") {
+        float x, y;
+        if (doc.get_module_position(e.id, x, y).kind == rs::ErrorKind::Ok)
+          ImNodes::SetNodeGridSpacePos(e.id, ImVec2(x, y));
+      }
+      break;
+    }
+  }
+}
 
 /// Draw the graph and wires in a window called "Editor"
-void draw_editor(bool *p_open, org::cfrs::CGraph &graph) {
+void draw_editor(org::cfrs::Document &doc, bool *p_open,
+                 org::cfrs::CGraph &graph) {
   if (ImGui::Begin("Editor", p_open)) {
 
     // Drag and drop into editor
@@ -18,12 +118,15 @@ void draw_editor(bool *p_open, org::cfrs::CGraph &graph) {
         }
         if (payload->IsDelivery()) {
           // Add node?
-          auto mi = org::cfrs::add_module_from_json_path(
-              "/home/kiran/Projects/Cpp/casperflow/resources/logical.json");
-          // Refresh the whole graph *right now*
-          graph = org::cfrs::get_graph();
-          // Set the position
-          ImNodes::SetNodeScreenSpacePos(mi, pos);
+          int32_t mi = -1;
+          auto err = doc.add_module_from_json_path(
+              "/home/kiran/Projects/Cpp/casperflow/resources/logical.json", mi);
+          if (err.kind == org::cfrs::ErrorKind::Ok) {
+            // Pick up the new module *right now* so we can place it
+            apply_events(doc, graph);
+            // Set the position
+            ImNodes::SetNodeScreenSpacePos(mi, pos);
+          }
         }
       }
       ImGui::EndDragDropTarget();
@@ -38,9 +141,16 @@ void draw_editor(bool *p_open, org::cfrs::CGraph &graph) {
 
       // Title
       ImNodes::BeginNodeTitleBar();
-      ImGui::TextUnformatted(mod.name.c_str());
+      ImGui::Text("%s (%s)", std::string(mod.instance).c_str(),
+                  std::string(mod.name).c_str());
       ImNodes::EndNodeTitleBar();
 
+      // Parameters
+      for (auto &param : mod.parameters) {
+        ImGui::Text("%s = %s", std::string(param.name).c_str(),
+                    std::string(param.value).c_str());
+      }
+
       // Style inputs and outputs like simulink where the text is edge-aligned
 
       // Inputs
@@ -71,6 +181,15 @@ void draw_editor(bool *p_open, org::cfrs::CGraph &graph) {
 
     ImNodes::MiniMap(0.1f, ImNodesMiniMapLocation_BottomRight);
     ImNodes::EndNodeEditor();
+
+    // Keep the document's positions up to date once a drag finishes, so
+    // exports and saved files match what's on screen
+    if (ImGui::IsMouseReleased(ImGuiMouseButton_Left)) {
+      for (auto &mod : graph.modules) {
+        auto p = ImNodes::GetNodeGridSpacePos(mod.id);
+        doc.set_module_position(mod.id, p.x, p.y);
+      }
+    }
   }
   ImGui::End();
 }
@@ -92,9 +211,31 @@ void draw_library(bool *p_open) {
   ImGui::End();
 }
 
+// Draw the script console, which runs Lua against the open design
+void draw_console(org::cfrs::Document &doc, bool *p_open,
+                  ApplicationLog &log) {
+  static char source[4096] = "";
+  ImGui::Begin("Console", p_open);
+  ImGui::InputTextMultiline(
+      "##source", source, IM_ARRAYSIZE(source),
+      ImVec2(-FLT_MIN, -ImGui::GetFrameHeightWithSpacing()));
+  if (ImGui::Button("Run")) {
+    rust::String output;
+    auto err = doc.run_script(source, output);
+    if (!output.empty()) {
+      log.add_log("%s", std::string(output).c_str());
+    }
+    if (err.kind != org::cfrs::ErrorKind::Ok) {
+      log.add_log("%s\n", err.message.c_str());
+    }
+  }
+  ImGui::End();
+}
+
 // Draw the main menu - file, window, etc.
-void draw_main_menu(bool *editor_open, bool *log_open, bool *browser_open,
-                    bool *demo_open, bool *quit) {
+void draw_main_menu(org::cfrs::Document &doc, bool *editor_open,
+                    bool *log_open, bool *browser_open, bool *console_open,
+                    bool *demo_open, bool *quit, ApplicationLog &log) {
   if (ImGui::BeginMainMenuBar()) {
     if (ImGui::BeginMenu("File")) {
       if (ImGui::MenuItem("Open library", "CTRL+o")) {
@@ -108,7 +249,50 @@ void draw_main_menu(bool *editor_open, bool *log_open, bool *browser_open,
     }
     if (ImGui::BeginMenu("Edit")) {
       if (ImGui::MenuItem("Dump netlist", "CTRL+d")) {
-        org::cfrs::dump_netlist();
+        doc.dump_netlist();
+      }
+      if (ImGui::MenuItem("Resource report")) {
+        rust::String report;
+        auto err = doc.resource_report(report);
+        if (err.kind == org::cfrs::ErrorKind::Ok) {
+          log.add_log("%s", std::string(report).c_str());
+        } else {
+          log.add_log("%s\n", err.message.c_str());
+        }
+      }
+      if (ImGui::MenuItem("Latency report")) {
+        rust::String report;
+        auto err = doc.latency_report(report);
+        if (err.kind == org::cfrs::ErrorKind::Ok) {
+          log.add_log("%s", std::string(report).c_str());
+        } else {
+          log.add_log("%s\n", err.message.c_str());
+        }
+      }
+      if (ImGui::MenuItem("Integer range report")) {
+        rust::String report;
+        auto err = doc.range_report(report);
+        if (err.kind == org::cfrs::ErrorKind::Ok) {
+          log.add_log("%s", std::string(report).c_str());
+        } else {
+          log.add_log("%s\n", err.message.c_str());
+        }
+      }
+      if (ImGui::MenuItem("Balance latency")) {
+        int32_t added = 0;
+        auto err = doc.balance_latency(added);
+        if (err.kind == org::cfrs::ErrorKind::Ok) {
+          log.add_log("Inserted %d delays\n", added);
+        } else {
+          log.add_log("%s\n", err.message.c_str());
+        }
+      }
+      if (ImGui::MenuItem("Tidy layout")) {
+        rust::Vec<org::cfrs::CPlacement> placements;
+        doc.tidy_layout(placements);
+        for (auto &p : placements) {
+          ImNodes::SetNodeGridSpacePos(p.id, ImVec2(p.x, p.y));
+        }
       }
       ImGui::EndMenu();
     }
@@ -116,6 +300,7 @@ void draw_main_menu(bool *editor_open, bool *log_open, bool *browser_open,
       ImGui::Checkbox("Editor", editor_open);
       ImGui::Checkbox("Log", log_open);
       ImGui::Checkbox("Library Browser", browser_open);
+      ImGui::Checkbox("Console", console_open);
       ImGui::Checkbox("ImGui Demo", demo_open);
       ImGui::EndMenu();
     }
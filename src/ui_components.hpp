@@ -6,10 +6,10 @@
 #include <imgui_internal.h>
 #include <imnodes.h>
 
-void draw_editor(bool *p_open, org::cfrs::CGraph &graph);
+void draw_editor(org::cfrs::Document &doc, bool *p_open,
+                 org::cfrs::CGraph &graph);
+void apply_events(org::cfrs::Document &doc, org::cfrs::CGraph &graph);
 void draw_library(bool *p_open);
-void draw_main_menu(bool *editor_open, bool *log_open, bool *browser_open,
-                    bool *demo_open, bool *quit);
 void file_selector(bool *stale_graph);
 
 struct ApplicationLog {
@@ -22,4 +22,9 @@ struct ApplicationLog {
   void clear();
   void add_log(const char *fmt, ...);
   void draw(const char *title, bool *p_open);
-};
\ No newline at end of file
+};
+
+void draw_console(org::cfrs::Document &doc, bool *p_open, ApplicationLog &log);
+void draw_main_menu(org::cfrs::Document &doc, bool *editor_open,
+                    bool *log_open, bool *browser_open, bool *console_open,
+                    bool *demo_open, bool *quit, ApplicationLog &log);